@@ -0,0 +1,224 @@
+//! The optional parameters of a chat request.  Shared by `llm-rs`,
+//! which sends them, and the web front and back ends, which pass
+//! them between them
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+/// The names `ChatOptions::set` knows
+pub const CHAT_OPTION_NAMES: [&str; 11] = [
+    "temperature",
+    "top_p",
+    "max_tokens",
+    "presence_penalty",
+    "frequency_penalty",
+    "stop",
+    "seed",
+    "n",
+    "logprobs",
+    "logit_bias",
+    "user",
+];
+
+/// The optional parameters of a chat request.  Unset options are not
+/// sent, and OpenAI uses its defaults.  Build with the methods:
+/// `ChatOptions::new().temperature(0.2).max_tokens(500)`
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatOptions {
+    /// 0 to 2.  Higher is more random
+    pub temperature: Option<f32>,
+    /// 0 to 1.  Only sample from the tokens in the top `top_p` of
+    /// probability mass
+    pub top_p: Option<f32>,
+    /// The most tokens to generate
+    pub max_tokens: Option<u32>,
+    /// -2 to 2.  Positive values encourage new topics
+    pub presence_penalty: Option<f32>,
+    /// -2 to 2.  Positive values discourage repeating lines
+    pub frequency_penalty: Option<f32>,
+    /// Up to four sequences where generation stops
+    pub stop: Vec<String>,
+    /// Makes sampling (mostly) repeatable
+    pub seed: Option<i64>,
+    /// How many choices to generate
+    pub n: Option<u32>,
+    /// Return the log probabilities of the generated tokens, with this
+    /// many (0 to 20) of the most likely alternatives at each position
+    pub logprobs: Option<u8>,
+    /// Token ID to a bias from -100 (ban it) to 100 (force it)
+    pub logit_bias: BTreeMap<u32, i32>,
+    /// An ID for the end user, for OpenAI's abuse monitoring
+    pub user: Option<String>,
+}
+
+impl ChatOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+    pub fn presence_penalty(mut self, presence_penalty: f32) -> Self {
+        self.presence_penalty = Some(presence_penalty);
+        self
+    }
+    pub fn frequency_penalty(mut self, frequency_penalty: f32) -> Self {
+        self.frequency_penalty = Some(frequency_penalty);
+        self
+    }
+    pub fn stop(mut self, stop: &str) -> Self {
+        self.stop.push(stop.to_string());
+        self
+    }
+    pub fn seed(mut self, seed: i64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+    pub fn n(mut self, n: u32) -> Self {
+        self.n = Some(n);
+        self
+    }
+    pub fn logprobs(mut self, top_logprobs: u8) -> Self {
+        self.logprobs = Some(top_logprobs);
+        self
+    }
+    pub fn logit_bias(mut self, token: u32, bias: i32) -> Self {
+        self.logit_bias.insert(token, bias);
+        self
+    }
+    pub fn user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Set the option called `name` from text, as typed by a user.
+    /// `None` clears it.  "stop" adds a sequence, and "logit_bias"
+    /// takes "<token ID>:<bias>"
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let value = match value {
+            Some(value) => value,
+            None => {
+                match name {
+                    "temperature" => self.temperature = None,
+                    "top_p" => self.top_p = None,
+                    "max_tokens" => self.max_tokens = None,
+                    "presence_penalty" => self.presence_penalty = None,
+                    "frequency_penalty" => self.frequency_penalty = None,
+                    "stop" => self.stop.clear(),
+                    "seed" => self.seed = None,
+                    "n" => self.n = None,
+                    "logprobs" => self.logprobs = None,
+                    "logit_bias" => self.logit_bias.clear(),
+                    "user" => self.user = None,
+                    _ => return Err(format!("Unknown chat option: {name}").into()),
+                };
+                return Ok(());
+            }
+        };
+        match name {
+            "temperature" => self.temperature = Some(in_range(value, 0.0, 2.0)?),
+            "top_p" => self.top_p = Some(in_range(value, 0.0, 1.0)?),
+            "max_tokens" => self.max_tokens = Some(value.parse()?),
+            "presence_penalty" => self.presence_penalty = Some(in_range(value, -2.0, 2.0)?),
+            "frequency_penalty" => self.frequency_penalty = Some(in_range(value, -2.0, 2.0)?),
+            "stop" => {
+                if self.stop.len() == 4 {
+                    return Err("At most four stop sequences".into());
+                }
+                self.stop.push(value.to_string());
+            }
+            "seed" => self.seed = Some(value.parse()?),
+            "n" => {
+                let n: u32 = value.parse()?;
+                if n == 0 {
+                    return Err("n must be at least 1".into());
+                }
+                self.n = Some(n);
+            }
+            "logprobs" => {
+                let top: u8 = value.parse()?;
+                if top > 20 {
+                    return Err("logprobs is from 0 to 20".into());
+                }
+                self.logprobs = Some(top);
+            }
+            "logit_bias" => {
+                let (token, bias) = value
+                    .split_once(':')
+                    .ok_or("logit_bias is <token ID>:<bias>")?;
+                let bias: i32 = bias.parse()?;
+                if !(-100..=100).contains(&bias) {
+                    return Err("A logit bias is from -100 to 100".into());
+                }
+                self.logit_bias.insert(token.parse()?, bias);
+            }
+            "user" => self.user = Some(value.to_string()),
+            _ => return Err(format!("Unknown chat option: {name}").into()),
+        };
+        Ok(())
+    }
+}
+
+/// Parse a float and check it is in `min..=max`
+fn in_range(value: &str, min: f32, max: f32) -> Result<f32, Box<dyn Error>> {
+    let v: f32 = value.parse()?;
+    if (min..=max).contains(&v) {
+        Ok(v)
+    } else {
+        Err(format!("{v} is not between {min} and {max}").into())
+    }
+}
+
+impl fmt::Display for ChatOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Only the options that are set, as "name: value" lines
+        let json = serde_json::to_value(self).map_err(|_| fmt::Error)?;
+        let mut lines: Vec<String> = vec![];
+        for name in CHAT_OPTION_NAMES.iter() {
+            match &json[name] {
+                Value::Null => (),
+                Value::Array(a) if a.is_empty() => (),
+                Value::Object(o) if o.is_empty() => (),
+                Value::String(s) => lines.push(format!("{name}: {s}")),
+                v => lines.push(format!("{name}: {v}")),
+            }
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_options() {
+        let mut options = ChatOptions::new();
+        options.set("temperature", Some("1.5")).unwrap();
+        options.set("logit_bias", Some("1234:20")).unwrap();
+        assert_eq!(
+            options,
+            ChatOptions::new().temperature(1.5).logit_bias(1234, 20)
+        );
+        assert!(options.set("temperature", Some("2.5")).is_err());
+        assert!(options.set("top_p", Some("x")).is_err());
+        assert!(options.set("logit_bias", Some("1234")).is_err());
+        assert!(options.set("colour", Some("red")).is_err());
+        options.set("temperature", None).unwrap();
+        assert_eq!(options.temperature, None);
+        assert_eq!(format!("{options}"), "logit_bias: {\"1234\":20}");
+    }
+}
@@ -0,0 +1,345 @@
+//! A local HTTP server that imitates the OpenAI endpoints `llm-rs`
+//! uses: chat, completions, images, audio, files, fine tuning and
+//! models.  Point `ApiInterface::api_url` at `MockServer::base_url`
+//! and nothing needs a key or the network.
+//!
+//! A request gets, in order of preference:
+//!
+//! 1. The next response scripted for its path with
+//!    `MockServer::push_response`.  Use this for errors and rate
+//!    limits.
+//!
+//! 2. A fixture file, if the server has a fixture directory.  The
+//!    file is named for the method and path: a POST to
+//!    `/v1/chat/completions` is answered with
+//!    `post_v1_chat_completions.json`
+//!
+//! 3. A built in response.  Chat and completions echo the prompt.
+//!    Files and fine tuning jobs are kept in memory so they can be
+//!    listed, retrieved and deleted
+//!
+//! Every response has OpenAI's rate limit headers
+mod routes;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use routes::State;
+use serde_json::json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::sync::oneshot;
+
+/// A response the server will send
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+impl MockResponse {
+    pub fn json(status: u16, body: Value) -> Self {
+        MockResponse {
+            status,
+            headers: vec![],
+            content_type: "application/json".to_string(),
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    /// Server-sent events, the way OpenAI streams replies: each of
+    /// `events` then "[DONE]"
+    pub fn event_stream(events: &[Value]) -> Self {
+        let mut body: String = events.iter().map(|e| format!("data: {e}\n\n")).collect();
+        body.push_str("data: [DONE]\n\n");
+        MockResponse {
+            status: 200,
+            headers: vec![],
+            content_type: "text/event-stream".to_string(),
+            body: body.into_bytes(),
+        }
+    }
+
+    /// An error the way OpenAI reports them
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::json(
+            status,
+            json!({
+                "error": {
+                    "message": message,
+                    "type": "invalid_request_error",
+                    "param": null,
+                    "code": null,
+                }
+            }),
+        )
+    }
+
+    /// A 429 with the headers OpenAI sends when it is too busy
+    pub fn rate_limited() -> Self {
+        let mut response = Self::json(
+            429,
+            json!({
+                "error": {
+                    "message": "Rate limit reached for requests",
+                    "type": "requests",
+                    "param": null,
+                    "code": "rate_limit_exceeded",
+                }
+            }),
+        );
+        response.headers = vec![
+            ("retry-after".to_string(), "1".to_string()),
+            (
+                "x-ratelimit-remaining-requests".to_string(),
+                "0".to_string(),
+            ),
+            ("x-ratelimit-reset-requests".to_string(), "1s".to_string()),
+        ];
+        response
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// A request the server received
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    /// The body as JSON.  `Value::Null` if it is not JSON
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+/// The server.  It runs on its own thread, with its own runtime, so
+/// it can be used from blocking and async code alike.  It stops when
+/// dropped
+pub struct MockServer {
+    origin: String,
+    state: Arc<Mutex<State>>,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Start on a free port, with no fixtures
+    pub fn start() -> io::Result<Self> {
+        Self::start_on(0, None)
+    }
+
+    /// Start on `port` (0 for any free port).  Responses are looked
+    /// for in `fixtures` before the built in ones
+    pub fn start_on(port: u16, fixtures: Option<PathBuf>) -> io::Result<Self> {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        let origin = format!("http://{}", listener.local_addr()?);
+        let state = Arc::new(Mutex::new(State::new(origin.clone(), fixtures)));
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let (ready_tx, ready_rx) = mpsc::channel::<io::Result<()>>();
+        let server_state = state.clone();
+        let thread = thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(r) => r,
+                Err(err) => {
+                    let _ = ready_tx.send(Err(err));
+                    return;
+                }
+            };
+            runtime.block_on(async move {
+                let builder = match Server::from_tcp(listener) {
+                    Ok(b) => b,
+                    Err(err) => {
+                        let _ = ready_tx.send(Err(io::Error::other(err)));
+                        return;
+                    }
+                };
+                let service = make_service_fn(move |_| {
+                    let state = server_state.clone();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                            handle(state.clone(), req)
+                        }))
+                    }
+                });
+                let _ = ready_tx.send(Ok(()));
+                let server = builder.serve(service).with_graceful_shutdown(async {
+                    shutdown_rx.await.ok();
+                });
+                if let Err(err) = server.await {
+                    eprintln!("Mock server: {err}");
+                }
+            });
+        });
+        ready_rx.recv().map_err(io::Error::other)??;
+
+        Ok(MockServer {
+            origin,
+            state,
+            shutdown: Some(shutdown_tx),
+            thread: Some(thread),
+        })
+    }
+
+    /// What to use in place of "https://api.openai.com/v1"
+    pub fn base_url(&self) -> String {
+        format!("{}/v1", self.origin)
+    }
+
+    /// Answer the next request to `path` (like
+    /// "/v1/chat/completions") with `response`.  Responses pushed for
+    /// the same path are used in order, then the path goes back to
+    /// its usual response
+    pub fn push_response(&self, path: &str, response: MockResponse) {
+        self.state.lock().unwrap().push_response(path, response);
+    }
+
+    /// Every request received so far, oldest first
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Block until the server stops.  It never does on its own, so
+    /// this is for running it as a programme
+    pub fn wait(mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Record the request and answer it
+async fn handle(
+    state: Arc<Mutex<State>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
+    let headers: HashMap<String, String> = req
+        .headers()
+        .iter()
+        .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.to_string(), v.to_string())))
+        .collect();
+    let body = hyper::body::to_bytes(req.into_body())
+        .await
+        .map(|b| b.to_vec())
+        .unwrap_or_default();
+    let request = RecordedRequest {
+        method,
+        path,
+        headers,
+        body,
+    };
+
+    let (response, rate_limit_headers) = {
+        let mut state = state.lock().unwrap();
+        let response = state.respond(&request);
+        state.requests.push(request);
+        (response, state.rate_limit_headers())
+    };
+
+    let mut builder = Response::builder()
+        .status(response.status)
+        .header("content-type", response.content_type.as_str());
+    for (name, value) in rate_limit_headers.iter() {
+        if !response.headers.iter().any(|(n, _)| n == name) {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+    }
+    for (name, value) in response.headers.iter() {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    Ok(builder
+        .body(Body::from(response.body))
+        .unwrap_or_else(|err| Response::new(Body::from(format!("{err}")))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    /// Enough HTTP to test with, without a client library
+    fn send(server: &MockServer, method: &str, path: &str, body: &str) -> (u16, String) {
+        let address = server.origin.trim_start_matches("http://");
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: {address}\r\nContent-Type: application/json\r\n\
+	     Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response
+            .split_once("\r\n\r\n")
+            .map(|(_, b)| b.to_string())
+            .unwrap_or_default();
+        (status, body)
+    }
+
+    #[test]
+    fn chat_echoes() {
+        let server = MockServer::start().unwrap();
+        let (status, body) = send(
+            &server,
+            "POST",
+            "/v1/chat/completions",
+            r#"{"model": "gpt-4o-mini", "messages": [{"role": "user", "content": "Hello"}]}"#,
+        );
+        assert_eq!(status, 200);
+        let body: Value = serde_json::from_str(body.as_str()).unwrap();
+        assert_eq!(
+            body["choices"][0]["message"]["content"],
+            "Mock reply to: Hello"
+        );
+        assert_eq!(server.requests()[0].json()["model"], "gpt-4o-mini");
+    }
+
+    #[test]
+    fn scripted_then_default() {
+        let server = MockServer::start().unwrap();
+        server.push_response("/v1/models", MockResponse::rate_limited());
+        let (status, _) = send(&server, "GET", "/v1/models", "");
+        assert_eq!(status, 429);
+        let (status, body) = send(&server, "GET", "/v1/models", "");
+        assert_eq!(status, 200);
+        assert!(body.contains("gpt-4o-mini"));
+        let (status, _) = send(&server, "GET", "/v1/nothing", "");
+        assert_eq!(status, 404);
+    }
+}
@@ -0,0 +1,29 @@
+//! Run the mock OpenAI server until killed.
+//! Usage: llm-mock [port] [fixtures directory]
+use llm_mock::MockServer;
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let port: u16 = match args.next() {
+        Some(p) => match p.parse() {
+            Ok(p) => p,
+            Err(_) => {
+                eprintln!("Usage: llm-mock [port] [fixtures directory]");
+                std::process::exit(1);
+            }
+        },
+        None => 0,
+    };
+    let fixtures = args.next().map(PathBuf::from);
+    let server = match MockServer::start_on(port, fixtures) {
+        Ok(s) => s,
+        Err(err) => {
+            eprintln!("Cannot start the mock server: {err}");
+            std::process::exit(1);
+        }
+    };
+    println!("{}", server.base_url());
+    server.wait();
+}
@@ -0,0 +1,623 @@
+//! What the mock server says to each request
+use crate::MockResponse;
+use crate::RecordedRequest;
+use serde_json::json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// The models the server says it has
+const MODELS: [&str; 6] = [
+    "gpt-4o",
+    "gpt-4o-mini",
+    "gpt-3.5-turbo-instruct",
+    "o1-mini",
+    "whisper-1",
+    "dall-e-3",
+];
+
+/// How many requests, and tokens, the rate limit headers say are
+/// allowed
+const RATE_LIMIT_REQUESTS: u64 = 10_000;
+const RATE_LIMIT_TOKENS: u64 = 2_000_000;
+
+/// A 1x1 transparent PNG, for image URLs to point at
+const PNG: [u8; 68] = [
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4,
+    0x89, 0x00, 0x00, 0x00, 0x0b, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x60, 0x00, 0x02, 0x00,
+    0x00, 0x05, 0x00, 0x01, 0x7a, 0x5e, 0xab, 0x3f, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44,
+    0xae, 0x42, 0x60, 0x82,
+];
+
+/// A file that has been uploaded
+struct StoredFile {
+    info: Value,
+    content: Vec<u8>,
+}
+
+pub(crate) struct State {
+    /// "http://127.0.0.1:<port>"
+    origin: String,
+    fixtures: Option<PathBuf>,
+    scripted: HashMap<String, VecDeque<MockResponse>>,
+    pub(crate) requests: Vec<RecordedRequest>,
+    files: Vec<StoredFile>,
+    jobs: Vec<Value>,
+    next_id: u64,
+}
+
+impl State {
+    pub(crate) fn new(origin: String, fixtures: Option<PathBuf>) -> Self {
+        State {
+            origin,
+            fixtures,
+            scripted: HashMap::new(),
+            requests: vec![],
+            files: vec![],
+            jobs: vec![],
+            next_id: 1,
+        }
+    }
+
+    pub(crate) fn push_response(&mut self, path: &str, response: MockResponse) {
+        self.scripted
+            .entry(path.to_string())
+            .or_default()
+            .push_back(response);
+    }
+
+    /// The headers OpenAI uses to say how close a client is to its
+    /// limits.  Each request uses one up
+    pub(crate) fn rate_limit_headers(&self) -> Vec<(String, String)> {
+        let used = self.requests.len() as u64;
+        vec![
+            (
+                "x-ratelimit-limit-requests".to_string(),
+                RATE_LIMIT_REQUESTS.to_string(),
+            ),
+            (
+                "x-ratelimit-remaining-requests".to_string(),
+                RATE_LIMIT_REQUESTS.saturating_sub(used).to_string(),
+            ),
+            ("x-ratelimit-reset-requests".to_string(), "6ms".to_string()),
+            (
+                "x-ratelimit-limit-tokens".to_string(),
+                RATE_LIMIT_TOKENS.to_string(),
+            ),
+            (
+                "x-ratelimit-remaining-tokens".to_string(),
+                RATE_LIMIT_TOKENS.to_string(),
+            ),
+            ("x-ratelimit-reset-tokens".to_string(), "0s".to_string()),
+            ("x-request-id".to_string(), format!("req_mock_{used}")),
+        ]
+    }
+
+    /// The scripted response, the fixture, or the built in response,
+    /// in that order
+    pub(crate) fn respond(&mut self, request: &RecordedRequest) -> MockResponse {
+        if let Some(response) = self
+            .scripted
+            .get_mut(request.path.as_str())
+            .and_then(|q| q.pop_front())
+        {
+            return response;
+        }
+        if let Some(response) = self.fixture(request) {
+            return response;
+        }
+        self.built_in(request)
+    }
+
+    /// The fixture file for a request is named for its method and
+    /// path.  GET /v1/models is "get_v1_models.json"
+    fn fixture(&self, request: &RecordedRequest) -> Option<MockResponse> {
+        let dir = self.fixtures.as_ref()?;
+        let name = format!(
+            "{}{}.json",
+            request.method.to_lowercase(),
+            request.path.replace('/', "_")
+        );
+        let contents = fs::read_to_string(dir.join(name)).ok()?;
+        match serde_json::from_str(contents.as_str()) {
+            Ok(body) => Some(MockResponse::json(200, body)),
+            Err(err) => Some(MockResponse::error(
+                500,
+                format!("Bad fixture for {}: {err}", request.path).as_str(),
+            )),
+        }
+    }
+
+    fn new_id(&mut self, prefix: &str) -> String {
+        let id = format!("{prefix}-mock{}", self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    fn built_in(&mut self, request: &RecordedRequest) -> MockResponse {
+        let segments: Vec<&str> = request
+            .path
+            .trim_start_matches('/')
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("POST", ["v1", "chat", "completions"]) => chat(&request.json()),
+            ("POST", ["v1", "completions"]) => completion(&request.json()),
+            ("POST", ["v1", "images", "generations"]) | ("POST", ["v1", "images", "edits"]) => {
+                MockResponse::json(
+                    200,
+                    json!({
+                        "created": now(),
+                        "data": [{"url": format!("{}/mock/image.png", self.origin)}],
+                    }),
+                )
+            }
+            ("GET", ["mock", "image.png"]) => MockResponse {
+                status: 200,
+                headers: vec![],
+                content_type: "image/png".to_string(),
+                body: PNG.to_vec(),
+            },
+            ("POST", ["v1", "audio", "transcriptions"]) => {
+                MockResponse::json(200, json!({"text": "Mock transcription"}))
+            }
+            ("GET", ["v1", "models"]) => MockResponse::json(
+                200,
+                json!({
+                    "object": "list",
+                    "data": MODELS.iter().map(|id| model(id)).collect::<Vec<Value>>(),
+                }),
+            ),
+            ("GET", ["v1", "models", id]) => MockResponse::json(200, model(id)),
+            ("DELETE", ["v1", "models", id]) => {
+                MockResponse::json(200, json!({"id": id, "object": "model", "deleted": true}))
+            }
+            ("GET", ["v1", "files"]) => MockResponse::json(
+                200,
+                json!({
+                    "object": "list",
+                    "data": self.files.iter().map(|f| f.info.clone()).collect::<Vec<Value>>(),
+                }),
+            ),
+            ("POST", ["v1", "files"]) => self.file_upload(request),
+            ("GET", ["v1", "files", id]) => match self.file(id) {
+                Some(file) => MockResponse::json(200, file.info.clone()),
+                None => no_such("file", id),
+            },
+            ("GET", ["v1", "files", id, "content"]) => match self.file(id) {
+                Some(file) => MockResponse {
+                    status: 200,
+                    headers: vec![],
+                    content_type: "application/octet-stream".to_string(),
+                    body: file.content.clone(),
+                },
+                None => no_such("file", id),
+            },
+            ("DELETE", ["v1", "files", id]) => {
+                let before = self.files.len();
+                self.files.retain(|f| f.info["id"] != *id);
+                if self.files.len() == before {
+                    no_such("file", id)
+                } else {
+                    MockResponse::json(200, json!({"id": id, "object": "file", "deleted": true}))
+                }
+            }
+            ("POST", ["v1", "fine_tuning", "jobs"]) => self.job_create(&request.json()),
+            ("GET", ["v1", "fine_tuning", "jobs"]) => MockResponse::json(
+                200,
+                json!({"object": "list", "data": self.jobs, "has_more": false}),
+            ),
+            ("GET", ["v1", "fine_tuning", "jobs", id]) => match self.job(id) {
+                Some(job) => MockResponse::json(200, job.clone()),
+                None => no_such("fine tuning job", id),
+            },
+            ("POST", ["v1", "fine_tuning", "jobs", id, "cancel"]) => {
+                match self.jobs.iter_mut().find(|j| j["id"] == *id) {
+                    Some(job) => {
+                        if job["status"] != "succeeded" {
+                            job["status"] = json!("cancelled");
+                        }
+                        MockResponse::json(200, job.clone())
+                    }
+                    None => no_such("fine tuning job", id),
+                }
+            }
+            ("GET", ["v1", "fine_tuning", "jobs", id, "events"]) => match self.job(id) {
+                Some(job) => MockResponse::json(
+                    200,
+                    json!({
+                        "object": "list",
+                        "data": [{
+                            "id": format!("ftevent-{id}"),
+                            "object": "fine_tuning.job.event",
+                            "created_at": job["created_at"],
+                            "level": "info",
+                            "message": "The job has successfully completed",
+                        }],
+                        "has_more": false,
+                    }),
+                ),
+                None => no_such("fine tuning job", id),
+            },
+            ("GET", ["v1", "fine_tuning", "jobs", id, "checkpoints"]) => match self.job(id) {
+                Some(_) => MockResponse::json(
+                    200,
+                    json!({"object": "list", "data": [], "has_more": false}),
+                ),
+                None => no_such("fine tuning job", id),
+            },
+            _ => MockResponse::error(
+                404,
+                format!(
+                    "Invalid URL ({} {}): not imitated by the mock server",
+                    request.method, request.path
+                )
+                .as_str(),
+            ),
+        }
+    }
+
+    fn file(&self, id: &str) -> Option<&StoredFile> {
+        self.files.iter().find(|f| f.info["id"] == id)
+    }
+
+    fn job(&self, id: &str) -> Option<&Value> {
+        self.jobs.iter().find(|j| j["id"] == id)
+    }
+
+    /// Keep the file from a multipart upload
+    fn file_upload(&mut self, request: &RecordedRequest) -> MockResponse {
+        let boundary = match request
+            .headers
+            .get("content-type")
+            .and_then(|c| c.split("boundary=").nth(1))
+        {
+            Some(b) => b.trim_matches('"').to_string(),
+            None => return MockResponse::error(400, "Expected a multipart upload"),
+        };
+        let parts = multipart(&request.body, boundary.as_str());
+        let purpose = parts
+            .iter()
+            .find(|p| p.name == "purpose")
+            .map(|p| String::from_utf8_lossy(&p.content).to_string());
+        let file = parts.into_iter().find(|p| p.name == "file");
+        let (purpose, file) = match (purpose, file) {
+            (Some(purpose), Some(file)) => (purpose, file),
+            _ => return MockResponse::error(400, "An upload needs a `file` and a `purpose`"),
+        };
+        let info = json!({
+            "id": self.new_id("file"),
+            "object": "file",
+            "bytes": file.content.len(),
+            "created_at": now(),
+            "filename": file.filename.unwrap_or_default(),
+            "purpose": purpose,
+        });
+        self.files.push(StoredFile {
+            info: info.clone(),
+            content: file.content,
+        });
+        MockResponse::json(200, info)
+    }
+
+    /// Fine tuning takes no time here
+    fn job_create(&mut self, body: &Value) -> MockResponse {
+        let training_file = match body["training_file"].as_str() {
+            Some(f) => f.to_string(),
+            None => return MockResponse::error(400, "Missing required parameter: 'training_file'"),
+        };
+        if self.file(training_file.as_str()).is_none() {
+            return no_such("file", training_file.as_str());
+        }
+        let model = body["model"].as_str().unwrap_or("gpt-4o-mini");
+        let id = self.new_id("ftjob");
+        let job = json!({
+            "id": id,
+            "object": "fine_tuning.job",
+            "created_at": now(),
+            "finished_at": now(),
+            "model": model,
+            "fine_tuned_model": format!("ft:{model}:mock::{id}"),
+            "organization_id": "org-mock",
+            "status": "succeeded",
+            "hyperparameters": body.get("hyperparameters").cloned().unwrap_or(json!({"n_epochs": 1})),
+            "training_file": training_file,
+            "validation_file": body["validation_file"],
+            "result_files": [],
+            "trained_tokens": 0,
+            "error": null,
+            "estimated_finish": null,
+            "seed": body["seed"],
+        });
+        self.jobs.push(job.clone());
+        MockResponse::json(200, job)
+    }
+}
+
+/// The reply to a chat is the last thing the user said
+fn chat(body: &Value) -> MockResponse {
+    let messages = match body["messages"].as_array() {
+        Some(m) if !m.is_empty() => m,
+        _ => return MockResponse::error(400, "Missing required parameter: 'messages'"),
+    };
+    let prompt = messages
+        .iter()
+        .rev()
+        .find(|m| m["role"] == "user")
+        .and_then(|m| m["content"].as_str())
+        .unwrap_or("");
+    // `n` choices, the first is always "Mock reply to: ..."
+    let n = body["n"].as_u64().unwrap_or(1).max(1);
+    let contents: Vec<String> = (0..n)
+        .map(|i| match i {
+            0 => format!("Mock reply to: {prompt}"),
+            i => format!("Mock reply {} to: {prompt}", i + 1),
+        })
+        .collect();
+    let prompt_tokens = messages
+        .iter()
+        .filter_map(|m| m["content"].as_str())
+        .map(words)
+        .sum::<usize>();
+    let completion_tokens = contents.iter().map(|c| words(c.as_str())).sum::<usize>();
+    let usage = json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    });
+    if body["stream"] == true {
+        return chat_stream(body, &contents, usage);
+    }
+    let choices: Vec<Value> = contents
+        .iter()
+        .enumerate()
+        .map(|(index, content)| {
+            let mut choice = json!({
+                "index": index,
+                "message": {"role": "assistant", "content": content},
+                "finish_reason": "stop",
+            });
+            if body["logprobs"] == true {
+                let top = body["top_logprobs"].as_u64().unwrap_or(0) as usize;
+                choice["logprobs"] = json!({"content": logprobs(content.as_str(), top)});
+            }
+            choice
+        })
+        .collect();
+    MockResponse::json(
+        200,
+        json!({
+            "id": format!("chatcmpl-mock{}", now()),
+            "object": "chat.completion",
+            "created": now(),
+            "model": body["model"],
+            "choices": choices,
+            "usage": usage,
+        }),
+    )
+}
+
+/// A chat reply streamed a word at a time, the first choice then the
+/// others.  The usage comes last if `stream_options.include_usage`
+fn chat_stream(body: &Value, contents: &[String], usage: Value) -> MockResponse {
+    let id = format!("chatcmpl-mock{}", now());
+    let chunk = |choices: Value| {
+        json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "created": now(),
+            "model": body["model"],
+            "choices": choices,
+        })
+    };
+    let mut events = vec![];
+    for (index, content) in contents.iter().enumerate() {
+        events.push(chunk(json!([{
+            "index": index,
+            "delta": {"role": "assistant", "content": ""},
+            "finish_reason": null,
+        }])));
+        for word in content.split_inclusive(' ') {
+            events.push(chunk(json!([{
+                "index": index,
+                "delta": {"content": word},
+                "finish_reason": null,
+            }])));
+        }
+        events.push(chunk(json!([{
+            "index": index,
+            "delta": {},
+            "finish_reason": "stop",
+        }])));
+    }
+    if body["stream_options"]["include_usage"] == true {
+        let mut last = chunk(json!([]));
+        last["usage"] = usage;
+        events.push(last);
+    }
+    MockResponse::event_stream(&events)
+}
+
+/// Log probabilities for each word of `content`, a token here.  They
+/// go down the further into the reply, so every colour of a heat map
+/// shows.  `top` alternatives for each
+fn logprobs(content: &str, top: usize) -> Vec<Value> {
+    content
+        .split_inclusive(' ')
+        .enumerate()
+        .map(|(i, token)| {
+            let logprob = -0.25 * i as f64;
+            let top_logprobs: Vec<Value> = (0..top)
+                .map(|j| {
+                    let token = if j == 0 {
+                        token.to_string()
+                    } else {
+                        format!("alt{j}")
+                    };
+                    json!({"token": token, "logprob": logprob - j as f64, "bytes": null})
+                })
+                .collect();
+            json!({
+                "token": token,
+                "logprob": logprob,
+                "bytes": token.as_bytes(),
+                "top_logprobs": top_logprobs,
+            })
+        })
+        .collect()
+}
+
+fn completion(body: &Value) -> MockResponse {
+    let prompt = body["prompt"].as_str().unwrap_or("");
+    // With a `suffix` the gap between the prompt and the suffix is
+    // filled
+    let completed = match body["suffix"].as_str() {
+        Some(_) => "mock_fill()".to_string(),
+        None => format!("Mock completion of: {prompt}"),
+    };
+    let prompt_tokens = words(prompt);
+    let completion_tokens = words(completed.as_str());
+    let text = if body["echo"] == true {
+        format!("{prompt}{completed}")
+    } else {
+        completed.clone()
+    };
+    let logprobs = match body["logprobs"].as_u64() {
+        Some(top) => {
+            let tokens: Vec<&str> = completed.split_inclusive(' ').collect();
+            let token_logprobs: Vec<f64> = (0..tokens.len()).map(|i| -0.25 * i as f64).collect();
+            let top_logprobs: Vec<Value> = tokens
+                .iter()
+                .zip(token_logprobs.iter())
+                .map(|(t, l)| {
+                    let mut alternatives = serde_json::Map::new();
+                    if top > 0 {
+                        alternatives.insert(t.to_string(), json!(l));
+                    }
+                    Value::Object(alternatives)
+                })
+                .collect();
+            json!({
+                "tokens": tokens,
+                "token_logprobs": token_logprobs,
+                "top_logprobs": top_logprobs,
+            })
+        }
+        None => Value::Null,
+    };
+    MockResponse::json(
+        200,
+        json!({
+            "id": format!("cmpl-mock{}", now()),
+            "object": "text_completion",
+            "created": now(),
+            "model": body["model"],
+            "choices": [{
+                "text": text,
+                "index": 0,
+                "logprobs": logprobs,
+                "finish_reason": "stop",
+            }],
+            "usage": {
+                "prompt_tokens": prompt_tokens,
+                "completion_tokens": completion_tokens,
+                "total_tokens": prompt_tokens + completion_tokens,
+            },
+        }),
+    )
+}
+
+fn model(id: &str) -> Value {
+    json!({
+        "id": id,
+        "object": "model",
+        "created": 1_700_000_000,
+        "owned_by": "mock",
+        "permission": [],
+        "root": id,
+        "parent": null,
+    })
+}
+
+fn no_such(what: &str, id: &str) -> MockResponse {
+    MockResponse::error(404, format!("No such {what}: {id}").as_str())
+}
+
+/// Near enough to a token count for a mock
+fn words(s: &str) -> usize {
+    s.split_whitespace().count()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// A field of a multipart form
+struct Part {
+    name: String,
+    filename: Option<String>,
+    content: Vec<u8>,
+}
+
+/// Split a multipart body into its fields
+fn multipart(body: &[u8], boundary: &str) -> Vec<Part> {
+    let delimiter = format!("--{boundary}");
+    let delimiter = delimiter.as_bytes();
+    let mut result = vec![];
+    let mut sections = vec![];
+    let mut start = None;
+    let mut i = 0;
+    while i + delimiter.len() <= body.len() {
+        if &body[i..i + delimiter.len()] == delimiter {
+            if let Some(s) = start {
+                sections.push(&body[s..i]);
+            }
+            i += delimiter.len();
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    for section in sections {
+        // Each section is "\r\n<headers>\r\n\r\n<content>\r\n"
+        let split = match section.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(s) => s,
+            None => continue,
+        };
+        let headers = String::from_utf8_lossy(&section[..split]);
+        let mut content = &section[split + 4..];
+        if content.ends_with(b"\r\n") {
+            content = &content[..content.len() - 2];
+        }
+        let disposition = match headers
+            .lines()
+            .find(|l| l.to_lowercase().starts_with("content-disposition"))
+        {
+            Some(d) => d.to_string(),
+            None => continue,
+        };
+        let parameter = |key: &str| {
+            disposition
+                .split(';')
+                .map(str::trim)
+                .find_map(|p| p.strip_prefix(key))
+                .map(|v| v.trim_matches('"').to_string())
+        };
+        if let Some(name) = parameter("name=") {
+            result.push(Part {
+                name,
+                filename: parameter("filename="),
+                content: content.to_vec(),
+            });
+        }
+    }
+    result
+}
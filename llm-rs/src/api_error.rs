@@ -1,3 +1,4 @@
+use crate::budget::OverBudget;
 use reqwest::StatusCode;
 use std::collections::HashMap;
 use std::{error::Error, fmt};
@@ -7,9 +8,14 @@ pub enum ApiErrorType {
     BadJson(String),
     FailedRequest(String),
     Error(String),
+    // Data was checked before sending and rejected
+    InvalidData(String),
     // When a bad status is returned from a network connection.
     // Includes the failing code and the textual error string
     Status(StatusCode, String),
+    // Refused before sending: it could cost more than the budget
+    // allows
+    OverBudget(OverBudget),
 }
 
 #[derive(Debug)]
@@ -36,6 +42,8 @@ impl fmt::Display for ApiError {
                 write!(f, "{header_report}\nFailed Request: {msg}")
             }
             ApiErrorType::BadJson(ref msg) => write!(f, "Bad JSON: {}", msg),
+            ApiErrorType::InvalidData(ref msg) => write!(f, "Invalid data: {msg}"),
+            ApiErrorType::OverBudget(ref over) => write!(f, "Over budget: {over}"),
 
             // HTTP failure.  Not a 200 status
             ApiErrorType::Status(ref status, ref reason) => {
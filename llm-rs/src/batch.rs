@@ -0,0 +1,311 @@
+//! The structures for the batch API.  A batch is a JSONL file of
+//! requests that OpenAI works through within 24 hours, for half the
+//! price of sending them one at a time.  See
+//! https://platform.openai.com/docs/api-reference/batch
+use crate::chat_request::chat_request_body;
+use crate::chat_request::ChatOptions;
+use crate::fine_tuning_job::time_stamp;
+use crate::json::ChatRequestInfo;
+use crate::json::Message;
+use crate::model_registry::Capability;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+
+/// The only completion window OpenAI offers
+pub const BATCH_COMPLETION_WINDOW: &str = "24h";
+
+/// The endpoint for chat requests in a batch
+pub const BATCH_CHAT_ENDPOINT: &str = "/v1/chat/completions";
+
+/// A batch that is in one of these states will not change again
+const FINISHED_STATES: [&str; 4] = ["completed", "failed", "expired", "cancelled"];
+
+/// One line of the input file of a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRequestLine {
+    /// Used to match the result to the request.  Must be unique in
+    /// the batch
+    pub custom_id: String,
+    pub method: String,
+    pub url: String,
+    pub body: Value,
+}
+
+impl BatchRequestLine {
+    /// A chat request with the conversation in `messages`.  The body
+    /// is shaped for the model as `chat_request_body` shapes it
+    pub fn chat(
+        custom_id: &str,
+        model: &str,
+        messages: &[Message],
+        options: &ChatOptions,
+        capabilities: &BTreeSet<Capability>,
+    ) -> Self {
+        BatchRequestLine {
+            custom_id: custom_id.to_string(),
+            method: "POST".to_string(),
+            url: BATCH_CHAT_ENDPOINT.to_string(),
+            body: chat_request_body(model, messages, options, capabilities),
+        }
+    }
+}
+
+/// A chat request for each of `prompts`, each starting a new
+/// conversation with the system prompt `purpose`.  The custom ID of
+/// a request is the index of its prompt
+pub fn chat_batch_requests(
+    prompts: &[String],
+    purpose: &str,
+    model: &str,
+    options: &ChatOptions,
+    capabilities: &BTreeSet<Capability>,
+) -> Vec<BatchRequestLine> {
+    prompts
+        .iter()
+        .enumerate()
+        .map(|(i, prompt)| {
+            let messages = [
+                Message {
+                    role: "system".to_string(),
+                    content: purpose.to_string(),
+                },
+                Message {
+                    role: "user".to_string(),
+                    content: prompt.clone(),
+                },
+            ];
+            BatchRequestLine::chat(
+                i.to_string().as_str(),
+                model,
+                &messages,
+                options,
+                capabilities,
+            )
+        })
+        .collect()
+}
+
+/// The requests as the contents of a batch input file
+pub fn to_jsonl(requests: &[BatchRequestLine]) -> Result<String, Box<dyn Error>> {
+    let mut result = String::new();
+    for request in requests {
+        result.push_str(serde_json::to_string(request)?.as_str());
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchRequestCounts {
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+}
+
+/// A batch as OpenAI describes it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Batch {
+    pub id: String,
+    pub endpoint: String,
+    pub input_file_id: String,
+    pub completion_window: String,
+    /// One of: validating, failed, in_progress, finalizing,
+    /// completed, expired, cancelling, cancelled
+    pub status: String,
+    pub output_file_id: Option<String>,
+    pub error_file_id: Option<String>,
+    pub created_at: i64,
+    pub completed_at: Option<i64>,
+    pub expires_at: Option<i64>,
+    pub request_counts: Option<BatchRequestCounts>,
+    /// Why the input file was rejected, if it was
+    pub errors: Option<Value>,
+}
+
+impl Batch {
+    /// The batch has stopped, for whatever reason
+    pub fn is_finished(&self) -> bool {
+        FINISHED_STATES.contains(&self.status.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchList {
+    pub data: Vec<Batch>,
+    pub has_more: bool,
+}
+
+/// The response to one request.  `body` is what the endpoint would
+/// have returned
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub status_code: u16,
+    pub request_id: String,
+    pub body: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchLineError {
+    pub code: Option<String>,
+    pub message: String,
+}
+
+/// One line of the output, or error, file of a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchOutputLine {
+    pub id: String,
+    pub custom_id: String,
+    pub response: Option<BatchResponse>,
+    pub error: Option<BatchLineError>,
+}
+
+impl BatchOutputLine {
+    /// The chat response, or why there is not one
+    pub fn chat_response(&self) -> Result<ChatRequestInfo, String> {
+        if let Some(ref error) = self.error {
+            return Err(error.message.clone());
+        }
+        match self.response {
+            Some(ref response) if response.status_code == 200 => {
+                serde_json::from_value(response.body.clone()).map_err(|e| format!("{e}"))
+            }
+            Some(ref response) => Err(match response.body["error"]["message"].as_str() {
+                Some(message) => format!("{}: {message}", response.status_code),
+                None => format!("{}", response.status_code),
+            }),
+            None => Err("No response".to_string()),
+        }
+    }
+}
+
+/// Parse the contents of a batch output or error file
+pub fn parse_output(contents: &str) -> Result<Vec<BatchOutputLine>, Box<dyn Error>> {
+    let mut result: Vec<BatchOutputLine> = vec![];
+    for line in contents.lines() {
+        if !line.trim().is_empty() {
+            result.push(serde_json::from_str(line)?);
+        }
+    }
+    Ok(result)
+}
+
+impl fmt::Display for Batch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "ID: {}", self.id)?;
+        writeln!(f, "Endpoint: {}", self.endpoint)?;
+        writeln!(f, "Status: {}", self.status)?;
+        writeln!(f, "Created At: {}", time_stamp(self.created_at))?;
+        if let Some(completed_at) = self.completed_at {
+            writeln!(f, "Completed At: {}", time_stamp(completed_at))?;
+        }
+        if let Some(expires_at) = self.expires_at {
+            writeln!(f, "Expires At: {}", time_stamp(expires_at))?;
+        }
+        writeln!(f, "Input File: {}", self.input_file_id)?;
+        if let Some(ref output_file_id) = self.output_file_id {
+            writeln!(f, "Output File: {output_file_id}")?;
+        }
+        if let Some(ref error_file_id) = self.error_file_id {
+            writeln!(f, "Error File: {error_file_id}")?;
+        }
+        if let Some(ref counts) = self.request_counts {
+            writeln!(
+                f,
+                "Requests: {} Completed: {} Failed: {}",
+                counts.total, counts.completed, counts.failed
+            )?;
+        }
+        if let Some(ref errors) = self.errors {
+            writeln!(f, "Errors: {errors}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for BatchList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for batch in self.data.iter() {
+            let counts = batch.request_counts.clone().unwrap_or_default();
+            writeln!(
+                f,
+                "{} {} {} {}/{}",
+                batch.id,
+                time_stamp(batch.created_at),
+                batch.status,
+                counts.completed + counts.failed,
+                counts.total
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn request_lines() {
+        let prompts = vec!["Hello".to_string(), "Goodbye".to_string()];
+        let options = ChatOptions::new()
+            .temperature(0.0)
+            .max_tokens(100)
+            .stop("END")
+            .seed(7)
+            .n(2);
+        let chat = BTreeSet::from([
+            Capability::Chat,
+            Capability::SystemRole,
+            Capability::Temperature,
+        ]);
+        let requests = chat_batch_requests(&prompts, "Be terse", "gpt-4o-mini", &options, &chat);
+        let jsonl = to_jsonl(&requests).unwrap();
+        let lines: Vec<Value> = jsonl
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["custom_id"], "1");
+        assert_eq!(lines[1]["url"], BATCH_CHAT_ENDPOINT);
+        assert_eq!(lines[1]["body"]["messages"][0]["role"], "system");
+        assert_eq!(lines[1]["body"]["messages"][1]["content"], "Goodbye");
+        assert_eq!(lines[1]["body"]["temperature"], 0.0);
+        assert_eq!(lines[1]["body"]["max_tokens"], 100);
+        assert_eq!(lines[1]["body"]["stop"], json!(["END"]));
+        assert_eq!(lines[1]["body"]["seed"], 7);
+        assert_eq!(lines[1]["body"]["n"], 2);
+
+        // A reasoning model takes no temperature, stop, seed or n, and
+        // its limit by another name
+        let reasoning = BTreeSet::from([Capability::Chat, Capability::Reasoning]);
+        let requests = chat_batch_requests(&prompts, "Be terse", "o1", &options, &reasoning);
+        let body = &requests[0].body;
+        assert!(body.get("temperature").is_none());
+        assert!(body.get("max_tokens").is_none());
+        assert_eq!(body["max_completion_tokens"], 100);
+        assert!(body.get("stop").is_none());
+        assert!(body.get("seed").is_none());
+        assert!(body.get("n").is_none());
+    }
+
+    #[test]
+    fn output_lines() {
+        let contents = r#"{"id": "batch_req_1", "custom_id": "0", "response": {"status_code": 200, "request_id": "r1", "body": {"id": "chatcmpl-1", "object": "chat.completion", "created": 1711652795, "model": "gpt-4o-mini", "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}], "usage": {"prompt_tokens": 10, "completion_tokens": 1, "total_tokens": 11}}}, "error": null}
+
+{"id": "batch_req_2", "custom_id": "1", "response": {"status_code": 400, "request_id": "r2", "body": {"error": {"message": "Bad model"}}}, "error": null}
+{"id": "batch_req_3", "custom_id": "2", "response": null, "error": {"code": "expired", "message": "Not done in time"}}
+"#;
+        let lines = parse_output(contents).unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0].chat_response().unwrap().choices[0].message.content,
+            "Hi"
+        );
+        assert_eq!(lines[1].chat_response().unwrap_err(), "400: Bad model");
+        assert_eq!(lines[2].chat_response().unwrap_err(), "Not done in time");
+    }
+}
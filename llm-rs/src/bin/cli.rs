@@ -1,16 +1,80 @@
 mod code {
     pub mod cli_error;
+    pub mod completion;
+    pub mod compose;
+    pub mod config;
+    pub mod meta_command;
     pub mod my_helper;
+    pub mod one_shot;
+    pub mod sessions;
     pub mod shared_state;
 }
 use chrono::Local;
+use code::completion::Candidates;
 use code::my_helper::MyHelper;
+use code::compose;
+use code::compose::ComposeKey;
+use code::config::ConfigFile;
+use code::config::Settings;
+use code::config::CONFIG_FILE;
+use code::meta_command;
+use code::meta_command::check_user_commands;
+use code::meta_command::UserCommand;
+use code::one_shot::usage_error;
+use code::one_shot::OneShot;
+use code::sessions::Session;
+use code::sessions::SessionStore;
+use code::sessions::SESSIONS_DIR;
 use code::shared_state::SharedState;
 use context::Context;
 use directories::ProjectDirs;
 use image::ImageFormat;
+use llm_rs::batch::BatchOutputLine;
+use llm_rs::budget::Budget;
+use llm_rs::budget::BudgetLimits;
+use llm_rs::budget::DEFAULT_WARN_AT;
+use llm_rs::candidates;
+use llm_rs::chat_request::ChatOptions;
+use llm_rs::chat_request::CHAT_OPTION_NAMES;
+use llm_rs::completion_request::CompletionOptions;
+use llm_rs::completion_request::FillInMiddle;
+use llm_rs::completion_request::COMPLETION_OPTION_NAMES;
+use llm_rs::completion_request::DEFAULT_COMPLETIONS_MODEL;
+use llm_rs::completion_request::FILL_MARKER;
+use llm_rs::cassette::Cassette;
+use llm_rs::response_cache::CachingTransport;
+use llm_rs::script::read_script;
+use llm_rs::script::ScriptLine;
+use llm_rs::script::ScriptReport;
+use llm_rs::script::Step;
+use llm_rs::response_cache::ResponseCache;
+use llm_rs::response_cache::DEFAULT_CACHE_SIZE;
+use llm_rs::response_cache::DEFAULT_CACHE_TTL;
+use llm_rs::transport::HttpTransport;
+use llm_rs::transport::TransportOptions;
+use llm_rs::transport::DEFAULT_TIMEOUT;
+use llm_rs::export::export as export_context;
+use llm_rs::export::ExportFormat;
+use llm_rs::export::EXPORT_FORMATS;
+use llm_rs::fine_tuning_export::export;
+use llm_rs::fine_tuning_export::ExportFilter;
+use llm_rs::fine_tuning_job::FineTuningJobRequest;
+use llm_rs::fine_tuning_job::DEFAULT_FINE_TUNE_MODEL;
+use llm_rs::fine_tuning_validate::validate_file;
+use llm_rs::include::include;
+use llm_rs::include::IncludeOptions;
+use llm_rs::include::Overflow;
+use llm_rs::json::ChatChoice;
+use llm_rs::markdown::MarkdownStream;
 use llm_rs::model_mode::ModelMode;
+use llm_rs::model_registry::available_models;
+use llm_rs::model_registry::Capability;
+use llm_rs::model_registry::ModelCache;
+use llm_rs::model_registry::ModelEntry;
+use llm_rs::model_registry::ModelRegistry;
+use llm_rs::model_registry::MODEL_CACHE_TTL;
 use openai_interface::ApiInterface;
+use openai_interface::BUDGET_WARNING_HEADER;
 use rand::distributions::Alphanumeric;
 use rand::Rng;
 use regex::Regex;
@@ -26,18 +90,25 @@ use std::env::current_dir;
 use std::error::Error;
 use std::fs::File;
 use std::fs::OpenOptions;
+use std::io::IsTerminal;
 use std::io::Read;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
 use std::time::Instant;
 use std::{env, fs};
+use terminal_size::Width;
 extern crate llm_rs;
 
 use clap::Parser;
+use clap::Subcommand;
 use llm_rs::context;
 use llm_rs::openai_interface;
+use llm_rs::openai_interface::API_URL;
 
 const DEFAULT_MODEL: &str = "gpt-4";
 const DEFAULT_TOKENS: u32 = 2_000_u32;
@@ -45,38 +116,160 @@ const DEFAULT_TEMPERATURE: f32 = 0.9_f32;
 const DEFAULT_MODE: &str = "chat";
 const DEFAULT_RECORD_FILE: &str = "reply.txt";
 const DEFAULT_HISTORY_FILE: &str = "history.txt";
+const FINE_TUNE_POLL_SECONDS: u64 = 10;
+const BATCH_POLL_SECONDS: u64 = 60;
+const MODEL_CACHE_FILE: &str = "models.json";
+const MODEL_CAPABILITIES_FILE: &str = "model_capabilities.json";
+const RESPONSE_CACHE_DIR: &str = "responses";
+const BUDGET_FILE: &str = "budget.json";
+
+/// The exit code of `--script` when an assertion fails or a step
+/// cannot be run
+const EXIT_SCRIPT_FAILED: i32 = 1;
 
 /// Command line argument definitions
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Arguments {
-    /// The model to use
-    #[arg(long, short = 'm',default_value=DEFAULT_MODEL)]
-    model: String,
+    /// The profile in the config file to use.  [Default: the config
+    /// file's `default_profile`]
+    #[arg(long)]
+    profile: Option<String>,
+
+    #[arg(long, short = 'm', help = format!("The model to use.  [Default: {DEFAULT_MODEL}]"))]
+    model: Option<String>,
 
-    /// Maximum tokens to return
-    #[arg(long, short = 't', default_value_t=DEFAULT_TOKENS)]
-    max_tokens: u32,
+    #[arg(long, short = 't', help = format!("Maximum tokens to return.  [Default: {DEFAULT_TOKENS}]"))]
+    max_tokens: Option<u32>,
 
-    /// Temperature for the model.
-    #[arg(long, short = 'T', default_value_t = DEFAULT_TEMPERATURE)]
-    temperature: f32,
+    #[arg(long, short = 'T', help = format!("Temperature for the model.  [Default: {DEFAULT_TEMPERATURE}]"))]
+    temperature: Option<f32>,
 
     /// The secret key.  [Default: environment variable `OPENAI_API_KEY`]
     #[arg(long)]
     api_key: Option<String>,
 
-    /// The initial mode (API endpoint)
-    #[arg(long, short='d', default_value=DEFAULT_MODE)]
-    mode: String,
+    #[arg(long, help = format!("Where requests are sent.  For testing against a mock server (see `llm-mock`).  [Default: {API_URL}]"))]
+    api_url: Option<String>,
 
-    /// The file name that prompts and replies are recorded in
-    #[arg(long, short='r', default_value=DEFAULT_RECORD_FILE)]
-    record_file: String,
+    /// Record requests and responses, with the key redacted, to this
+    /// cassette (JSONL)
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Answer requests from this cassette, made with `--record`,
+    /// instead of the network
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Seconds a request can take
+    #[arg(long, default_value_t = DEFAULT_TIMEOUT.as_secs())]
+    timeout: u64,
+
+    /// Send requests through this proxy, like "http://proxy:8080"
+    #[arg(long)]
+    proxy: Option<String>,
+
+    /// Trust the certificate authority in this PEM file.  Can be given
+    /// more than once
+    #[arg(long)]
+    ca_cert: Vec<PathBuf>,
+
+    /// Answer requests that have a temperature of 0, or a seed, from a
+    /// cache of earlier answers if they have been asked before.
+    /// Streamed replies are not cached, so this turns streaming off
+    #[arg(long)]
+    cache: bool,
+
+    #[arg(long, help = format!("Where the cache is.  [Default: \"{RESPONSE_CACHE_DIR}\" in the cache directory]"))]
+    cache_dir: Option<PathBuf>,
+
+    /// Seconds an answer stays in the cache
+    #[arg(long, default_value_t = DEFAULT_CACHE_TTL.as_secs())]
+    cache_ttl: u64,
+
+    /// The most megabytes the cache can take
+    #[arg(long, default_value_t = DEFAULT_CACHE_SIZE / 1024 / 1024)]
+    cache_size: u64,
+
+    /// The most cents this session can spend on chats, completions
+    /// and images
+    #[arg(long)]
+    budget_session: Option<f64>,
+
+    /// The most cents that can be spent in a day
+    #[arg(long)]
+    budget_day: Option<f64>,
+
+    /// The most cents that can be spent in a month
+    #[arg(long)]
+    budget_month: Option<f64>,
+
+    #[arg(long, help = format!("Warn when this fraction of a budget is spent.  [Default: {DEFAULT_WARN_AT}]"))]
+    budget_warn: Option<f64>,
+
+    #[arg(long, short = 'd', help = format!("The initial mode (API endpoint).  [Default: {DEFAULT_MODE}]"))]
+    mode: Option<String>,
+
+    #[arg(long, short = 'r', help = format!("The file name that prompts and replies are recorded in. [Default: {DEFAULT_RECORD_FILE}]"))]
+    record_file: Option<String>,
 
     /// The system prompt sent to the chat model
     #[arg(long, short='p', default_value=None)]
     system_prompt: Option<String>,
+
+    /// Wait for the whole chat reply instead of showing it as it
+    /// arrives
+    #[arg(long)]
+    no_stream: bool,
+
+    /// Carry on with a saved session: the one with this ID or name, or
+    /// the latest.  See `! sessions`
+    #[arg(long)]
+    resume: Option<Option<String>>,
+
+    /// Run a script of prompts, meta commands and assertions, then
+    /// exit: 0 if it passed, 1 if it failed.  See `! run`
+    #[arg(long)]
+    script: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Commands that run and exit instead of starting the interactive
+/// interface
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert saved conversations (from `! sx`) into fine tuning JSONL
+    FineTuneExport {
+        /// The JSONL file to write
+        output: PathBuf,
+
+        /// Saved conversations, or directories of them
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Only conversations with this model
+        #[arg(long)]
+        model: Option<String>,
+
+        /// Only conversations with at least this many prompt/response pairs
+        #[arg(long, default_value_t = 1)]
+        min_turns: usize,
+
+        /// Only conversations whose system prompt matches this regular expression
+        #[arg(long)]
+        purpose: Option<String>,
+    },
+
+    /// Send one prompt, in the mode given with `--mode`, print the
+    /// reply and exit.  Exits with 1 if the request fails and 2 if the
+    /// command line is wrong
+    Ask(OneShot),
+
+    /// Print the meta commands as the Markdown table in the README
+    MetaCommands,
 }
 
 /// A structure to hold data for the interface.
@@ -118,6 +311,50 @@ struct CliInterface {
 
     /// Local data.  Generally this is reading local files of data
     local_data: HashMap<String, String>,
+
+    /// Colour the tokens of chat replies by their probability
+    heat_map: bool,
+
+    /// The heat map turned on log probabilities, so turns them off
+    heat_map_logprobs: bool,
+
+    /// A chat prompt and its choices, waiting for one to be picked
+    /// with "! pick"
+    choices: Option<(String, Vec<ChatChoice>)>,
+
+    /// Earlier answers, if `--cache`
+    cache: Option<Arc<ResponseCache>>,
+
+    /// What the interface started with, from the command line, the
+    /// profile or the defaults
+    settings: Settings,
+
+    /// Show chat replies as they arrive
+    stream: bool,
+
+    /// This session, saved to `sessions` after every turn
+    session: Session,
+
+    sessions: SessionStore,
+
+    /// The last prompt sent, before `{name}`s were expanded.  For
+    /// composing the next in an editor
+    last_prompt: String,
+
+    /// The line to compose in an editor, put there by Alt-e
+    compose_key: Arc<Mutex<Option<String>>>,
+
+    /// Model IDs for completion, from the cached list of models
+    model_ids: Vec<String>,
+
+    /// File IDs for completion, from the last "! f"
+    file_ids: Vec<String>,
+
+    /// Meta commands the user made in the config file
+    user_commands: HashMap<String, UserCommand>,
+
+    /// A script is running.  Scripts do not run scripts
+    in_script: bool,
 }
 
 impl CliInterface {
@@ -159,613 +396,1815 @@ impl CliInterface {
         get(url).unwrap().read_to_end(&mut img_data).unwrap();
         println!("Down loaded URL: {} bytes", img_data.len());
 
-        let incomming_image_file_path = Self::make_file("png")?;
-        println!("incomming_image_file_path {:?}", incomming_image_file_path);
-        let mut incomming_image_file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&incomming_image_file_path)?;
-        println!("Created {:?}", incomming_image_file_path);
-        incomming_image_file.write_all(&img_data)?;
-        let img = image::open(&incomming_image_file_path)?;
-        println!("Opened {:?}", incomming_image_file);
-        incomming_image_file.write_all(&img_data)?;
-        println!(
-            "Wrote image: {:?} {:#?}",
-            start.elapsed(),
-            incomming_image_file_path,
-        );
+        let incomming_image_file_path = Self::make_file("png")?;
+        println!("incomming_image_file_path {:?}", incomming_image_file_path);
+        let mut incomming_image_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(&incomming_image_file_path)?;
+        println!("Created {:?}", incomming_image_file_path);
+        incomming_image_file.write_all(&img_data)?;
+        let img = image::open(&incomming_image_file_path)?;
+        println!("Opened {:?}", incomming_image_file);
+        incomming_image_file.write_all(&img_data)?;
+        println!(
+            "Wrote image: {:?} {:#?}",
+            start.elapsed(),
+            incomming_image_file_path,
+        );
+
+        // Ensure the image has an alpha channel
+        let img_rgba = img.into_rgba8();
+
+        self.image = Some(incomming_image_file_path.as_path().to_owned());
+        img_rgba.save_with_format(self.image.clone().unwrap(), ImageFormat::Png)?;
+        webbrowser::open(self.image.clone().unwrap().as_os_str().to_str().unwrap())?;
+
+        Ok(())
+    }
+
+    fn set_up_read_line(&self) -> rustyline::Result<Editor<MyHelper, FileHistory>> {
+        let config = Config::builder()
+            .history_ignore_space(true)
+            .completion_type(CompletionType::List)
+            .edit_mode(EditMode::Emacs)
+            .build();
+        let h = MyHelper {
+            completer: FilenameCompleter::new(),
+            highlighter: MatchingBracketHighlighter::new(),
+            hinter: HistoryHinter {},
+            colored_prompt: "".to_owned(),
+            validator: MatchingBracketValidator::new(),
+            candidates: self.candidates(),
+        };
+        let mut read_line = Editor::with_config(config)?;
+        read_line.set_helper(Some(h));
+        read_line.bind_sequence(KeyEvent::alt('n'), Cmd::HistorySearchForward);
+        read_line.bind_sequence(KeyEvent::alt('p'), Cmd::HistorySearchBackward);
+        // Compose the line in an editor
+        read_line.bind_sequence(
+            KeyEvent::alt('e'),
+            EventHandler::Conditional(Box::new(ComposeKey {
+                line: self.compose_key.clone(),
+            })),
+        );
+        if read_line.load_history(self.history_file.as_str()).is_err() {
+            println!("No previous history.");
+        }
+
+        // Set control key C-q to quit.  Not really needed.  C-c does this
+        // auto-magically
+        read_line.bind_sequence(
+            Event::KeySeq(vec![KeyEvent::ctrl('q')]),
+            EventHandler::Simple(Cmd::Interrupt),
+        );
+        Ok(read_line)
+    }
+
+    /// What the line editor can complete
+    fn candidates(&self) -> Candidates {
+        let mut models = self.model_ids.clone();
+        if !models.contains(&self.model) {
+            models.push(self.model.clone());
+        }
+        let mut names: Vec<String> = self.local_data.keys().cloned().collect();
+        names.sort();
+        let mut user_commands: Vec<String> = self.user_commands.keys().cloned().collect();
+        user_commands.sort();
+        Candidates {
+            models,
+            names,
+            file_ids: self.file_ids.clone(),
+            user_commands,
+        }
+    }
+
+    fn expand_variables(&self, input: String) -> Result<String, Box<dyn Error>> {
+        let re = Regex::new(r"\{(\w+)\}").unwrap();
+        let result = re
+            .replace_all(&input, |caps: &regex::Captures| {
+                self.local_data
+                    .get(&caps[1])
+                    .unwrap_or(&caps[0].to_string())
+                    .to_string()
+            })
+            .to_string();
+        Ok(result)
+    }
+
+    /// Process prompts that are to effect or inspect the programme itself
+    /// `prommpt` is what the user entered after the initial "!".  The
+    /// commands are in `META_COMMANDS`, and the user's in
+    /// `user_commands`
+    fn process_meta(
+        &mut self,
+        prompt: &str,
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // The first word is: "!"
+        // The rest of the words are commands for the programme to interpret.
+        let words: Vec<&str> = prompt.split_whitespace().skip(1).collect();
+        let (cmd, args) = match words.split_first() {
+            Some((cmd, args)) => (*cmd, args),
+            None => return Ok("Enter a meta command".to_string()),
+        };
+        if let Some(command) = meta_command::find(cmd) {
+            return (command.handler)(self, args, api_interface);
+        }
+        match self.user_commands.get(cmd).cloned() {
+            Some(user_command) => {
+                // Arguments go on the end of the last step
+                let mut response_text = String::new();
+                for words in user_command.with_args(args) {
+                    let (cmd, args) = words.split_first().ok_or("Empty command")?;
+                    let command =
+                        meta_command::find(cmd).ok_or_else(|| format!("Unknown command: {cmd}"))?;
+                    response_text += (command.handler)(self, args, api_interface)?.as_str();
+                    if !response_text.ends_with('\n') {
+                        response_text.push('\n');
+                    }
+                }
+                Ok(response_text)
+            }
+            None => Ok(format!("Unknown command: {cmd}\n")),
+        }
+    }
+
+    fn meta_files(
+        &mut self,
+        _args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // List files
+        let vl = api_interface.files_list().unwrap();
+
+        // Sort the returned list.  The OpenAI API returns
+        // files in a random order
+        let mut sorted_vec = vl.body;
+        sorted_vec.sort();
+        self.file_ids = sorted_vec.iter().map(|f| f.1.clone()).collect();
+        let response_text = format!(
+            ".....File ID...................Name{}",
+            sorted_vec
+                .iter()
+                .fold(String::new(), |a, b| format!("{a}\n{}: {}", b.1, b.0))
+        );
+        Ok(response_text)
+    }
+
+    fn meta_file_upload(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Upload a file of fine tuning data.  It is checked for training
+        // `model` first
+        // "! fu <path> [model]"
+        let mut meta = args.iter().copied();
+        let response_text = match meta.next() {
+            None => format!(
+                "! fu <path> [model]  Paths relative to {}",
+                current_dir()?.display()
+            ),
+            Some(file_name) => {
+                let model = meta.next().unwrap_or(DEFAULT_FINE_TUNE_MODEL);
+                match api_interface.files_upload_fine_tuning(Path::new(file_name), model) {
+                    Ok(r) => format!("Uploaded: {}", r.body),
+                    Err(err) => format!("{err}: Failed to upload {file_name}"),
+                }
+            }
+        };
+        Ok(response_text)
+    }
+
+    fn meta_file_validate(
+        &mut self,
+        args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Validate a fine tuning file, and estimate the cost of training
+        // "! fv <path> [model] [epochs]"
+        let mut meta = args.iter().copied();
+        let response_text = match meta.next() {
+            Some(file_name) => {
+                let model = meta.next().unwrap_or(DEFAULT_FINE_TUNE_MODEL);
+                let epochs: Option<usize> = match meta.next().map(str::parse::<usize>) {
+                    Some(Ok(e)) => Some(e),
+                    Some(Err(_)) => {
+                        return Ok(
+                            "! fv <path> [model] [epochs]  Epochs is a whole number".to_string()
+                        )
+                    }
+                    None => None,
+                };
+                match validate_file(Path::new(file_name), model, epochs) {
+                    Ok(report) => format!("{report}"),
+                    Err(err) => format!("{err}: Failed to read {file_name}"),
+                }
+            }
+            None => "! fv <path> [model] [epochs]".to_string(),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_file_info(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // File info
+        let mut meta = args.iter().copied();
+        let response_text: String;
+        if let Some(file_id) = meta.next() {
+            response_text = match api_interface.file_info(file_id.to_string()) {
+                Ok(s) => s.body,
+                Err(err) => format!("{err} Failed to delete"),
+            };
+        } else {
+            response_text = "Enter a file ID".to_string();
+        }
+        Ok(response_text)
+    }
+
+    fn meta_file_contents(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // File contents
+        let mut meta = args.iter().copied();
+        let response_text: String;
+        if let Some(file_id) = meta.next() {
+            let local_file = meta.next();
+            response_text = match api_interface.file_contents(file_id.to_string()) {
+                Ok(s) => {
+                    if let Some(local_file) = local_file {
+                        let mut file = File::create(local_file)?;
+                        file.write_all(s.body.as_bytes())?;
+                        "success".to_string()
+                    } else {
+                        s.body
+                    }
+                }
+                Err(err) => format!("{err} Failed to get contents"),
+            };
+        } else {
+            response_text = "Enter a file ID".to_string();
+        }
+        Ok(response_text)
+    }
+
+    fn meta_file_delete(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Delete a file
+        let file_id: String = args.join(" ");
+        let response_text = match api_interface.files_delete(file_id.clone()) {
+            Ok(_) => {
+                self.file_ids.retain(|f| *f != file_id);
+                "Deleted".to_string()
+            }
+            Err(err) => format!("{err} Failed to delete"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_settings(
+        &mut self,
+        _args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        let response_text = format!(
+            "OpenAI Interface: {api_interface}\n\
+             Record File:{}\n\
+             Model: {}\n\
+             Model Mode: {}\n\
+             Image: {:#?}\n\
+             mask: {:#?}\n\
+             audio file:{:#?}\n\
+             Completions{}\n\
+             {}",
+            // Display the parameters
+            self.record_file,
+            self.model,
+            self.model_mode,
+            self.image,
+            self.mask,
+            self.audio_file,
+            self.local_data
+                .keys()
+                .fold("".to_string(), |a, b| format!("{a}\n\t{b}")),
+            self.settings,
+        );
+        Ok(response_text)
+    }
+
+    fn meta_model_info(
+        &mut self,
+        _args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Display all the info we can get about models
+        let response_text = match api_interface.model_list() {
+            Ok(s) => s.body,
+            Err(err) => format!("{err}: Failed to get model list"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_models(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Display the models for the current mode, or all
+        // models.  The list is cached.  "refresh" fetches it
+        // again
+        // "! md [all] [refresh]"
+        let ttl = if args.contains(&"refresh") {
+            Duration::ZERO
+        } else {
+            MODEL_CACHE_TTL
+        };
+        let response_text = match CliInterface::available_models(api_interface, ttl) {
+            Ok(models) => {
+                self.model_ids = models.iter().map(|m| m.id.clone()).collect();
+                let models: Vec<&ModelEntry> = if args.contains(&"all") {
+                    models.iter().collect()
+                } else {
+                    models
+                        .iter()
+                        .filter(|m| m.can(self.model_mode.capability()))
+                        .collect()
+                };
+                format!(
+                    "Models for mode: {}: {}",
+                    if args.contains(&"all") {
+                        "all".to_string()
+                    } else {
+                        self.model_mode.to_string()
+                    },
+                    models
+                        .iter()
+                        .fold(String::new(), |a, b| format!("{a}\n\t{b}"))
+                )
+            }
+            Err(err) => format!("{err}: Failed to get models"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_model_set(
+        &mut self,
+        args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Set a model
+        let mut meta = args.iter().copied();
+        let response_text: String;
+        if let Some(model_name) = meta.next() {
+            response_text = format!("New model: {model_name}");
+            self.model = model_name.to_string();
+        } else {
+            response_text = "No model".to_string();
+        }
+        Ok(response_text)
+    }
+
+    fn meta_modes(
+        &mut self,
+        _args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        let response_text = "Modes\n\t\
+                         completions\n\t\
+                         chat\n\t\
+                         image\n\t\
+                         image_edit\n\t\
+                         audio_transcription\n\t\
+                         "
+        .to_string();
+        Ok(response_text)
+    }
+
+    fn meta_mode(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Set the mode (effectively the API endpoint at OpenAI
+        let mut meta = args.iter().copied();
+        let response_text: String;
+        match meta.next() {
+            // "! m" on its own to get a list of models
+            // "! m <model name>" to change it
+            Some(mode) => match mode {
+                "completions" => {
+                    self.model_mode = ModelMode::Completions;
+                    // Chat models do not do completions
+                    let capabilities = api_interface
+                        .model_registry
+                        .capabilities(self.model.as_str());
+                    if capabilities.is_empty() || capabilities.contains(&Capability::Completions) {
+                        response_text = "Model mode => Completions\n".to_string();
+                    } else {
+                        self.model = DEFAULT_COMPLETIONS_MODEL.to_string();
+                        response_text =
+                            format!("Model mode => Completions\nModel => {}\n", self.model);
+                    }
+                }
+                "chat" => {
+                    // A conversation with the LLM. `system_prompt` sets
+                    // the tone of the conversation.  It can be over
+                    // ridden here, and there must be some prompt
+                    let purpose = meta.collect::<Vec<&str>>().join(" ");
+                    if purpose.is_empty() && api_interface.context.purpose.is_empty() {
+                        response_text = "Provide a system prompt for the chat".to_string();
+                    } else {
+                        self.model_mode = ModelMode::Chat;
+                        response_text = "Model mode => Chat\n".to_string();
+                        if !purpose.is_empty() {
+                            api_interface.context.purpose = purpose;
+                        }
+                    }
+                }
+                "image" => {
+                    // Create images from prompts.  If a file is passed in
+                    // it is an image to edit, so the mode is set to
+                    // `ImageEdit`
+                    let file_name: String = meta.collect::<Vec<&str>>().join(" ");
+                    if file_name.is_empty() {
+                        // User is going to get AI to generate the image
+                        self.model_mode = ModelMode::Image;
+                        response_text = "Model mode => Image\n".to_string();
+                    } else {
+                        // User is supplying an image
+                        if PathBuf::from(file_name.as_str()).exists() {
+                            self.image = Some(PathBuf::from(file_name));
+                            self.model_mode = ModelMode::ImageEdit;
+                            response_text = "Model mode => ImageEdit\n".to_string();
+                        } else {
+                            self.model_mode = ModelMode::Image;
+                            response_text =
+                                "File: {file_name} does not exist.  Model mode => Image\n"
+                                    .to_string();
+                        }
+                    }
+                }
+                "image_edit" => {
+                    // Edit an image.
+                    match self.model_mode {
+                        ModelMode::Image => {
+                            if self.image.is_none() && self.focus_image_url.is_none() {
+                                response_text = format!(
+                                    "Cannot switch to ImageEdit mode \
+                                     from {} mode until you have created \
+                                     an image.  Enter a prompt to create an image",
+                                    self.model_mode
+                                );
+                            } else if self.mask.is_none() {
+                                response_text = format!(
+                                    "Cannot switch to ImageEdit mode \
+                                     from {} mode until you have created \
+                                     a mask.",
+                                    self.model_mode
+                                );
+                            } else {
+                                response_text = "Edit image".to_string();
+                                self.model_mode = ModelMode::ImageEdit;
+                            }
+                        }
+                        _ => {
+                            response_text = format!("Cannot switch to ImageEdit mode from {} mode.  Must be in Image mode", self.model_mode);
+                        }
+                    };
+                }
+                "audio_transcription" => {
+                    if self.audio_file.is_none() {
+                        response_text =
+                            "Add an audio file before switching to audio_transcription mode"
+                                .to_string();
+                    } else {
+                        self.model_mode = ModelMode::AudioTranscription;
+                        response_text = "Audio Transcription mode".to_string();
+                    }
+                }
+                _ => response_text = format!("{mode} not a Model Mode\n"),
+            },
+            None => {
+                response_text = "Model modes\n\
+                                 completions\n\
+                                 chat\n\
+                                 image\n\
+                                 image_edit\n\
+                                 audio_transcription\n"
+                    .to_string()
+            }
+        }
+        Ok(response_text)
+    }
+
+    fn meta_context_display(
+        &mut self,
+        _args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        let response_text = api_interface.context.as_string();
+        Ok(response_text)
+    }
+
+    fn meta_context_clear(
+        &mut self,
+        _args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        let response_text = "Clear context".to_string();
+        api_interface.clear_context();
+        Ok(response_text)
+    }
+
+    fn meta_context_print(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Print out the conversation to the passed path
+        // in a human readable form
+
+        let file_path: String = args.join(" ");
+        let response_text = match File::create(file_path.clone()) {
+            Ok(mut f) => {
+                // Created file
+                // Save the context into the specified file
+                let context: Context = api_interface.get_context()?;
+                // `context` has query/response pairs.  So has an even length
+                assert!(context.len() % 2 == 0);
+                let context = CliInterface::pretty_print_conversation(context)?;
+                f.write_all(context.as_bytes())?;
+                format!("Wrote context to {file_path}")
+            }
+
+            Err(err) => {
+                // Failed to create file
+                format!("{err}: Failed to open file at: {file_path}")
+            }
+        };
+        Ok(response_text)
+    }
+
+    fn meta_export(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Write the conversation as Markdown, HTML or JSON
+        let (format, file_path) = match args.split_first() {
+            Some((format, path)) if !path.is_empty() => (*format, path.join(" ")),
+            _ => return Ok(format!("! export <{}> <path>", EXPORT_FORMATS.join(" | "))),
+        };
+        let format = match ExportFormat::from_str(format) {
+            Ok(format) => format,
+            Err(err) => return Ok(err),
+        };
+        let mut context: Context = api_interface.get_context()?;
+        if context.model.is_none() {
+            context.model = Some(self.model.clone());
+        }
+        let response_text = match File::create(file_path.as_str()) {
+            Ok(mut f) => {
+                f.write_all(export_context(&context, format).as_bytes())?;
+                format!("Exported the conversation as {format} to {file_path}")
+            }
+            Err(err) => format!("{err}: Failed to open file at: {file_path}"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_verbosity(
+        &mut self,
+        args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // set verbosity
+        let mut meta = args.iter().copied();
+        let response_text: String;
+        if let Some(v) = meta.next() {
+            response_text = match v.parse::<usize>() {
+                Ok(v) => {
+                    self.verbose = v;
+                    format!("Verbosity set to {v}\n")
+                }
+                Err(err) => format!("Cannot make a usize from {v} because: {err}\n"),
+            }
+        } else {
+            response_text = "No verbosity level passed".to_string();
+        }
+        Ok(response_text)
+    }
+
+    fn meta_max_tokens(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // set tokens
+        let mut meta = args.iter().copied();
+        let response_text: String;
+        if let Some(t) = meta.next() {
+            response_text = match t.parse::<u32>() {
+                Ok(t) => {
+                    api_interface.tokens = t;
+                    format!("New tokens: {t}\n")
+                }
+                Err(err) => format!("Cannot make a float from {t} because: {err}\n"),
+            };
+        } else {
+            response_text = "No tokens".to_string();
+        }
+        Ok(response_text)
+    }
+
+    fn meta_temperature(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // set temperature
+        let mut meta = args.iter().copied();
+        let response_text: String;
+        if let Some(t) = meta.next() {
+            response_text = match t.parse::<f32>() {
+                Ok(t) => {
+                    if (0.0_..=2.0).contains(&t) {
+                        api_interface.temperature = t;
+                        format!("New temperature: {t}\n")
+                    } else {
+                        "A float between 0 and 2\n".to_string()
+                    }
+                }
+                Err(err) => format!("Cannot make a float from {t} because: {err}\n"),
+            }
+        } else {
+            response_text = "No temperature".to_string();
+        }
+        Ok(response_text)
+    }
+
+    fn meta_pick(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Commit one of the choices for the last chat prompt.
+        // "! pick" shows them again
+        let mut meta = args.iter().copied();
+        let response_text = match (meta.next(), self.choices.take()) {
+            (_, None) => "No choices to pick from\n".to_string(),
+            (None, Some((prompt, choices))) => {
+                let text = candidates::side_by_side(&choices, self.heat_map, terminal_width());
+                self.choices = Some((prompt, choices));
+                format!("{text}\n")
+            }
+            (Some(n), Some((prompt, choices))) => match n.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= choices.len() => {
+                    api_interface.commit(
+                        prompt.as_str(),
+                        choices[n - 1].message.content.as_str(),
+                        self.model.as_str(),
+                    );
+                    format!("Carrying on with choice {n}\n")
+                }
+                _ => {
+                    let len = choices.len();
+                    self.choices = Some((prompt, choices));
+                    format!("Pick from 1 to {len}\n")
+                }
+            },
+        };
+        Ok(response_text)
+    }
+
+    fn meta_heat_map(
+        &mut self,
+        _args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Toggle the heat map of token probabilities
+        self.heat_map = !self.heat_map;
+        let response_text = if self.heat_map {
+            self.heat_map_logprobs = api_interface.chat_options.logprobs.is_none();
+            if self.heat_map_logprobs {
+                api_interface.chat_options.logprobs = Some(0);
+            }
+            format!("Heat map on\n{}\n", candidates::legend())
+        } else {
+            // Turn off the log probabilities the heat map turned on
+            if self.heat_map_logprobs && api_interface.chat_options.logprobs == Some(0) {
+                api_interface.chat_options.logprobs = None;
+            }
+            self.heat_map_logprobs = false;
+            "Heat map off\n".to_string()
+        };
+        Ok(response_text)
+    }
+
+    fn meta_sessions(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Sessions saved after every turn.  "open", "rename"
+        // and "delete" take an ID or a name
+        let response_text = match self.sessions_meta(args, api_interface) {
+            Ok(text) => text,
+            Err(err) => format!("{err}\n"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_stream(
+        &mut self,
+        _args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Toggle showing chat replies as they arrive
+        self.stream = !self.stream;
+        let response_text = match (self.stream, self.cache.is_some()) {
+            (true, true) => "Streaming on.  Streamed replies are not cached\n".to_string(),
+            (true, false) => "Streaming on\n".to_string(),
+            (false, _) => "Streaming off\n".to_string(),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_cache(
+        &mut self,
+        args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // The response cache.  "! cache clear" empties it
+        let mut meta = args.iter().copied();
+        let response_text = match (self.cache.as_ref(), meta.next()) {
+            (None, _) => "No cache.  Start with --cache\n".to_string(),
+            (Some(cache), Some("clear")) => match cache.clear() {
+                Ok(()) => "Cache cleared\n".to_string(),
+                Err(err) => format!("{err}: Failed to clear {}\n", cache.dir().display()),
+            },
+            (Some(cache), _) => match cache.size() {
+                Ok((entries, bytes)) => format!(
+                    "Cache {}: {entries} answers, {bytes} bytes\n",
+                    cache.dir().display()
+                ),
+                Err(err) => format!("{err}: Failed to read {}\n", cache.dir().display()),
+            },
+        };
+        Ok(response_text)
+    }
+
+    fn meta_budget(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // What has been spent, and the limits.  "! budget
+        // <period> [cents]" sets or removes a limit
+        let mut meta = args.iter().copied();
+        let response_text = match (api_interface.budget.as_mut(), meta.next()) {
+            (None, _) => {
+                "No budget.  Start with --budget-session, --budget-day or --budget-month\n"
+                    .to_string()
+            }
+            (Some(budget), None) => format!("{budget}\n"),
+            (Some(budget), Some(period)) => {
+                let limit = match period {
+                    "session" => Some(&mut budget.limits.session),
+                    "day" => Some(&mut budget.limits.day),
+                    "month" => Some(&mut budget.limits.month),
+                    _ => None,
+                };
+                match (limit, meta.next().map(str::parse::<f64>)) {
+                    (None, _) => {
+                        format!("Unknown budget period: {period}.  Use session, day or month\n")
+                    }
+                    (Some(_), Some(Err(err))) => format!("{err}: Not a number of cents\n"),
+                    (Some(limit), cents) => {
+                        *limit = cents.map(Result::unwrap);
+                        format!("{budget}\n")
+                    }
+                }
+            }
+        };
+        Ok(response_text)
+    }
+
+    fn meta_completion_options(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Completion options.  Like "! o"
+        let mut meta = args.iter().copied();
+        let response_text = match meta.next() {
+            None => format!("{}\n", api_interface.completion_options),
+            Some("clear") => {
+                api_interface.completion_options = CompletionOptions::default();
+                "Completion options cleared\n".to_string()
+            }
+            Some(name) => {
+                let value = meta.collect::<Vec<&str>>().join(" ");
+                let value = if value.is_empty() {
+                    None
+                } else {
+                    Some(value.as_str())
+                };
+                match api_interface.completion_options.set(name, value) {
+                    Ok(()) => format!("{}\n", api_interface.completion_options),
+                    Err(err) => format!("{err}\nOptions: {}\n", COMPLETION_OPTION_NAMES.join(", ")),
+                }
+            }
+        };
+        Ok(response_text)
+    }
+
+    fn meta_fill_in_middle(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Fill the gap marked in a file with a completion
+        // "! fim <path> [output]"
+        let mut meta = args.iter().copied();
+        let response_text = match meta.next() {
+            None => format!("! fim <path> [output]  Fill the {FILL_MARKER} in the file"),
+            Some(path) => {
+                let text = match fs::read_to_string(path) {
+                    Ok(text) => text,
+                    Err(err) => return Ok(format!("{err}: Failed to read {path}\n")),
+                };
+                let fim = match FillInMiddle::new(text.as_str(), FILL_MARKER) {
+                    Ok(fim) => fim,
+                    Err(err) => return Ok(format!("{err}: {path}\n")),
+                };
+                let model = if self.model_mode == ModelMode::Completions {
+                    self.model.clone()
+                } else {
+                    DEFAULT_COMPLETIONS_MODEL.to_string()
+                };
+                match api_interface.fill_in_middle(&fim, model.as_str()) {
+                    Ok(r) => match meta.next() {
+                        Some(output) => match fs::write(output, fim.fill(r.body.as_str())) {
+                            Ok(()) => format!("{}\nWrote {output}\n", r.body),
+                            Err(err) => format!("{}\n{err}: Failed to write {output}\n", r.body),
+                        },
+                        None => format!("{}\n", r.body),
+                    },
+                    Err(err) => format!("{err}: Failed fim {path}"),
+                }
+            }
+        };
+        Ok(response_text)
+    }
+
+    fn meta_chat_options(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Chat options.  "! o" lists them, "! o <name> <value>"
+        // sets one, "! o <name>" unsets it, "! o clear" unsets all
+        let mut meta = args.iter().copied();
+        let response_text = match meta.next() {
+            None => format!("{}\n", api_interface.chat_options),
+            Some("clear") => {
+                api_interface.chat_options = ChatOptions::default();
+                "Chat options cleared\n".to_string()
+            }
+            Some(name) => {
+                let value = meta.collect::<Vec<&str>>().join(" ");
+                let value = if value.is_empty() {
+                    None
+                } else {
+                    Some(value.as_str())
+                };
+                match api_interface.chat_options.set(name, value) {
+                    Ok(()) => format!("{}\n", api_interface.chat_options),
+                    Err(err) => format!("{err}\nOptions: {}\n", CHAT_OPTION_NAMES.join(", ")),
+                }
+            }
+        };
+        Ok(response_text)
+    }
+
+    fn meta_system_prompt_edit(
+        &mut self,
+        _args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Edit the system prompt in the editor
+        let response_text = if self.model_mode != ModelMode::Chat {
+            "This only makes sense in Chat mode".to_string()
+        } else {
+            match compose::edit(api_interface.context.purpose.as_str()) {
+                Ok(text) => {
+                    api_interface.context.purpose = text.trim().to_string();
+                    format!("System Prompt {}", api_interface.context.purpose)
+                }
+                Err(err) => format!("{err}"),
+            }
+        };
+        Ok(response_text)
+    }
+
+    fn meta_data_edit(
+        &mut self,
+        args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Edit a buffer for `{name}` in the editor.  Emptied,
+        // it is removed
+        let mut meta = args.iter().copied();
+        let response_text = match meta.next() {
+            Some(name) => {
+                let text = self.local_data.get(name).cloned().unwrap_or_default();
+                match compose::edit(text.as_str()) {
+                    Ok(text) if text.trim().is_empty() => {
+                        self.local_data.remove(name);
+                        format!("Removed {name}")
+                    }
+                    Ok(text) => {
+                        let len = text.len();
+                        self.local_data.insert(name.to_string(), text);
+                        format!("Loaded {name}: {len} bytes")
+                    }
+                    Err(err) => format!("{err}"),
+                }
+            }
+            None => "! efl <name>".to_string(),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_system_prompt(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        let response_text: String;
+        if self.model_mode != ModelMode::Chat {
+            response_text = "This only makes sense in Chat mode".to_string();
+        } else {
+            let system_prompt = args.join(" ");
+            if system_prompt.is_empty() {
+                if api_interface.context.purpose.is_empty() {
+                    response_text = "Provide a system prompt for the chat".to_string();
+                } else {
+                    response_text = format!("System Prompt {}", api_interface.context.purpose);
+                }
+            } else {
+                response_text = format!("System Prompt {system_prompt}");
+                api_interface.context.purpose = system_prompt;
+            }
+        }
+        Ok(response_text)
+    }
+
+    fn meta_image_clear(
+        &mut self,
+        _args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Clear `api_imterface.image` and api_interface.miage_focus_url`
+        self.image = None;
+        self.focus_image_url = None;
+
+        // If mode is ImageEdit set it to Image
+        if self.model_mode == ModelMode::ImageEdit {
+            //		self.api
+            Ok(format!("Image cleared. Mode: {}", self.model_mode))
+        } else {
+            Ok("Image cleared".to_string())
+        }
+    }
+
+    fn meta_audio(
+        &mut self,
+        args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        let response_text: String;
+        let file_name: String = args.join(" ");
+        if file_name.is_empty() {
+            response_text = format!(
+                "Enter an audio file to transcribe: {}",
+                current_dir()?.display()
+            );
+        } else if PathBuf::from(file_name.as_str()).exists() {
+            self.model_mode = ModelMode::AudioTranscription;
+            self.audio_file = Some(file_name.clone());
+            let _path = Path::new(file_name.as_str());
+            response_text = format!(
+                "Audio Transcription mode.  \
+                 File: {file_name}"
+            );
+        } else {
+            response_text = format!(
+                "{file_name} dose not exist.  Paths relative to {}",
+                current_dir()?.display()
+            );
+        }
+        Ok(response_text)
+    }
+
+    fn meta_mask(
+        &mut self,
+        args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Set a mask
+        let response_text: String;
+        let file_name: String = args.join(" ");
+        if file_name.is_empty() {
+            response_text = format!(
+                "Enter the mask file path relative to: {}",
+                current_dir()?.display()
+            );
+        } else if PathBuf::from(file_name.as_str()).exists() {
+            self.mask = Some(PathBuf::from(file_name));
+            response_text = format!("Mask set to: {:?}", self.mask.clone().unwrap());
+        } else {
+            response_text = format!(
+                "{file_name} dose not exist.  Paths relative to {}",
+                current_dir()?.display()
+            );
+        }
+        Ok(response_text)
+    }
+
+    fn meta_fine_tune_retrieve(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Retrieve tune info
+        let id: String = args.join(" ");
+        let response_text = match api_interface.fine_tune_retrieve(id.as_str()) {
+            Ok(res) => format!("{}", res.body),
+            Err(err) => format!("{err}: Failed ftr => fine_tune_retrieve {id}"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_fine_tune_list(
+        &mut self,
+        _args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // List fine tuning jobs
+        let response_text = match api_interface.fine_tune_list() {
+            Ok(vs) => format!("{}", vs.body),
+            Err(err) => format!("{err}: Failed ftl => fine_tune_list"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_fine_tune(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Start fine tuning a model: "! ft <file ID> [model] [key=value...]"
+        let mut meta = args.iter().copied();
+        let response_text: String;
+        match meta.next() {
+            Some(training_file) => {
+                let mut request = FineTuningJobRequest::new(training_file, DEFAULT_FINE_TUNE_MODEL);
+                let mut error: Option<String> = None;
+                for arg in meta {
+                    match arg.split_once('=') {
+                        Some((key, value)) => {
+                            if let Err(err) = request.set(key, value) {
+                                error = Some(err);
+                                break;
+                            }
+                        }
+                        None => request.model = arg.to_string(),
+                    }
+                }
+                response_text = match error {
+                    Some(err) => err,
+                    None => match api_interface.fine_tune_create(&request) {
+                        Ok(result) => format!("Fine tuning job created\n{}", result.body),
+                        Err(err) => format!("{err}: Failed fine_tune_create {training_file}"),
+                    },
+                };
+            }
+            None => {
+                response_text = "! ft <file ID> [model] [validation=<file ID>] [epochs=n] [batch=n] [lr=x] [suffix=s] [seed=n]".to_string()
+            }
+        };
+        Ok(response_text)
+    }
+
+    fn meta_fine_tune_export(
+        &mut self,
+        args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Make fine tuning data from saved conversations
+        // "! ftx <output> <path>... [model=m] [turns=n] [purpose=regex]"
+        let mut meta = args.iter().copied();
+        let mut filter = ExportFilter::default();
+        let mut paths: Vec<PathBuf> = vec![];
+        let mut error: Option<String> = None;
+        let output = meta.next().map(PathBuf::from);
+        for arg in meta {
+            match arg.split_once('=') {
+                Some(("model", m)) => filter.model = Some(m.to_string()),
+                Some(("turns", t)) => match t.parse::<usize>() {
+                    Ok(t) => filter.min_turns = t,
+                    Err(err) => error = Some(format!("{err}: turns={t}")),
+                },
+                Some(("purpose", p)) => match Regex::new(p) {
+                    Ok(re) => filter.purpose = Some(re),
+                    Err(err) => error = Some(format!("{err}: purpose={p}")),
+                },
+                _ => paths.push(PathBuf::from(arg)),
+            }
+        }
+        let response_text = match (output, error) {
+            (_, Some(err)) => err,
+            (Some(output), None) if !paths.is_empty() => {
+                match CliInterface::fine_tune_export(output.as_path(), &paths, &filter) {
+                    Ok(r) => r,
+                    Err(err) => format!("{err}: Failed to write {}", output.display()),
+                }
+            }
+            _ => "! ftx <output> <path>... [model=m] [turns=n] [purpose=regex]".to_string(),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_fine_tune_events(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // The events for a fine tuning job
+        let id: String = args.join(" ");
+        let response_text = match api_interface.fine_tune_events(id.as_str(), None) {
+            Ok(res) => res
+                .body
+                .data
+                .iter()
+                .rev()
+                .fold(String::new(), |a, b| format!("{a}{b}\n")),
+            Err(err) => format!("{err}: Failed fte => fine_tune_events {id}"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_fine_tune_cancel(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Cancel a fine tuning job
+        let id: String = args.join(" ");
+        let response_text = match api_interface.fine_tune_cancel(id.as_str()) {
+            Ok(res) => format!("{}", res.body),
+            Err(err) => format!("{err}: Failed ftc => fine_tune_cancel {id}"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_fine_tune_checkpoints(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // The checkpoints of a fine tuning job
+        let id: String = args.join(" ");
+        let response_text = match api_interface.fine_tune_checkpoints(id.as_str()) {
+            Ok(res) => res
+                .body
+                .data
+                .iter()
+                .fold(String::new(), |a, b| format!("{a}{b}\n")),
+            Err(err) => format!("{err}: Failed ftk => fine_tune_checkpoints {id}"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_fine_tune_delete(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Delete a fine tuned model
+        let model: String = args.join(" ");
+        let response_text = match api_interface.fine_tune_delete_model(model.as_str()) {
+            Ok(_) => format!("Deleted {model}"),
+            Err(err) => format!("{err}: Failed ftd => fine_tune_delete_model {model}"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_fine_tune_watch(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Watch a fine tuning job until it finishes.  Events
+        // are printed as they arrive
+        let id: String = args.join(" ");
+        let response_text = match api_interface.fine_tune_monitor(
+            id.as_str(),
+            Duration::from_secs(FINE_TUNE_POLL_SECONDS),
+            |event| println!("{event}"),
+        ) {
+            Ok(res) => format!("{}", res.body),
+            Err(err) => format!("{err}: Failed ftw => fine_tune_monitor {id}"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_batch(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Send every line of a file as a chat prompt in one
+        // batch, wait for it and write the results
+        // "! b <prompts file> <output file>"
+        let mut meta = args.iter().copied();
+        let prompts_file = meta.next();
+        let output = meta.next();
+        let response_text = match (prompts_file, output) {
+            (Some(prompts_file), Some(output)) => {
+                let prompts: Vec<String> = match fs::read_to_string(prompts_file) {
+                    Ok(prompts) => prompts
+                        .lines()
+                        .filter(|l| !l.trim().is_empty())
+                        .map(|l| l.to_string())
+                        .collect(),
+                    Err(err) => return Ok(format!("{err}: Failed to read {prompts_file}")),
+                };
+                let requests = api_interface.chat_batch_requests(&prompts, self.model.as_str());
+                // Kept so the results can be got with "! br" if they
+                // cannot be written
+                let mut batch_id = String::new();
+                match api_interface.batch_run(
+                    &requests,
+                    Duration::from_secs(BATCH_POLL_SECONDS),
+                    |batch| {
+                        println!("{}: {}", batch.id, batch.status);
+                        batch_id = batch.id.clone();
+                    },
+                ) {
+                    Ok(res) => match CliInterface::batch_write_results(
+                        Path::new(output),
+                        &res.body,
+                        Some(&prompts),
+                    ) {
+                        Ok(written) => written,
+                        Err(err) => format!(
+                            "{err}: Failed to write {output}.  \"! br {batch_id} <output file>\" writes the results"
+                        ),
+                    },
+                    Err(err) => format!("{err}: Failed b => batch_run {prompts_file}"),
+                }
+            }
+            _ => "! b <prompts file> <output file>".to_string(),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_batch_list(
+        &mut self,
+        _args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // List batches
+        let response_text = match api_interface.batch_list() {
+            Ok(res) => format!("{}", res.body),
+            Err(err) => format!("{err}: Failed bl => batch_list"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_batch_status(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // The status of a batch
+        let id: String = args.join(" ");
+        let response_text = match api_interface.batch_retrieve(id.as_str()) {
+            Ok(res) => format!("{}", res.body),
+            Err(err) => format!("{err}: Failed bs => batch_retrieve {id}"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_batch_cancel(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Cancel a batch
+        let id: String = args.join(" ");
+        let response_text = match api_interface.batch_cancel(id.as_str()) {
+            Ok(res) => format!("{}", res.body),
+            Err(err) => format!("{err}: Failed bc => batch_cancel {id}"),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_batch_results(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Write the results of a finished batch
+        // "! br <id> <output file>"
+        let mut meta = args.iter().copied();
+        let id = meta.next();
+        let output = meta.next();
+        let response_text = match (id, output) {
+            (Some(id), Some(output)) => match api_interface
+                .batch_retrieve(id)
+                .and_then(|batch| api_interface.batch_results(&batch.body))
+            {
+                Ok(res) => {
+                    match CliInterface::batch_write_results(Path::new(output), &res.body, None) {
+                        Ok(written) => written,
+                        Err(err) => format!("{err}: Failed to write {output}"),
+                    }
+                }
+                Err(err) => format!("{err}: Failed br => batch_results {id}"),
+            },
+            _ => "! br <id> <output file>".to_string(),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_data_load(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Load files, directories or web pages into a buffer to use
+        // as part of a prompt
+        let mut meta = args.iter().copied();
+        let response_text: String;
+        match meta.next() {
+            Some(name) => {
+                let mut options = IncludeOptions::default();
+                let mut sources: Vec<&str> = vec![];
+                while let Some(word) = meta.next() {
+                    match word {
+                        "--truncate" => options.overflow = Overflow::Truncate,
+                        "--max-tokens" => {
+                            options.max_tokens = match meta.next().map(|n| n.parse::<usize>()) {
+                                Some(Ok(n)) => n,
+                                _ => return Ok("--max-tokens <tokens>".to_string()),
+                            };
+                        }
+                        _ => sources.push(word),
+                    }
+                }
+                // One path with spaces in it
+                let joined = sources.join(" ");
+                if sources.len() > 1 && Path::new(joined.as_str()).exists() {
+                    sources = vec![joined.as_str()];
+                }
+                if sources.is_empty() {
+                    response_text = format!(
+                        "! fl <name> [--max-tokens <tokens>] [--truncate] <path | glob | url>...: \
+                         The text of files, directories and web pages is bound to the name \
+                         for use in prompts: {{name}} expands to it.  Paths are relative to: {}",
+                        current_dir()?.display()
+                    );
+                } else {
+                    match include(&sources, &options, api_interface.transport.as_ref()) {
+                        Ok(inclusion) => {
+                            response_text = format!("Loaded {name}: {inclusion}");
+                            // Associate the name and the contents
+                            _ = self.local_data.insert(name.to_string(), inclusion.text);
+                        }
+                        Err(err) => {
+                            response_text = format!(
+                                "{err}.  Did not load {name}.  Paths are relative to {}",
+                                current_dir()?.display()
+                            );
+                        }
+                    }
+                }
+            }
+            None => response_text = "Cannot get name".to_string(),
+        };
+        Ok(response_text)
+    }
+
+    fn meta_context_save(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        let file_path: String = args.join(" ");
+        // Save the context into the specified file
+        let context = api_interface.get_context()?;
+        let serialized_context = serde_json::to_string(&context)?;
+        let response_text = format!("Saved context to {}", file_path);
+        let mut file = File::create(file_path)?;
+        file.write_all(serialized_context.as_bytes())?;
+        Ok(response_text)
+    }
+
+    fn meta_context_restore(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        // Read the context from a file.
+        let response_text: String;
+        let file_path: String = args.join(" ");
+        if file_path.is_empty() {
+            response_text = format!(
+                "Enter the path of the file containing the context: {}",
+                current_dir()?.display()
+            );
+        } else if PathBuf::from(file_path.as_str()).exists() {
+            // Read the contents of the file.
+            let file_contents = fs::read_to_string(Path::new(&file_path))?;
+            // Deserialize the Vec<String> from the file contents.
+            let context: Context = serde_json::from_str(&file_contents)?;
+
+            // Set the context in the API interface.
+            api_interface.set_context(context);
 
-        // Ensure the image has an alpha channel
-        let img_rgba = img.into_rgba8();
+            response_text = "Context loaded from file.".to_string();
+        } else {
+            response_text = format!(
+                "{file_path} does not exist. Paths relative to {}",
+                current_dir()?.display()
+            );
+        }
+        Ok(response_text)
+    }
 
-        self.image = Some(incomming_image_file_path.as_path().to_owned());
-        img_rgba.save_with_format(self.image.clone().unwrap(), ImageFormat::Png)?;
-        webbrowser::open(self.image.clone().unwrap().as_os_str().to_str().unwrap())?;
+    /// "! e" is handled before meta commands are, as it makes a prompt
+    fn meta_compose(
+        &mut self,
+        _args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        Err("! e can only be typed".into())
+    }
 
-        Ok(())
+    fn meta_help(
+        &mut self,
+        _args: &[&str],
+        _api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        Ok(meta_command::help(&self.user_commands))
     }
 
-    fn set_up_read_line(&self) -> rustyline::Result<Editor<MyHelper, FileHistory>> {
-        let config = Config::builder()
-            .history_ignore_space(true)
-            .completion_type(CompletionType::List)
-            .edit_mode(EditMode::Emacs)
-            .build();
-        let h = MyHelper {
-            completer: FilenameCompleter::new(),
-            highlighter: MatchingBracketHighlighter::new(),
-            hinter: HistoryHinter {},
-            colored_prompt: "".to_owned(),
-            validator: MatchingBracketValidator::new(),
+    /// "! e [text]", and Alt-e, write the prompt in the editor,
+    /// starting with `text` or the last prompt.  Other input is left
+    /// as it is.  `None` if there is nothing to send
+    fn compose(&self, input: String) -> Option<String> {
+        let text = match input.trim().strip_prefix("! e") {
+            Some(text) if text.is_empty() || text.starts_with(char::is_whitespace) => text.trim(),
+            _ => return Some(input),
         };
-        let mut read_line = Editor::with_config(config)?;
-        read_line.set_helper(Some(h));
-        read_line.bind_sequence(KeyEvent::alt('n'), Cmd::HistorySearchForward);
-        read_line.bind_sequence(KeyEvent::alt('p'), Cmd::HistorySearchBackward);
-        if read_line.load_history(self.history_file.as_str()).is_err() {
-            println!("No previous history.");
+        let text = if text.is_empty() {
+            self.last_prompt.as_str()
+        } else {
+            text
+        };
+        match compose::edit(text) {
+            Ok(text) if text.trim().is_empty() => {
+                println!("Nothing to send");
+                None
+            }
+            Ok(text) => {
+                let text = text.trim_end().to_string();
+                println!("{text}");
+                Some(text)
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                None
+            }
         }
+    }
 
-        // Set control key C-q to quit.  Not really needed.  C-c does this
-        // auto-magically
-        read_line.bind_sequence(
-            Event::KeySeq(vec![KeyEvent::ctrl('q')]),
-            EventHandler::Simple(Cmd::Interrupt),
-        );
-        Ok(read_line)
+    /// Save the session, if there is anything in it
+    fn save_session(&mut self, api_interface: &ApiInterface) {
+        if api_interface.context.is_empty()
+            && self.local_data.is_empty()
+            && !self.sessions.exists(self.session.id.as_str())
+        {
+            return;
+        }
+        self.session.context = api_interface.context.clone();
+        self.session.model = self.model.clone();
+        self.session.mode = self.model_mode.to_string();
+        self.session.local_data = self.local_data.clone();
+        self.session.touch();
+        if let Err(err) = self.sessions.save(&self.session) {
+            eprintln!("{err}: Failed to save the session");
+        }
     }
 
-    fn expand_variables(&self, input: String) -> Result<String, Box<dyn Error>> {
-        let re = Regex::new(r"\{(\w+)\}").unwrap();
-        let result = re
-            .replace_all(&input, |caps: &regex::Captures| {
-                self.local_data
-                    .get(&caps[1])
-                    .unwrap_or(&caps[0].to_string())
-                    .to_string()
-            })
-            .to_string();
-        Ok(result)
+    /// Carry on with `session`.  Modes that need an image or audio
+    /// file go back to chat
+    fn resume(&mut self, session: Session, api_interface: &mut ApiInterface) -> String {
+        api_interface.set_context(session.context.clone());
+        self.model = session.model.clone();
+        self.model_mode = match ModelMode::from_str(session.mode.as_str()) {
+            Ok(mode @ (ModelMode::Chat | ModelMode::Completions | ModelMode::Image)) => mode,
+            _ => ModelMode::Chat,
+        };
+        self.local_data = session.local_data.clone();
+        self.choices = None;
+        let result = format!("Session {session}\n{}", api_interface.context.as_string());
+        self.session = session;
+        result
     }
 
-    /// Process prompts that are to effect or inspect the programme itself
-    /// `prommpt` is what the user entered after the initial "!"
-    fn process_meta(
+    /// "! sessions": list, open, rename or delete saved sessions
+    fn sessions_meta(
         &mut self,
-        prompt: &str,
+        args: &[&str],
         api_interface: &mut ApiInterface,
     ) -> Result<String, Box<dyn Error>> {
-        let mut meta = prompt.split_whitespace();
-        // The first word is: "!"
-        // The rest of the words are commands for the programme to interpret.
-
-        let response_text: String;
-        if let Some(cmd) = meta.nth(1) {
-            // Handle commands here
-            match cmd {
-                "f" => {
-                    // List files
-                    let vl = api_interface.files_list().unwrap();
-
-		    // Sort the returned list.  The OpenAI API returns
-		    // files in a random order
-		    let mut sorted_vec = vl.body;
-		    sorted_vec.sort();
-                    response_text = format!(
-                        ".....File ID...................Name{}",
-                        sorted_vec
-                            .iter()
-                            .fold(String::new(), |a, b| format!("{a}\n{}: {}", b.1, b.0))
-                    );
+        Ok(match args {
+            [] | ["list"] => {
+                let sessions = match self.sessions.list() {
+                    Ok(sessions) => sessions,
+                    Err(err) => return Ok(format!("{err}: Failed to list sessions\n")),
+                };
+                if sessions.is_empty() {
+                    "No sessions\n".to_string()
+                } else {
+                    sessions.iter().fold(String::new(), |a, s| {
+                        let current = if s.id == self.session.id { "*" } else { " " };
+                        format!("{a}{current} {s}\n")
+                    })
                 }
-                "fu" => {
-                    let file_name: String = meta.collect::<Vec<&str>>().join(" ");
-                    if file_name.is_empty() {
-                        response_text = format!(
-                            "Enter an audio file to transcribe: {}",
-                            current_dir()?.display()
-                        );
-                    } else if PathBuf::from(file_name.as_str()).exists() {
-                        response_text = match api_interface
-                            .files_upload_fine_tuning(Path::new(file_name.as_str()))
-                        {
-                            Ok(r) => r.body,
-                            Err(err) => format!("{err}: Failed to upload {file_name}"),
-                        };
-                    } else {
-                        response_text = format!(
-                            "{file_name} dose not exist.  Paths relative to {}",
-                            current_dir()?.display()
-                        );
+            }
+            ["open", key @ ..] if !key.is_empty() => {
+                let key = key.join(" ");
+                match self.sessions.find(key.as_str()) {
+                    Ok(session) => {
+                        self.save_session(api_interface);
+                        self.resume(session, api_interface)
                     }
+                    Err(err) => format!("{err}: Failed to open session {key}\n"),
                 }
-		"fi" => {
-		    // File info
-                    if let Some(file_id) =  meta.next() {
-			response_text = match api_interface.file_info(file_id.to_string()) {
-			    Ok(s) => s.body,
-			    Err(err) => format!("{err} Failed to delete"),
-			};
-		    }else{
-			response_text = "Enter a file ID".to_string();
-		    }
-		}
-		"fc" => {
-		    // File contents
-                    if let Some(file_id) =  meta.next() {
-			let local_file = meta.next();
-			response_text = match api_interface.file_contents(file_id.to_string()) {
-			    Ok(s) => if let Some(local_file) = local_file {
-				let mut file = File::create(local_file)?;
-				file.write_all(s.body.as_bytes())?;
-				"success".to_string()
-			    }else{
-				s.body
-			    },
-			    Err(err) => format!("{err} Failed to get contents"),
-			};
-		    }else{
-			response_text = "Enter a file ID".to_string();
-		    }
-		}
-		"fd" => {
-		    // Delete a file
-                    let file_id: String = meta.collect::<Vec<&str>>().join(" ");
-		    response_text = match api_interface.files_delete(file_id) {
-			Ok(_) => "Deleted".to_string(),
-			Err(err) => format!("{err} Failed to delete"),
-		    };
-		}
-                "p" => {
-                    response_text = format!(
-                        "OpenAI Interface: {api_interface}\n\
-			 Record File:{}\n\
-			 Model: {}\n\
-			 Model Mode: {}\n\
-			 Image: {:#?}\n\
-			 mask: {:#?}\n\
-			 audio file:{:#?}\n\
-			 Completions{}",
-                        // Display the parameters
-                        self.record_file,
-			self.model,
-			self.model_mode,
-			self.image,
-			self.mask,
-			self.audio_file,
-			self.local_data.keys().fold("".to_string(), |a, b| format!("{a}\n\t{b}")),
-                    );
-                }
-		"mm" => {
-		    // Display all the info we can get about models
-		    response_text = match api_interface.model_list() {
-			Ok(s) => s.body,
-			Err(err) => format!("{err}: Failed to get model list"),
-		    }
-		}
-                "md" => {
-                    // Display known models
-                    let mut model_list: Vec<&str> = self.model_mode.models_available();
-                    model_list.sort();
-                    response_text = format!(
-                        "Models for mode: {}: {}",
-                        self.model_mode,
-                        model_list
-                            .iter()
-                            .fold(String::new(), |a, b| format!("{a}\n\t{b}"))
-                    );
-                }
-                "ms" => {
-                    // Set a model
-                    if let Some(model_name) = meta.next() {
-                        response_text = format!("New model: {model_name}");
-                        self.model = model_name.to_string();
-                    } else {
-                        response_text = "No model".to_string();
-                    }
+            }
+            ["rename", key, name @ ..] if !name.is_empty() => {
+                let session = match self.sessions.rename(key, name.join(" ").as_str()) {
+                    Ok(session) => session,
+                    Err(err) => return Ok(format!("{err}: Failed to rename session {key}\n")),
+                };
+                if session.id == self.session.id {
+                    self.session.name = session.name.clone();
                 }
-                "ml" => {
-                    response_text = "Modes\n\t\
-				     completions\n\t\
-				     chat\n\t\
-				     image\n\t\
-				     image_edit\n\t\
-				     audio_transcription\n\t\
-				     "
-			.to_string()
+                format!("Renamed: {session}\n")
+            }
+            ["delete", key @ ..] if !key.is_empty() => {
+                let key = key.join(" ");
+                let session = match self.sessions.delete(key.as_str()) {
+                    Ok(session) => session,
+                    Err(err) => return Ok(format!("{err}: Failed to delete session {key}\n")),
+                };
+                if session.id == self.session.id {
+                    // Carry on, but not in the deleted session
+                    let mode = self.model_mode.to_string();
+                    self.session =
+                        Session::new(self.sessions.new_id(), self.model.as_str(), mode.as_str());
+                    api_interface.context.clear();
                 }
-                "m" => {
-                    // Set the mode (effectively the API endpoint at OpenAI
-                    match meta.next() {
-                        // "! m" on its own to get a list of models
-                        // "! m <model name>" to change it
-                        Some(mode) => match mode {
-                            "completions" => {
-                                response_text = "Model mode => Completions\n".to_string();
-                                self.model_mode = ModelMode::Completions;
-                            }
-                            "chat" => {
-                                // A conversation with the LLM. `system_prompt` sets
-                                // the tone of the conversation.  It can be over
-                                // ridden here, and there must be some prompt
-                                let purpose = meta.collect::<Vec<&str>>().join(" ");
-                                if purpose.is_empty()
-                                    && api_interface.context.purpose.is_empty()
-                                {
-                                    response_text =
-                                        "Provide a system prompt for the chat".to_string();
-                                } else {
-                                    self.model_mode = ModelMode::Chat;
-                                    response_text = "Model mode => Chat\n".to_string();
-                                    if !purpose.is_empty() {
-                                        api_interface.context.purpose = purpose;
-                                    }
-                                }
-                            }
-                            "image" => {
-                                // Create images from prompts.  If a file is passed in
-                                // it is an image to edit, so the mode is set to
-                                // `ImageEdit`
-                                let file_name: String = meta.collect::<Vec<&str>>().join(" ");
-                                if file_name.is_empty() {
-                                    // User is going to get AI to generate the image
-                                    self.model_mode = ModelMode::Image;
-                                    response_text = "Model mode => Image\n".to_string();
-                                } else {
-                                    // User is supplying an image
-                                    if PathBuf::from(file_name.as_str()).exists() {
-                                        self.image = Some(PathBuf::from(file_name));
-                                        self.model_mode = ModelMode::ImageEdit;
-                                        response_text = "Model mode => ImageEdit\n".to_string();
-                                    } else {
-                                        self.model_mode = ModelMode::Image;
-                                        response_text =
-                                            "File: {file_name} does not exist.  Model mode => Image\n"
-                                            .to_string();
-                                    }
-                                }
-                            }
-                            "image_edit" => {
-                                // Edit an image.
-                                match self.model_mode {
-                                    ModelMode::Image => {
-                                        if self.image.is_none() && self.focus_image_url.is_none() {
-                                            response_text = format!(
-                                                "Cannot switch to ImageEdit mode \
-						 from {} mode until you have created \
-						 an image.  Enter a prompt to create an image",
-                                                self.model_mode
-                                            );
-                                        } else if self.mask.is_none() {
-                                            response_text = format!(
-                                                "Cannot switch to ImageEdit mode \
-						 from {} mode until you have created \
-						 a mask.",
-                                                self.model_mode
-                                            );
-                                        } else {
-                                            response_text = "Edit image".to_string();
-                                            self.model_mode = ModelMode::ImageEdit;
-                                        }
-                                    }
-                                    _ => {
-                                        response_text = format!("Cannot switch to ImageEdit mode from {} mode.  Must be in Image mode", self.model_mode);
-                                    }
-                                };
-                            }
-                            "audio_transcription" => {
-                                if self.audio_file.is_none() {
-                                    response_text = "Add an audio file before switching to audio_transcription mode".to_string();
-                                } else {
-                                    self.model_mode = ModelMode::AudioTranscription;
-                                    response_text = "Audio Transcription mode".to_string();
-                                }
-                            }
-                            _ => response_text = format!("{mode} not a Model Mode\n"),
-                        },
-                        None => {
-                            response_text = "Model modes\n\
-					     completions\n\
-					     chat\n\
-					     image\n\
-					     image_edit\n\
-					     audio_transcription\n"
-                                .to_string()
+                format!("Deleted: {session}\n")
+            }
+            _ => "! sessions [list | open <id> | rename <id> <name> | delete <id>]\n".to_string(),
+        })
+    }
+
+    /// `! run <path>`
+    fn meta_run(
+        &mut self,
+        args: &[&str],
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        let path = args.join(" ");
+        if path.is_empty() {
+            return Ok("! run <script>".to_string());
+        }
+        Ok(
+            match self.run_script(Path::new(path.as_str()), api_interface) {
+                Ok(report) => format!("{report}\n"),
+                Err(err) => format!("{err}: Failed to run {path}\n"),
+            },
+        )
+    }
+
+    /// Run the script at `path`, printing each reply and check as it
+    /// goes.  A prompt or meta command that fails stops it.  Errors
+    /// are for scripts that cannot be read or parsed
+    fn run_script(
+        &mut self,
+        path: &Path,
+        api_interface: &mut ApiInterface,
+    ) -> Result<ScriptReport, Box<dyn Error>> {
+        if self.in_script {
+            return Err("Scripts cannot run scripts".into());
+        }
+        let lines = read_script(path)?;
+        self.in_script = true;
+        let report = self.run_script_lines(lines, api_interface);
+        self.in_script = false;
+        Ok(report)
+    }
+
+    fn run_script_lines(
+        &mut self,
+        lines: Vec<ScriptLine>,
+        api_interface: &mut ApiInterface,
+    ) -> ScriptReport {
+        let mut report = ScriptReport::default();
+        // The last reply, and what it cost if that is known
+        let mut reply: Option<(String, Option<f64>)> = None;
+        for ScriptLine { line, step } in lines {
+            match step {
+                Step::Meta(meta) => {
+                    match self.process_meta(format!("! {meta}").as_str(), api_interface) {
+                        Ok(output) => println!("! {meta}\n{}", output.trim_end()),
+                        Err(err) => {
+                            Self::script_failure(&mut report, line, format!("! {meta}: {err}"));
+                            break;
                         }
                     }
                 }
-                "dx" => {
-                    response_text = api_interface.context.as_string();
-                }
-                "cx" => {
-                    response_text = "Clear context".to_string();
-                    api_interface.clear_context();
-                }
-		"ppx" => {
-		    // Print out the conversation to the passed path
-		    // in a human readable form
-			    
-		    let file_path: String = meta.collect::<Vec<&str>>().join(" ");
-		    response_text = match File::create(file_path.clone()) {
-			Ok(mut f) => {
-			    // Created file
-			    // Save the context into the specified file
-			    let context: Context = api_interface.get_context()?;
-			    // `context` has query/response pairs.  So has an even length
-			    assert!(context.len() % 2 == 0);
-			    let context = CliInterface::pretty_print_conversation(context)?;
-			    f.write_all(context.as_bytes())?;
-			    format!("Wrote context to {file_path}")
-			}
-		    
-			Err(err) => {
-			    // Failed to create file
-			    format!("{err}: Failed to open file at: {file_path}")
-			}
-
-		    };
-		}
-		
-                "v" => {
-                    // set verbosity
-                    if let Some(v) = meta.next() {
-                        response_text = match v.parse::<usize>() {
-                            Ok(v) => {
-                                self.verbose = v;
-                                format!("Verbosity set to {v}\n")
-                            }
-                            Err(err) => format!("Cannot make a usize from {v} because: {err}\n"),
+                Step::Prompt(prompt) => {
+                    let prompt = match self.expand_variables(prompt) {
+                        Ok(prompt) => prompt,
+                        Err(err) => {
+                            Self::script_failure(&mut report, line, err.to_string());
+                            break;
                         }
-                    } else {
-                        response_text = "No verbosity level passed".to_string();
-                    }
-                }
-                "k" => {
-                    // set tokens
-                    if let Some(t) = meta.next() {
-                        response_text = match t.parse::<u32>() {
-                            Ok(t) => {
-                                api_interface.tokens = t;
-                                format!("New tokens: {t}\n")
-                            }
-                            Err(err) => format!("Cannot make a float from {t} because: {err}\n"),
-                        };
-                    } else {
-                        response_text = "No tokens".to_string();
-                    }
-                }
-                "t" => {
-                    // set temperature
-                    if let Some(t) = meta.next() {
-                        response_text = match t.parse::<f32>() {
-                            Ok(t) => {
-                                if (0.0_..=2.0).contains(&t) {
-                                    api_interface.temperature = t;
-                                    format!("New temperature: {t}\n")
-                                } else {
-                                    "A float between 0 and 2\n".to_string()
+                    };
+                    println!("> {prompt}");
+                    let result = match self.model_mode {
+                        ModelMode::Chat => api_interface.chat(prompt.as_str(), self.model.as_str()),
+                        ModelMode::Completions => {
+                            api_interface.completion(prompt.as_str(), self.model.as_str())
+                        }
+                        ref mode => Err(format!(
+                            "Scripts send chat and completions prompts, not {mode}"
+                        )
+                        .into()),
+                    };
+                    match result {
+                        Ok(result) => {
+                            let cost: Option<f64> =
+                                result.headers.get("Cost").and_then(|c| c.parse().ok());
+                            if let Some(cost) = cost {
+                                self.cost = cost;
+                                report.cost += cost;
+                                if let Err(err) = SharedState::read_write_atomic(move |mut ss| {
+                                    ss.spent += cost;
+                                    ss
+                                }) {
+                                    eprintln!("{err}: Failed to update costs");
                                 }
                             }
-                            Err(err) => format!("Cannot make a float from {t} because: {err}\n"),
+                            println!("{}", result.body.trim_end());
+                            reply = Some((result.body, cost));
                         }
-                    } else {
-                        response_text = "No temperature".to_string();
-                    }
-                }
-                "sp" => {
-                    if self.model_mode != ModelMode::Chat {
-                        response_text = "This only makes sense in Chat mode".to_string();
-                    } else {
-                        let system_prompt = meta.collect::<Vec<&str>>().join(" ");
-                        if system_prompt.is_empty() {
-                            if api_interface.context.purpose.is_empty() {
-                                response_text = "Provide a system prompt for the chat".to_string();
-                            } else {
-                                response_text =
-                                    format!("System Prompt {}", api_interface.context.purpose);
-                            }
-                        } else {
-                            response_text = format!("System Prompt {system_prompt}");
-                            api_interface.context.purpose = system_prompt;
+                        Err(err) => {
+                            Self::script_failure(&mut report, line, err.to_string());
+                            break;
                         }
                     }
                 }
-                "ci" => {
-                    // Clear `api_imterface.image` and api_interface.miage_focus_url`
-                    self.image = None;
-                    self.focus_image_url = None;
-
-                    // If mode is ImageEdit set it to Image
-                    if self.model_mode == ModelMode::ImageEdit {
-                        //		self.api
-                        response_text = format!("Image cleared. Mode: {}", self.model_mode);
-                    } else {
-                        response_text = "Image cleared".to_string();
-                    }
-                }
-                "a" => {
-                    let file_name: String = meta.collect::<Vec<&str>>().join(" ");
-                    if file_name.is_empty() {
-                        response_text = format!(
-                            "Enter an audio file to transcribe: {}",
-                            current_dir()?.display()
-                        );
-                    } else if PathBuf::from(file_name.as_str()).exists() {
-                        self.model_mode = ModelMode::AudioTranscription;
-                        self.audio_file = Some(file_name.clone());
-                        let _path = Path::new(file_name.as_str());
-                        response_text = format!(
-                            "Audio Transcription mode.  \
-			     File: {file_name}"
-                        );
-                    } else {
-                        response_text = format!(
-                            "{file_name} dose not exist.  Paths relative to {}",
-                            current_dir()?.display()
-                        );
-                    }
-                }
-                "mask" => {
-                    // Set a mask
-                    let file_name: String = meta.collect::<Vec<&str>>().join(" ");
-                    if file_name.is_empty() {
-                        response_text = format!(
-                            "Enter the mask file path relative to: {}",
-                            current_dir()?.display()
-                        );
-                    } else if PathBuf::from(file_name.as_str()).exists() {
-                        self.mask = Some(PathBuf::from(file_name));
-                        response_text = format!("Mask set to: {:?}", self.mask.clone().unwrap());
-                    } else {
-                        response_text = format!(
-                            "{file_name} dose not exist.  Paths relative to {}",
-                            current_dir()?.display()
-                        );
+                Step::Capture(name, capture) => {
+                    let captured = match reply {
+                        Some((ref reply, _)) => capture.capture(reply.as_str()),
+                        None => Err("No reply to capture from".to_string()),
+                    };
+                    match captured {
+                        Ok(value) => {
+                            println!("{{{name}}} = {value}");
+                            self.local_data.insert(name, value);
+                        }
+                        Err(err) => Self::script_failure(
+                            &mut report,
+                            line,
+                            format!("@capture {name}: {err}"),
+                        ),
                     }
                 }
-		"ftr" => {
-		    // Retrieve tune info
-                    let id: String = meta.collect::<Vec<&str>>().join(" ");
-		    response_text = match api_interface.fine_tune_retrieve(id.as_str()) {
-			Ok(res) => res.body,
-			Err(err) => format!("{err}: Failed ftr => fine_tune_retrieve {id}"),			
-		    }
-		}
-		"ftl" => {
-		    // List fine tune models
-		    response_text = match api_interface.fine_tune_list() {
-			Ok(vs) => vs.body,
-			Err(err) => format!("{err}: Failed ftl => fine_tune_list"),
-		    };
-		}
-		"ft" => {
-		    // Start fine tuning a model
-                    match meta.next() {
-                        Some(name) => {
-			    response_text = match api_interface.fine_tune_create(name.to_string()) {
-				Ok(result) => result.body,
-				Err(err) => format!("{err}: Failed fine_tune_create{name}"),
-			    };
-			},
-                        None => {
-                            response_text = "Cannot get name".to_string()
-                        }
+                Step::Assert(assertion) => {
+                    report.assertions += 1;
+                    let checked = match reply {
+                        Some((ref reply, cost)) => assertion.check(reply.as_str(), cost),
+                        None => Err("No reply to check".to_string()),
                     };
-		}
-			
-			    
-		    
-                "fl" => {
-                    // Load a file's contents into a buffer to use as
-                    // part of a prompt
-                    match meta.next() {
-                        Some(name) => {
-			    let file_name: String = meta.collect::<Vec<&str>>().join(" ");
-			    if file_name.is_empty() {
-				response_text = format!(
-				    "! fl <name> <file path>: The contents of a file\
-				     is bound to the name for use in prompts: {{name}}\
-				     expands to file content.  The path is relative to:\
-				     {}",
-				    current_dir()?.display()
-				);
-			    } else if !PathBuf::from(file_name.as_str()).exists() {
-				response_text = format!(
-				    "{file_name} does not exist.  Paths relative to {}",
-				    current_dir()?.display()
-				);
-			    } else {
-				// Got the name and the file
-
-				// Get file contents
-				let contents = fs::read_to_string(Path::new(&file_name))?;
-				// Associate the name and the contents
-				_ = self.local_data.insert(name.to_string(), contents);
-				response_text = format!("Loaded {name}");
-
-			    }
-			},
-                        None => {
-                            response_text = "Cannot get name".to_string()
+                    match checked {
+                        Ok(()) => println!("PASS line {line}: {assertion}"),
+                        Err(err) => {
+                            Self::script_failure(&mut report, line, format!("{assertion}: {err}"))
                         }
-                    };
-
-                }
-		"sx" => {
-		    let file_path: String = meta.collect::<Vec<&str>>().join(" ");
-		    // Save the context into the specified file
-		    let context = api_interface.get_context()?;
-		    let serialized_context = serde_json::to_string(&context)?;
-		    response_text = format!("Saved context to {}", file_path);
-		    let mut file = File::create(file_path)?;
-		    file.write_all(serialized_context.as_bytes())?;
-
-		}
-		"rx" => {
-		    // Read the context from a file.
-		    let file_path: String = meta.collect::<Vec<&str>>().join(" ");
-		    if file_path.is_empty() {
-			response_text = format!(
-			    "Enter the path of the file containing the context: {}",
-			    current_dir()?.display()
-			);
-		    } else if PathBuf::from(file_path.as_str()).exists() {
-			// Read the contents of the file.
-			let file_contents = fs::read_to_string(Path::new(&file_path))?;
-			// Deserialize the Vec<String> from the file contents.
-			let context: Context = serde_json::from_str(&file_contents)?;
-
-			// Set the context in the API interface.
-			api_interface.set_context(context);
-
-			response_text = "Context loaded from file.".to_string();
-		    } else {
-			response_text = format!(
-			    "{file_path} does not exist. Paths relative to {}",
-			    current_dir()?.display()
-			);
-		    }
-		}
-                "?" => {
-                    response_text = "\
-		    p  Display settings\n\
-		    mm Display all available information about all models\n\
-		    md Display all available models for the current mode\n\
-		    ms <model> Change the current model\n\
-		    ml List modes\
-		    m  <mode> Change mode (API endpoint\n\
-		    dx Display context (for chat)\n\
-		    cx Clear context\n\
-		    ppx <path> Pretty print conversation to path\n\
-		    v  Set verbosity\n\
-		    k  Set max tokens for completions\n\
-		    t  Set temperature for completions\n\
-		    sp Set system prompt (after `! cc`\n\
-		    ci Clear image\
-		    mask <path> Set the mask to use in image edit mode.  A 1024x1024 PNG with transparent mask\n\
-		    a <path> Audio file for transcription\n\
-		    ci Clear the image stored for editing\n\
-		    f List the files stored on the server\n\
-		    fu <path> Upload a file of fine tuning data\n\
-		    fd <file id> Delete a file\n\
-		    fi <file id> Get information about file\n\
-		    fc <file id> [destination_file] Get contents of file\n\
-		    ft <file ID> Start a fine tune model using a JASONL training file \n\
-		    ftl List fine tunes\n\
-		    ftr <id> Retrieve the info for a fine tune\n\
-		    fl <name> <path>  Associate the contents of the `path` with `name` for use in prompts like: {{name}}\n\
-		    sx <path>  Save the context to a file at the specified path\n\
-		    rx <path>  Restore the context from a file at the specified path\n\
- 		    ?  This text\n"
-                        .to_string()
+                    }
                 }
-                _ => response_text = format!("Unknown command: {cmd}\n"),
-            };
+            }
+        }
+        report
+    }
+
+    /// Show a failure where it happens in the run.  The report only
+    /// counts it
+    fn script_failure(report: &mut ScriptReport, line: usize, why: String) {
+        println!("FAIL line {line}: {why}");
+        report.failures.push((line, why));
+    }
+
+    /// If chat replies are shown as they arrive.  Several choices and
+    /// the heat map need the whole reply
+    fn streaming(&self, api_interface: &ApiInterface) -> bool {
+        self.stream && !self.heat_map && api_interface.chat_options.n.unwrap_or(1) <= 1
+    }
+
+    /// Send a chat `prompt` and show the reply, rendered, as it
+    /// arrives.  Returns the costs and headers, then the reply
+    fn stream_chat(
+        &mut self,
+        prompt: &str,
+        api_interface: &mut ApiInterface,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut stdout = std::io::stdout();
+        let mut markdown = MarkdownStream::new(terminal_width(), stdout.is_terminal());
+        let result = api_interface.chat_stream(prompt, self.model.as_str(), &mut |token| {
+            _ = write!(stdout, "{}", markdown.push(token));
+            _ = stdout.flush();
+        });
+        write!(stdout, "{}", markdown.finish())?;
+        let result = result?;
+        self.cost = result.headers.get("Cost").unwrap().parse::<f64>()?;
+        let cost = self.cost;
+        let ss = SharedState::read_write_atomic(move |mut ss| {
+            ss.spent += cost;
+            ss
+        })?;
+        let headers = self.after_request(result.headers)?;
+        Ok(format!(
+            "{cost:.2}/{:.2}/{:.2}:{}{headers}\n{}",
+            api_interface.context.cost,
+            ss.spent,
+            api_interface.context.len(),
+            result.body,
+        ))
+    }
+
+    /// Show the choices for a chat `prompt`.  One choice is committed
+    /// to the conversation.  Several are shown side by side and kept
+    /// for "! pick"
+    fn show_choices(
+        &mut self,
+        prompt: &str,
+        choices: Vec<ChatChoice>,
+        api_interface: &mut ApiInterface,
+    ) -> String {
+        if choices.is_empty() {
+            return "No choices in the response".to_string();
+        }
+        if choices.len() == 1 {
+            let choice = &choices[0];
+            api_interface.commit(prompt, choice.message.content.as_str(), self.model.as_str());
+            if self.heat_map && !choice.tokens().is_empty() {
+                format!("{}\n{}", candidates::heat_map(choice), candidates::legend())
+            } else {
+                choice.message.content.clone()
+            }
         } else {
-            response_text = "Enter a meta command".to_string();
+            let result = format!(
+                "{}\n{}! pick <n> to carry on with one",
+                candidates::side_by_side(&choices, self.heat_map, terminal_width()),
+                if self.heat_map {
+                    format!("{}\n", candidates::legend())
+                } else {
+                    "".to_string()
+                },
+            );
+            self.choices = Some((prompt.to_string(), choices));
+            result
         }
-        Ok(response_text)
     }
 
     /// Data about the request before it goes out.  Cach headers, only
@@ -786,7 +2225,8 @@ impl CliInterface {
                     .insert(k.clone(), response_headers.get(k).unwrap().clone());
                 result += &format!("{k}: {}\n", response_headers[k]);
             }
-        } else {
+        } else if let Some(warning) = response_headers.get(BUDGET_WARNING_HEADER) {
+            result += &format!("{BUDGET_WARNING_HEADER}: {warning}\n");
         }
         Ok(result)
     }
@@ -795,42 +2235,253 @@ impl CliInterface {
         let saved_context = context.as_string();
         Ok(saved_context)
     }
+
+    /// Write saved conversations as fine tuning data, then check
+    /// the data written
+    fn fine_tune_export(
+        output: &Path,
+        paths: &[PathBuf],
+        filter: &ExportFilter,
+    ) -> Result<String, Box<dyn Error>> {
+        let report = export(paths, filter, output)?;
+        let validation = validate_file(output, DEFAULT_FINE_TUNE_MODEL, None)?;
+        Ok(format!("{report}Wrote {}\n{validation}", output.display()))
+    }
+
+    /// Every model with its capabilities.  The list is cached in the
+    /// cache directory
+    fn available_models(
+        api_interface: &ApiInterface,
+        ttl: Duration,
+    ) -> Result<Vec<ModelEntry>, Box<dyn Error>> {
+        let project_dir = ProjectDirs::from("worik", "org", "llm-rs").unwrap();
+        available_models(
+            api_interface,
+            &project_dir.cache_dir().join(MODEL_CACHE_FILE),
+            ttl,
+            &api_interface.model_registry,
+        )
+    }
+
+    /// Write the results of a batch as JSONL, one line per request
+    /// in the order of their custom IDs: `{"custom_id", "prompt",
+    /// "response"}` or `{"custom_id", "prompt", "error"}`.  The prompt
+    /// is only there if `prompts` is known
+    fn batch_write_results(
+        output: &Path,
+        lines: &[BatchOutputLine],
+        prompts: Option<&[String]>,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut lines: Vec<&BatchOutputLine> = lines.iter().collect();
+        lines.sort_by_key(|l| {
+            (
+                l.custom_id.parse::<usize>().unwrap_or(usize::MAX),
+                l.custom_id.clone(),
+            )
+        });
+        let mut file = File::create(output)?;
+        let mut failed = 0;
+        let mut tokens = 0;
+        for line in lines.iter() {
+            let mut result = serde_json::json!({ "custom_id": line.custom_id });
+            if let Some(prompt) =
+                prompts.and_then(|p| line.custom_id.parse::<usize>().ok().and_then(|i| p.get(i)))
+            {
+                result["prompt"] = serde_json::json!(prompt);
+            }
+            match line.chat_response() {
+                Ok(response) => {
+                    tokens += response.usage.total_tokens;
+                    result["response"] = serde_json::json!(response.choices[0].message.content);
+                }
+                Err(err) => {
+                    failed += 1;
+                    result["error"] = serde_json::json!(err);
+                }
+            }
+            writeln!(file, "{result}")?;
+        }
+        Ok(format!(
+            "Wrote {} results ({failed} failed, {tokens} tokens) to {}",
+            lines.len(),
+            output.display()
+        ))
+    }
+}
+
+/// How many columns the terminal has, from `COLUMNS` or the
+/// terminal.  120 if neither says
+fn terminal_width() -> usize {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .or_else(|| terminal_size::terminal_size().map(|(Width(w), _)| w as usize))
+        .unwrap_or(120)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     // Get the command line options
-    let cmd_line_opts = Arguments::parse();
+    let mut cmd_line_opts = Arguments::parse();
+
+    // Commands that do not need the interactive interface
+    let mut one_shot: Option<OneShot> = None;
+    if let Some(command) = cmd_line_opts.command.take() {
+        match command {
+            Command::FineTuneExport {
+                output,
+                paths,
+                model,
+                min_turns,
+                purpose,
+            } => {
+                let filter = ExportFilter {
+                    model,
+                    min_turns,
+                    purpose: match purpose {
+                        Some(p) => Some(Regex::new(p.as_str())?),
+                        None => None,
+                    },
+                };
+                println!(
+                    "{}",
+                    CliInterface::fine_tune_export(output.as_path(), &paths, &filter)?
+                );
+                return Ok(());
+            }
+            Command::Ask(ask) => one_shot = Some(ask),
+            Command::MetaCommands => {
+                print!("{}", meta_command::markdown_table());
+                return Ok(());
+            }
+        }
+    }
+    // Errors exit with `EXIT_USAGE_ERROR` instead of panicking
+    let non_interactive = one_shot.is_some() || cmd_line_opts.script.is_some();
+
+    // Settings not given on the command line come from a profile in
+    // the config file, if there is one
+    let project_dir = ProjectDirs::from("worik", "org", "llm-rs").unwrap();
+    let (profile, user_commands) =
+        match ConfigFile::read(&project_dir.config_dir().join(CONFIG_FILE)).and_then(|config| {
+            check_user_commands(&config.commands)?;
+            Ok((config.profile(cmd_line_opts.profile.as_deref())?, config.commands))
+        }) {
+        Ok(config) => config,
+        Err(err) if non_interactive => usage_error(err.to_string().as_str()),
+        Err(err) => return Err(err),
+    };
+    let mut settings = Settings::new(profile);
 
     // API key.  Stored in openai_interface
     let _key_binding: String;
     let api_key = match cmd_line_opts.api_key.as_deref() {
         Some(key) => key,
         None => {
-            _key_binding = env::var("OPENAI_API_KEY").unwrap();
+            // Replaying a cassette needs no key
+            _key_binding = match env::var("OPENAI_API_KEY") {
+                Ok(key) => key,
+                Err(_) if cmd_line_opts.replay.is_some() => String::new(),
+                Err(err) if non_interactive => {
+                    usage_error(format!("{err}: OPENAI_API_KEY").as_str())
+                }
+                Err(err) => return Err(Box::new(err)),
+            };
             _key_binding.as_str()
         }
     };
 
     // The model.  Stored in openai_interface
-    let model = cmd_line_opts.model.as_str();
+    let model = settings.value(
+        "model",
+        cmd_line_opts.model.take(),
+        |p| p.model.clone(),
+        DEFAULT_MODEL.to_string(),
+    );
+    let model = model.as_str();
 
     // Maximum tokens.  Stored in openai_interface.  TODO: Store in CliInterface?
-    let tokens: u32 = cmd_line_opts.max_tokens;
+    let tokens: u32 = settings.value(
+        "max_tokens",
+        cmd_line_opts.max_tokens,
+        |p| p.max_tokens,
+        DEFAULT_TOKENS,
+    );
 
     // .  Stored in openai_interface
-    let temperature: f32 = cmd_line_opts.temperature;
+    let temperature: f32 = settings.value(
+        "temperature",
+        cmd_line_opts.temperature,
+        |p| p.temperature,
+        DEFAULT_TEMPERATURE,
+    );
 
     // The mode.  Stored in openai_interface.  TODO Should be stored
     // in CliInterface
-    let mode: ModelMode = match ModelMode::from_str(cmd_line_opts.mode.as_str()) {
+    let mode = settings.value(
+        "mode",
+        cmd_line_opts.mode.take(),
+        |p| p.mode.clone(),
+        DEFAULT_MODE.to_string(),
+    );
+    let mode: ModelMode = match ModelMode::from_str(mode.as_str()) {
         Ok(m) => m,
-        Err(_) => panic!("{} is an invalid mode", cmd_line_opts.mode.as_str()),
+        Err(_) if non_interactive => {
+            usage_error(format!("{mode} is an invalid mode").as_str())
+        }
+        Err(_) => panic!("{mode} is an invalid mode"),
     };
-
+    let api_url = settings.value(
+        "provider",
+        cmd_line_opts.api_url.take(),
+        |p| p.api_url(),
+        API_URL.to_string(),
+    );
+    let system_prompt = settings.option(
+        "system_prompt",
+        cmd_line_opts.system_prompt.take(),
+        |p| p.system_prompt.clone(),
+        None,
+    );
+    let limits = BudgetLimits {
+        session: settings.option(
+            "budget_session",
+            cmd_line_opts.budget_session,
+            |p| p.budget_session,
+            None,
+        ),
+        day: settings.option("budget_day", cmd_line_opts.budget_day, |p| p.budget_day, None),
+        month: settings.option(
+            "budget_month",
+            cmd_line_opts.budget_month,
+            |p| p.budget_month,
+            None,
+        ),
+        warn_at: settings.value(
+            "budget_warn",
+            cmd_line_opts.budget_warn,
+            |p| p.budget_warn,
+            DEFAULT_WARN_AT,
+        ),
+    };
+    let record_file = settings.value(
+        "record_file",
+        cmd_line_opts.record_file.take(),
+        |p| p.record_file.clone(),
+        DEFAULT_RECORD_FILE.to_string(),
+    );
+    let history_file = settings.value(
+        "history_file",
+        None,
+        |p| p.history_file.clone(),
+        DEFAULT_HISTORY_FILE.to_string(),
+    );
+    let verbose = settings.value("verbose", None, |p| p.verbose, 0);
+
+    let sessions = SessionStore::new(&project_dir.data_dir().join(SESSIONS_DIR));
     let mut cli_interface = CliInterface {
         record_file: DEFAULT_RECORD_FILE.to_string(),
-        history_file: DEFAULT_HISTORY_FILE.to_string(),
-        verbose: 0,
+        history_file,
+        verbose,
         audio_file: None,
         model: model.to_string(),
         model_mode: mode.clone(),
@@ -840,9 +2491,108 @@ fn main() -> Result<(), Box<dyn Error>> {
         header_cache: HashMap::new(),
         cost: 0.0,
         local_data: HashMap::new(),
+        heat_map: false,
+        heat_map_logprobs: false,
+        choices: None,
+        cache: None,
+        stream: !cmd_line_opts.no_stream,
+        settings,
+        session: Session::new(sessions.new_id(), model, mode.to_string().as_str()),
+        sessions,
+        last_prompt: String::new(),
+        compose_key: Arc::new(Mutex::new(None)),
+        model_ids: ModelCache::load(&project_dir.cache_dir().join(MODEL_CACHE_FILE))
+            .map(|cache| cache.models.into_iter().map(|m| m.id).collect())
+            .unwrap_or_default(),
+        file_ids: vec![],
+        user_commands,
+        in_script: false,
     };
+    // Flags that cannot be used are usage errors too
+    let set_up_error = |err: Box<dyn Error>| -> Box<dyn Error> {
+        if non_interactive {
+            usage_error(err.to_string().as_str())
+        }
+        err
+    };
+    let mut api_interface = ApiInterface::new(api_key.to_string(), tokens, temperature);
+    api_interface.api_url = api_url;
+    api_interface.transport = Arc::new(
+        HttpTransport::new(&TransportOptions {
+            timeout: Some(Duration::from_secs(cmd_line_opts.timeout)),
+            proxy: cmd_line_opts.proxy,
+            ca_certificates: cmd_line_opts.ca_cert,
+            ..Default::default()
+        })
+        .map_err(set_up_error)?,
+    );
+    if let Some(ref path) = cmd_line_opts.record {
+        api_interface.transport = Arc::new(
+            Cassette::record(path, api_interface.transport.clone())
+                .map_err(|err| set_up_error(format!("{err}: {}", path.display()).into()))?,
+        );
+    } else if let Some(ref path) = cmd_line_opts.replay {
+        api_interface.transport = Arc::new(
+            Cassette::replay(path)
+                .map_err(|err| set_up_error(format!("{err}: {}", path.display()).into()))?,
+        );
+    }
+    if cmd_line_opts.cache {
+        let dir = cmd_line_opts
+            .cache_dir
+            .unwrap_or_else(|| project_dir.cache_dir().join(RESPONSE_CACHE_DIR));
+        let cache = Arc::new(
+            ResponseCache::new(
+                dir.as_path(),
+                Duration::from_secs(cmd_line_opts.cache_ttl),
+                cmd_line_opts.cache_size * 1024 * 1024,
+            )
+            .map_err(set_up_error)?,
+        );
+        api_interface.transport = Arc::new(CachingTransport::new(
+            cache.clone(),
+            api_interface.transport.clone(),
+        ));
+        cli_interface.cache = Some(cache);
+        // Streamed replies are not cached
+        cli_interface.stream = false;
+    }
+    // Limits on spending.  What was spent today and this month is
+    // kept in the data directory
+    if limits.session.is_some() || limits.day.is_some() || limits.month.is_some() {
+        api_interface.budget = Some(Budget::new(
+            limits,
+            &project_dir.data_dir().join(BUDGET_FILE),
+        ));
+    }
+    // What models can do.  Can be overridden in
+    // `model_capabilities.json` in the config directory
+    api_interface.model_registry =
+        ModelRegistry::with_overrides(&project_dir.config_dir().join(MODEL_CAPABILITIES_FILE))
+            .map_err(set_up_error)?;
+    if let Some(one_shot) = one_shot {
+        std::process::exit(one_shot.run(
+            &mut api_interface,
+            model,
+            mode,
+            system_prompt,
+        ));
+    }
+    if let Some(ref path) = cmd_line_opts.script {
+        if let Some(sp) = system_prompt {
+            api_interface.context.purpose = sp;
+        }
+        match cli_interface.run_script(path, &mut api_interface) {
+            Ok(report) => {
+                println!("{report}");
+                std::process::exit(if report.passed() { 0 } else { EXIT_SCRIPT_FAILED });
+            }
+            Err(err) => usage_error(err.to_string().as_str()),
+        }
+    }
+
     // The file name of the conversation record
-    cli_interface.record_file = cmd_line_opts.record_file;
+    cli_interface.record_file = record_file;
     // Keep  record of the conversations
     let mut options = OpenOptions::new();
     let mut conversation_record_file: File = options
@@ -853,24 +2603,50 @@ fn main() -> Result<(), Box<dyn Error>> {
         .unwrap();
     let mut read_line: Editor<MyHelper, FileHistory> = cli_interface.set_up_read_line()?;
     let mut prompt: String;
-    let mut api_interface = ApiInterface::new(api_key.to_string(), tokens, temperature);
-    if let Some(sp) = cmd_line_opts.system_prompt {
+    if let Some(sp) = system_prompt {
         api_interface.context.purpose = sp;
     }
+    if let Some(key) = cmd_line_opts.resume {
+        let session = match key {
+            Some(key) => cli_interface.sessions.find(key.as_str())?,
+            None => match cli_interface.sessions.latest()? {
+                Some(session) => session,
+                None => return Err("No session to resume".into()),
+            },
+        };
+        println!("{}", cli_interface.resume(session, &mut api_interface));
+    }
     loop {
         // Read the input text
-        let p = format!("{}/{mode}:> ", cli_interface.model);
-        read_line.helper_mut().expect("No helper").colored_prompt = format!("\x1b[1;32m{p}\x1b[0m");
+        let left = match api_interface.budget.as_ref().map(Budget::remaining) {
+            Some(Ok(Some(left))) => format!(" [{left:.2}c left]"),
+            Some(Err(err)) => format!(" [budget: {err}]"),
+            _ => "".to_string(),
+        };
+        let p = format!("{}/{mode}{left}:> ", cli_interface.model);
+        let helper = read_line.helper_mut().expect("No helper");
+        helper.colored_prompt = format!("\x1b[1;32m{p}\x1b[0m");
+        helper.candidates = cli_interface.candidates();
         let readline = read_line.readline(&p);
-        let input = match readline {
-            Ok(line) => line,
-            Err(err) => {
+        let composing = cli_interface.compose_key.lock().unwrap().take();
+        let input = match (readline, composing) {
+            // Alt-e
+            (_, Some(line)) => format!("! e {line}"),
+            (Ok(line), None) => line,
+            (Err(err), None) => {
                 // Exit from loop
                 eprintln!("Readline {err}");
                 break;
             }
         };
+        let input = match cli_interface.compose(input) {
+            Some(input) => input,
+            None => continue,
+        };
         read_line.add_history_entry(input.as_str())?;
+        if !input.trim().is_empty() && !input.trim().starts_with('!') {
+            cli_interface.last_prompt = input.clone();
+        }
 
         // Expand and varoables i the prompt
 
@@ -893,15 +2669,33 @@ fn main() -> Result<(), Box<dyn Error>> {
         // * It can be the completion message (error or success) for
         //   some change to the state of this programme
         let response_text: String;
+        // The reply at the end of `response_text` is already on the
+        // screen
+        let mut streamed = false;
         let prompt = prompt.as_str().trim();
         if prompt.is_empty() {
             response_text = "No prompt\n".to_string();
         } else if prompt.starts_with('!') {
             let cprompt = prompt;
-            response_text = cli_interface.process_meta(cprompt, &mut api_interface)?;
+            // A meta command that fails is reported, and the
+            // interface carries on
+            response_text = match cli_interface.process_meta(cprompt, &mut api_interface) {
+                Ok(text) => text,
+                Err(err) => format!("{err}\n"),
+            };
         } else {
             // Send the prompt to the LLM
             let start_time = Local::now();
+
+            // No choice was picked for the last chat prompt.  Carry on
+            // with the first
+            if let Some((last_prompt, choices)) = cli_interface.choices.take() {
+                api_interface.commit(
+                    last_prompt.as_str(),
+                    choices[0].message.content.as_str(),
+                    cli_interface.model.as_str(),
+                );
+            }
             let response = match cli_interface.model_mode {
                 ModelMode::AudioTranscription => {
                     let prompt_param: Option<&str> = if prompt.is_empty() {
@@ -919,7 +2713,18 @@ fn main() -> Result<(), Box<dyn Error>> {
                         Err(err) => format!("{err}"),
                     }
                 }
-                ModelMode::Chat => match api_interface.chat(prompt, cli_interface.model.as_str()) {
+                ModelMode::Chat if cli_interface.streaming(&api_interface) => {
+                    match cli_interface.stream_chat(prompt, &mut api_interface) {
+                        Ok(response) => {
+                            streamed = true;
+                            response
+                        }
+                        Err(err) => format!("{err}"),
+                    }
+                }
+                ModelMode::Chat => match api_interface
+                    .chat_choices(prompt, cli_interface.model.as_str())
+                {
                     Ok(apt_result) => {
                         // Get ready
                         cli_interface.cost = apt_result
@@ -943,6 +2748,8 @@ fn main() -> Result<(), Box<dyn Error>> {
                             Err(err) => panic!("{err}: Failed to update costs"),
                         };
 
+                        let reply =
+                            cli_interface.show_choices(prompt, apt_result.body, &mut api_interface);
                         let this_cost = cli_interface.cost;
                         let total_cost = ss.spent;
                         let conversation_cost = api_interface.context.cost;
@@ -953,7 +2760,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                             total_cost,
                             api_interface.context.len(),
                             cli_interface.after_request(apt_result.headers)?,
-                            apt_result.body,
+                            reply,
                         )
                     }
                     Err(err) => format!("{err}"),
@@ -1021,7 +2828,15 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .as_bytes(),
             )
             .unwrap();
-        println! {"{response_text}"};
+        if streamed {
+            // Only the costs and headers are left to show
+            let reply = api_interface.context.prompt_response.last().unwrap();
+            let rest = response_text.strip_suffix(reply.as_str()).unwrap_or("");
+            println!("{}", rest.trim_end());
+        } else {
+            println! {"{response_text}"};
+        }
+        cli_interface.save_session(&api_interface);
     }
 
     read_line
@@ -0,0 +1,163 @@
+//! Tab completion and hints for the interactive interface.  What
+//! each meta command takes is described in `META_COMMANDS`, so
+//! paths are only completed where a path is expected
+use crate::code::meta_command;
+use crate::code::meta_command::Arg;
+use crate::code::meta_command::META_COMMANDS;
+use crate::code::meta_command::MODES;
+use rustyline::completion::Pair;
+
+/// What can be completed that changes as the interface runs
+#[derive(Debug, Default, Clone)]
+pub struct Candidates {
+    pub models: Vec<String>,
+    /// Loaded with `! fl`
+    pub names: Vec<String>,
+    /// Listed with `! f`
+    pub file_ids: Vec<String>,
+    /// From the config file
+    pub user_commands: Vec<String>,
+}
+
+/// How to complete a line
+pub enum Completion {
+    /// It is a path: use the file name completer
+    Path,
+    /// Where the word being completed starts, and what it can become
+    Words(usize, Vec<Pair>),
+}
+
+/// The words after "!" in `line`, if it is a meta command
+fn meta_words(line: &str) -> Option<Vec<&str>> {
+    line.trim_start()
+        .strip_prefix('!')
+        .map(|rest| rest.split_whitespace().collect())
+}
+
+/// The words in `choices` that start with `word`
+fn pairs<'a>(word: &str, choices: impl Iterator<Item = &'a str>, suffix: &str) -> Vec<Pair> {
+    choices
+        .filter(|c| c.starts_with(word))
+        .map(|c| Pair {
+            display: c.to_string(),
+            replacement: format!("{c}{suffix}"),
+        })
+        .collect()
+}
+
+/// How to complete `line` with the cursor at `pos`
+pub fn complete(line: &str, pos: usize, candidates: &Candidates) -> Completion {
+    let before = &line[..pos];
+    // The word being completed, and where it starts
+    let start = before
+        .rfind(char::is_whitespace)
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let word = &before[start..];
+    let words = match meta_words(before) {
+        Some(words) => words,
+        None => {
+            // A prompt.  "{na" completes to "{name}"
+            return match word.rfind('{') {
+                Some(brace) if !word[brace..].contains('}') => Completion::Words(
+                    start + brace,
+                    candidates
+                        .names
+                        .iter()
+                        .filter(|n| n.starts_with(&word[brace + 1..]))
+                        .map(|n| Pair {
+                            display: n.clone(),
+                            replacement: format!("{{{n}}}"),
+                        })
+                        .collect(),
+                ),
+                _ => Completion::Words(pos, vec![]),
+            };
+        }
+    };
+    // The number of the word being completed
+    let index = if word.is_empty() || word == "!" {
+        words.len()
+    } else {
+        words.len() - 1
+    };
+    if index == 0 {
+        // "!" needs a space after it, so "!m" completes to "! ms"
+        let (start, word, space) = match word.strip_prefix('!') {
+            Some(word) => (start + 1, word, " "),
+            None => (start, word, ""),
+        };
+        // Aliases and the user's commands too
+        let names = META_COMMANDS
+            .iter()
+            .flat_map(|c| {
+                let space = if c.args.is_empty() { "" } else { " " };
+                std::iter::once(c.name)
+                    .chain(c.aliases.iter().copied())
+                    .map(move |n| (n, space))
+            })
+            .chain(candidates.user_commands.iter().map(|n| (n.as_str(), " ")));
+        return Completion::Words(
+            start,
+            names
+                .filter(|(n, _)| n.starts_with(word))
+                .map(|(n, after)| Pair {
+                    display: n.to_string(),
+                    replacement: format!("{space}{n}{after}"),
+                })
+                .collect(),
+        );
+    }
+    let arg = match meta_command::find(words[0]) {
+        Some(command) => match command.args.get(index - 1) {
+            Some(arg) => *arg,
+            None if command.args.last() == Some(&Arg::Paths) => Arg::Paths,
+            None => Arg::Text,
+        },
+        None => Arg::Text,
+    };
+    let choices: Vec<&str> = match arg {
+        Arg::Path | Arg::Paths => return Completion::Path,
+        Arg::Model => candidates.models.iter().map(String::as_str).collect(),
+        Arg::Mode => MODES.to_vec(),
+        Arg::Name => candidates.names.iter().map(String::as_str).collect(),
+        Arg::FileId => candidates.file_ids.iter().map(String::as_str).collect(),
+        Arg::Option(names) => ["clear"].iter().chain(names.iter()).copied().collect(),
+        Arg::Words(words) => words.to_vec(),
+        Arg::Text => vec![],
+    };
+    Completion::Words(start, pairs(word, choices.into_iter(), " "))
+}
+
+/// The syntax of the meta command being typed, if no arguments have
+/// been.  With the rest of its name if only part of it has been
+/// typed.  The second value is how much of the hint is the name
+pub fn hint(line: &str, pos: usize) -> Option<(String, usize)> {
+    if pos < line.len() {
+        return None;
+    }
+    let words = meta_words(line)?;
+    let typed = match words.as_slice() {
+        [command] => *command,
+        _ => return None,
+    };
+    let (command, rest) = match meta_command::find(typed) {
+        Some(command) => (command, ""),
+        None => {
+            let mut matches = META_COMMANDS.iter().filter(|c| c.name.starts_with(typed));
+            match (matches.next(), matches.next()) {
+                (Some(command), None) => (command, &command.name[typed.len()..]),
+                _ => return None,
+            }
+        }
+    };
+    if command.syntax.is_empty() {
+        return (!rest.is_empty()).then(|| (rest.to_string(), rest.len()));
+    }
+    let space = if line.ends_with(char::is_whitespace) && rest.is_empty() {
+        ""
+    } else {
+        " "
+    };
+    Some((format!("{rest}{space}{}", command.syntax), rest.len()))
+}
@@ -0,0 +1,64 @@
+//! Write prompts, the system prompt and `{name}` buffers in an
+//! editor: `$VISUAL`, or `$EDITOR`, or vi
+use rustyline::Cmd;
+use rustyline::ConditionalEventHandler;
+use rustyline::Event;
+use rustyline::EventContext;
+use rustyline::RepeatCount;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// If neither `VISUAL` nor `EDITOR` is set
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Open the editor on a file with `text` in it.  Returns what is in
+/// the file when the editor exits
+pub fn edit(text: &str) -> Result<String, Box<dyn Error>> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    // The editor can have arguments, like "code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or("The editor is blank")?;
+    let mut file = tempfile::Builder::new()
+        .prefix("llm-rs-")
+        .suffix(".md")
+        .tempfile()?;
+    file.write_all(text.as_bytes())?;
+    file.flush()?;
+    let status = Command::new(program)
+        .args(words)
+        .arg(file.path())
+        .status()
+        .map_err(|err| format!("{err}: Cannot run {editor}"))?;
+    if !status.success() {
+        return Err(format!("{editor} failed: {status}").into());
+    }
+    // Read by name.  Some editors replace the file
+    Ok(fs::read_to_string(file.path())?)
+}
+
+/// Bound to a key, asks for the line being edited to be composed in
+/// the editor.  The line is put in `line`, and `readline` returns as
+/// if interrupted
+pub struct ComposeKey {
+    pub line: Arc<Mutex<Option<String>>>,
+}
+
+impl ConditionalEventHandler for ComposeKey {
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        *self.line.lock().unwrap() = Some(ctx.line().to_string());
+        Some(Cmd::Interrupt)
+    }
+}
@@ -0,0 +1,196 @@
+//! Named profiles of settings for the command line interface, in
+//! `config.toml` in the config directory:
+//!
+//! ```toml
+//! default_profile = "work"
+//!
+//! [profiles.work]
+//! model = "gpt-4o"
+//! temperature = 0.2
+//! system_prompt = "You are a careful programmer"
+//! budget_day = 100.0
+//!
+//! [profiles.local]
+//! provider = "http://127.0.0.1:8080/v1"
+//! ```
+//!
+//! A command line flag beats the profile, and the profile beats the
+//! built in default
+use crate::code::meta_command::UserCommand;
+use llm_rs::openai_interface::API_URL;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// In the config directory
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// The provider that is not a URL
+const OPENAI: &str = "openai";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Used when `--profile` is not given
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+    /// Meta commands of the user's: aliases and macros
+    #[serde(default)]
+    pub commands: HashMap<String, UserCommand>,
+}
+
+/// Settings that replace the defaults.  Any can be left out
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// "openai", or the URL requests are sent to
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub mode: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub system_prompt: Option<String>,
+    pub record_file: Option<String>,
+    pub history_file: Option<String>,
+    /// Cents
+    pub budget_session: Option<f64>,
+    pub budget_day: Option<f64>,
+    pub budget_month: Option<f64>,
+    pub budget_warn: Option<f64>,
+    pub verbose: Option<usize>,
+}
+
+impl Profile {
+    /// The URL of the provider
+    pub fn api_url(&self) -> Option<String> {
+        self.provider.as_ref().map(|p| {
+            if p == OPENAI {
+                API_URL.to_string()
+            } else {
+                p.clone()
+            }
+        })
+    }
+}
+
+impl ConfigFile {
+    /// Read the configuration at `path`.  If there is none, there are
+    /// no profiles
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(text.as_str())
+                .map_err(|err| format!("{}: {err}", path.display()).into()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(ConfigFile::default()),
+            Err(err) => Err(format!("{}: {err}", path.display()).into()),
+        }
+    }
+
+    /// The profile called `name`, or the default profile if `name` is
+    /// `None`.  Either can be missing
+    pub fn profile(&self, name: Option<&str>) -> Result<Option<(String, Profile)>, String> {
+        let name = match name.or(self.default_profile.as_deref()) {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        match self.profiles.get(name) {
+            Some(profile) => Ok(Some((name.to_string(), profile.clone()))),
+            None => {
+                let mut names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+                names.sort();
+                Err(format!(
+                    "No profile called {name}.  There are: {}",
+                    names.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+/// Where a setting came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    Profile(String),
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Profile(name) => write!(f, "profile {name}"),
+            Source::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// The settings the interface started with, and where each came from
+#[derive(Debug, Default)]
+pub struct Settings {
+    profile: Option<(String, Profile)>,
+    sources: Vec<(&'static str, String, Source)>,
+}
+
+impl Settings {
+    pub fn new(profile: Option<(String, Profile)>) -> Self {
+        Settings {
+            profile,
+            sources: vec![],
+        }
+    }
+
+    /// The setting called `name`: `flag` if it was given, or what the
+    /// profile has, or `default`
+    pub fn value<T: fmt::Display>(
+        &mut self,
+        name: &'static str,
+        flag: Option<T>,
+        profile: impl Fn(&Profile) -> Option<T>,
+        default: T,
+    ) -> T {
+        self.option(name, flag, profile, Some(default)).unwrap()
+    }
+
+    /// Like `value` for a setting that need not be set
+    pub fn option<T: fmt::Display>(
+        &mut self,
+        name: &'static str,
+        flag: Option<T>,
+        profile: impl Fn(&Profile) -> Option<T>,
+        default: Option<T>,
+    ) -> Option<T> {
+        let (value, source) = if let Some(flag) = flag {
+            (Some(flag), Source::CommandLine)
+        } else if let Some((profile_name, value)) = self
+            .profile
+            .as_ref()
+            .and_then(|(n, p)| profile(p).map(|v| (n.clone(), v)))
+        {
+            (Some(value), Source::Profile(profile_name))
+        } else {
+            (default, Source::Default)
+        };
+        if let Some(ref value) = value {
+            self.sources.push((name, value.to_string(), source));
+        }
+        value
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.profile {
+            Some((ref name, _)) => writeln!(f, "Profile: {name}")?,
+            None => writeln!(f, "Profile: none")?,
+        }
+        for (name, value, source) in self.sources.iter() {
+            writeln!(f, "\t{name}: {value} ({source})")?;
+        }
+        Ok(())
+    }
+}
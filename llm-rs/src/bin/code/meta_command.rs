@@ -0,0 +1,670 @@
+//! The meta commands of the interactive interface: "! name ...".
+//! Each has its help and what its arguments are, for `! ?`, the
+//! README and completion.  Users can add their own in `config.toml`:
+//!
+//! ```toml
+//! [commands]
+//! mini = "ms gpt-4o-mini"
+//! fresh = ["cx", "sp You answer in one sentence"]
+//! ```
+use crate::code::config::CONFIG_FILE;
+use crate::CliInterface;
+use llm_rs::chat_request::CHAT_OPTION_NAMES;
+use llm_rs::completion_request::COMPLETION_OPTION_NAMES;
+use llm_rs::export::EXPORT_FORMATS;
+use llm_rs::openai_interface::ApiInterface;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// What an argument of a meta command is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arg {
+    Path,
+    /// Any number of paths
+    Paths,
+    Model,
+    Mode,
+    /// Loaded with `! fl`, used as `{name}`
+    Name,
+    /// Of a file on the server
+    FileId,
+    /// An option name, or "clear"
+    Option(&'static [&'static str]),
+    Words(&'static [&'static str]),
+    /// Nothing to complete
+    Text,
+}
+
+/// Runs a meta command with the words after its name.  Returns what
+/// to print
+pub type Handler =
+    fn(&mut CliInterface, &[&str], &mut ApiInterface) -> Result<String, Box<dyn Error>>;
+
+/// A meta command, "! name ...", and what it takes
+pub struct MetaCommand {
+    pub name: &'static str,
+    /// Other names for it
+    pub aliases: &'static [&'static str],
+    /// The arguments, as shown in help and hints
+    pub syntax: &'static str,
+    pub args: &'static [Arg],
+    pub help: &'static str,
+    pub handler: Handler,
+}
+
+impl MetaCommand {
+    /// Called `name`, or has it as an alias
+    pub fn is(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+
+    /// The syntax and the help, on one line
+    fn describe(&self) -> String {
+        let mut text = if self.syntax.is_empty() {
+            self.help.to_string()
+        } else {
+            format!("{} {}", self.syntax, self.help)
+        };
+        if !self.aliases.is_empty() {
+            text = format!("{text}.  Also: {}", self.aliases.join(", "));
+        }
+        text
+    }
+}
+
+const fn meta(
+    name: &'static str,
+    syntax: &'static str,
+    args: &'static [Arg],
+    help: &'static str,
+    handler: Handler,
+) -> MetaCommand {
+    MetaCommand {
+        name,
+        aliases: &[],
+        syntax,
+        args,
+        help,
+        handler,
+    }
+}
+
+const fn aliased(aliases: &'static [&'static str], command: MetaCommand) -> MetaCommand {
+    MetaCommand { aliases, ..command }
+}
+
+/// The modes `! m` can change to
+pub const MODES: [&str; 5] = [
+    "completions",
+    "chat",
+    "image",
+    "image_edit",
+    "audio_transcription",
+];
+
+const MD_WORDS: [&str; 2] = ["all", "refresh"];
+
+/// In the order `! ?` lists them
+pub const META_COMMANDS: &[MetaCommand] = &[
+    meta(
+        "p",
+        "",
+        &[],
+        "Display settings, and where each came from (see Profiles)",
+        CliInterface::meta_settings,
+    ),
+    meta(
+        "mm",
+        "",
+        &[],
+        "Display all available information about all models",
+        CliInterface::meta_model_info,
+    ),
+    aliased(
+        &["models"],
+        meta(
+            "md",
+            "[all] [refresh]",
+            &[Arg::Words(&MD_WORDS), Arg::Words(&MD_WORDS)],
+            "Display the models for the current mode (or all models) and what they can do",
+            CliInterface::meta_models,
+        ),
+    ),
+    meta(
+        "ms",
+        "<model>",
+        &[Arg::Model],
+        "Change the current model",
+        CliInterface::meta_model_set,
+    ),
+    aliased(
+        &["modes"],
+        meta("ml", "", &[], "List modes", CliInterface::meta_modes),
+    ),
+    meta(
+        "m",
+        "<mode>",
+        &[Arg::Mode],
+        "Change mode (API endpoint)",
+        CliInterface::meta_mode,
+    ),
+    meta(
+        "v",
+        "<verbosity>",
+        &[Arg::Text],
+        "Set verbosity",
+        CliInterface::meta_verbosity,
+    ),
+    meta(
+        "k",
+        "<max tokens>",
+        &[Arg::Text],
+        "Set max tokens for completions",
+        CliInterface::meta_max_tokens,
+    ),
+    meta(
+        "t",
+        "<temperature>",
+        &[Arg::Text],
+        "Set temperature for completions",
+        CliInterface::meta_temperature,
+    ),
+    meta(
+        "pick",
+        "[n]",
+        &[Arg::Text],
+        "Carry on with choice n of the last chat reply, or show the choices again",
+        CliInterface::meta_pick,
+    ),
+    meta(
+        "hm",
+        "",
+        &[],
+        "Toggle a heat map of how likely each token of a chat reply was",
+        CliInterface::meta_heat_map,
+    ),
+    meta(
+        "stream",
+        "",
+        &[],
+        "Toggle showing chat replies as they arrive.  Not with several choices or the heat map",
+        CliInterface::meta_stream,
+    ),
+    meta(
+        "sessions",
+        "[list | open <id> | rename <id> <name> | delete <id>]",
+        &[
+            Arg::Words(&["list", "open", "rename", "delete"]),
+            Arg::Text,
+            Arg::Text,
+        ],
+        "List, reopen, name or delete saved sessions.  An ID or a name will do",
+        CliInterface::meta_sessions,
+    ),
+    meta(
+        "cache",
+        "[clear]",
+        &[Arg::Words(&["clear"])],
+        "Display or empty the response cache (see `--cache`)",
+        CliInterface::meta_cache,
+    ),
+    meta(
+        "budget",
+        "[session | day | month [cents]]",
+        &[Arg::Words(&["session", "day", "month"]), Arg::Text],
+        "Display spending, or set or remove a limit (see `--budget-session`)",
+        CliInterface::meta_budget,
+    ),
+    meta(
+        "co",
+        "[clear | <name> [value]]",
+        &[Arg::Option(&COMPLETION_OPTION_NAMES), Arg::Text],
+        "Display, set or unset completion options: suffix, echo, stop, best_of, logprobs",
+        CliInterface::meta_completion_options,
+    ),
+    meta(
+        "fim",
+        "<path> [output]",
+        &[Arg::Path, Arg::Path],
+        "Fill the gap marked `<FILL>` in a file with a completion, and write the filled file to `output`",
+        CliInterface::meta_fill_in_middle,
+    ),
+    meta(
+        "o",
+        "[clear | <name> [value]]",
+        &[Arg::Option(&CHAT_OPTION_NAMES), Arg::Text],
+        "Display, set or unset chat options (see Chat options under Features)",
+        CliInterface::meta_chat_options,
+    ),
+    meta(
+        "sp",
+        "[system prompt]",
+        &[Arg::Text],
+        "Set the system prompt, or display it.  Chat only",
+        CliInterface::meta_system_prompt,
+    ),
+    meta(
+        "esp",
+        "",
+        &[],
+        "Edit the system prompt in `$VISUAL` or `$EDITOR`",
+        CliInterface::meta_system_prompt_edit,
+    ),
+    meta(
+        "mask",
+        "<path>",
+        &[Arg::Path],
+        "Set the mask to use in image edit mode.  A 1024x1024 PNG with transparent mask",
+        CliInterface::meta_mask,
+    ),
+    meta(
+        "ci",
+        "",
+        &[],
+        "Clear the image stored for editing",
+        CliInterface::meta_image_clear,
+    ),
+    meta(
+        "a",
+        "<path>",
+        &[Arg::Path],
+        "Audio file for transcription",
+        CliInterface::meta_audio,
+    ),
+    meta(
+        "f",
+        "",
+        &[],
+        "List the files stored on the server",
+        CliInterface::meta_files,
+    ),
+    meta(
+        "fu",
+        "<path> [model]",
+        &[Arg::Path, Arg::Model],
+        "Check and upload a file of fine tuning data for model",
+        CliInterface::meta_file_upload,
+    ),
+    meta(
+        "fv",
+        "<path> [model] [epochs]",
+        &[Arg::Path, Arg::Model, Arg::Text],
+        "Check a file of fine tuning data and estimate the cost of training",
+        CliInterface::meta_file_validate,
+    ),
+    meta(
+        "fd",
+        "<file id>",
+        &[Arg::FileId],
+        "Delete a file",
+        CliInterface::meta_file_delete,
+    ),
+    meta(
+        "fi",
+        "<file id>",
+        &[Arg::FileId],
+        "Get information about file",
+        CliInterface::meta_file_info,
+    ),
+    meta(
+        "fc",
+        "<file id> [destination_file]",
+        &[Arg::FileId, Arg::Path],
+        "Get contents of file",
+        CliInterface::meta_file_contents,
+    ),
+    meta(
+        "ft",
+        "<file id> [model] [key=value...]",
+        &[Arg::FileId, Arg::Model, Arg::Text],
+        "Start a fine tuning job.  Keys: validation, epochs, batch, lr, suffix, seed",
+        CliInterface::meta_fine_tune,
+    ),
+    meta(
+        "ftl",
+        "",
+        &[],
+        "List fine tuning jobs",
+        CliInterface::meta_fine_tune_list,
+    ),
+    meta(
+        "ftr",
+        "<id>",
+        &[Arg::Text],
+        "Retrieve the info for a fine tuning job",
+        CliInterface::meta_fine_tune_retrieve,
+    ),
+    meta(
+        "fte",
+        "<id>",
+        &[Arg::Text],
+        "List the events of a fine tuning job",
+        CliInterface::meta_fine_tune_events,
+    ),
+    meta(
+        "ftw",
+        "<id>",
+        &[Arg::Text],
+        "Watch a fine tuning job, printing events until it finishes",
+        CliInterface::meta_fine_tune_watch,
+    ),
+    meta(
+        "ftc",
+        "<id>",
+        &[Arg::Text],
+        "Cancel a fine tuning job",
+        CliInterface::meta_fine_tune_cancel,
+    ),
+    meta(
+        "ftk",
+        "<id>",
+        &[Arg::Text],
+        "List the checkpoints of a fine tuning job",
+        CliInterface::meta_fine_tune_checkpoints,
+    ),
+    meta(
+        "ftd",
+        "<model>",
+        &[Arg::Model],
+        "Delete a fine tuned model",
+        CliInterface::meta_fine_tune_delete,
+    ),
+    meta(
+        "ftx",
+        "<output> <path>... [model=m] [turns=n] [purpose=regex]",
+        &[Arg::Path, Arg::Paths],
+        "Make fine tuning data from conversations saved with `! sx`",
+        CliInterface::meta_fine_tune_export,
+    ),
+    meta(
+        "b",
+        "<prompts file> <output file>",
+        &[Arg::Path, Arg::Path],
+        "Send each line of a file as a chat prompt in a batch.  Waits for it to finish and writes the results as JSONL",
+        CliInterface::meta_batch,
+    ),
+    meta(
+        "bl",
+        "",
+        &[],
+        "List batches",
+        CliInterface::meta_batch_list,
+    ),
+    meta(
+        "bs",
+        "<id>",
+        &[Arg::Text],
+        "The status of a batch",
+        CliInterface::meta_batch_status,
+    ),
+    meta(
+        "bc",
+        "<id>",
+        &[Arg::Text],
+        "Cancel a batch",
+        CliInterface::meta_batch_cancel,
+    ),
+    meta(
+        "br",
+        "<id> <output file>",
+        &[Arg::Text, Arg::Path],
+        "Write the results of a finished batch as JSONL",
+        CliInterface::meta_batch_results,
+    ),
+    meta(
+        "fl",
+        "<name> [--max-tokens <n>] [--truncate] <path | glob | url>...",
+        &[Arg::Name, Arg::Paths],
+        "Associate the text of files, globs, directories and web pages with `name` for use in prompts like: {name} (see Loading Files)",
+        CliInterface::meta_data_load,
+    ),
+    meta(
+        "efl",
+        "<name>",
+        &[Arg::Name],
+        "Edit the data for `{name}` in `$VISUAL` or `$EDITOR`.  Empty it to remove it",
+        CliInterface::meta_data_edit,
+    ),
+    meta(
+        "e",
+        "[text]",
+        &[],
+        "Write the prompt in `$VISUAL` or `$EDITOR`, starting with the text or the last prompt.  Alt-e does the same with the line being typed",
+        CliInterface::meta_compose,
+    ),
+    meta(
+        "dx",
+        "",
+        &[],
+        "Display context (for chat)",
+        CliInterface::meta_context_display,
+    ),
+    meta(
+        "cx",
+        "",
+        &[],
+        "Clear context",
+        CliInterface::meta_context_clear,
+    ),
+    meta(
+        "sx",
+        "<path>",
+        &[Arg::Path],
+        "Save the context to a file at the specified path",
+        CliInterface::meta_context_save,
+    ),
+    meta(
+        "rx",
+        "<path>",
+        &[Arg::Path],
+        "Restore the context from a file at the specified path",
+        CliInterface::meta_context_restore,
+    ),
+    meta(
+        "ppx",
+        "<path>",
+        &[Arg::Path],
+        "Pretty print the conversation to a file",
+        CliInterface::meta_context_print,
+    ),
+    meta(
+        "export",
+        "<markdown | html | json> <path>",
+        &[Arg::Words(&EXPORT_FORMATS), Arg::Path],
+        "Write the conversation, with the system prompt, model, cost and times, as Markdown, a web page or OpenAI messages in JSON",
+        CliInterface::meta_export,
+    ),
+    meta(
+        "run",
+        "<script>",
+        &[Arg::Path],
+        "Run a script of prompts and meta commands, with captures and assertions (see Scripts)",
+        CliInterface::meta_run,
+    ),
+    aliased(
+        &["help"],
+        meta("?", "", &[], "This text", CliInterface::meta_help),
+    ),
+];
+
+/// The meta command called `name`, or with `name` as an alias
+pub fn find(name: &str) -> Option<&'static MetaCommand> {
+    META_COMMANDS.iter().find(|c| c.is(name))
+}
+
+/// Meta commands that only work when typed, so cannot be used in
+/// user commands
+const TYPED_ONLY: [&str; 1] = ["e"];
+
+/// A command from the config file.  It stands for one meta command,
+/// with some of its arguments, or for several run one after another
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum UserCommand {
+    Alias(String),
+    Macro(Vec<String>),
+}
+
+impl UserCommand {
+    /// The meta commands, without the "!"
+    pub fn steps(&self) -> Vec<&str> {
+        let steps: Vec<&str> = match self {
+            UserCommand::Alias(step) => vec![step.as_str()],
+            UserCommand::Macro(steps) => steps.iter().map(String::as_str).collect(),
+        };
+        steps
+            .into_iter()
+            .map(|s| s.trim().trim_start_matches('!').trim_start())
+            .collect()
+    }
+
+    /// The words of each step, with `args` on the end of the last
+    pub fn with_args<'a>(&'a self, args: &[&'a str]) -> Vec<Vec<&'a str>> {
+        let steps = self.steps();
+        let last = steps.len().saturating_sub(1);
+        steps
+            .into_iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let mut words: Vec<&str> = step.split_whitespace().collect();
+                if i == last {
+                    words.extend_from_slice(args);
+                }
+                words
+            })
+            .collect()
+    }
+}
+
+/// User commands must not hide meta commands, and can only use them
+pub fn check_user_commands(commands: &HashMap<String, UserCommand>) -> Result<(), String> {
+    for (name, command) in commands.iter() {
+        if find(name).is_some() {
+            return Err(format!(
+                "The command {name} in the config file is a meta command"
+            ));
+        }
+        let steps = command.steps();
+        if steps.is_empty() {
+            return Err(format!("The command {name} in the config file is empty"));
+        }
+        for step in steps {
+            let first = step.split_whitespace().next().unwrap_or_default();
+            match find(first) {
+                None => {
+                    return Err(format!(
+                        "The command {name} in the config file uses {first:?}, which is not a meta command"
+                    ))
+                }
+                Some(command) if TYPED_ONLY.contains(&command.name) => {
+                    return Err(format!(
+                        "The command {name} in the config file uses {first:?}, which can only be typed"
+                    ))
+                }
+                Some(_) => (),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `! ?`: every meta command, and the user's
+pub fn help(user_commands: &HashMap<String, UserCommand>) -> String {
+    let mut text = META_COMMANDS.iter().fold(String::new(), |a, c| {
+        format!("{a}{} {}\n", c.name, c.describe())
+    });
+    if !user_commands.is_empty() {
+        let mut names: Vec<&String> = user_commands.keys().collect();
+        names.sort();
+        text = names
+            .iter()
+            .fold(format!("{text}\nFrom {CONFIG_FILE}:\n"), |a, n| {
+                format!("{a}{n} = {}\n", user_commands[*n].steps().join("; "))
+            });
+    }
+    text
+}
+
+/// The table of meta commands in the README
+pub fn markdown_table() -> String {
+    META_COMMANDS
+        .iter()
+        .fold("|Command| Result|\n|:---|:---|\n".to_string(), |a, c| {
+            format!("{a}|! {}| {}|\n", c.name, c.describe().replace('|', "\\|"))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(toml: &str) -> HashMap<String, UserCommand> {
+        #[derive(Deserialize)]
+        struct Config {
+            commands: HashMap<String, UserCommand>,
+        }
+        toml::from_str::<Config>(toml).unwrap().commands
+    }
+
+    #[test]
+    fn readme_has_the_table() {
+        let readme =
+            std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/../README.md")).unwrap();
+        assert!(
+            readme.contains(markdown_table().as_str()),
+            "Update the README from `cli meta-commands`"
+        );
+    }
+
+    #[test]
+    fn user_commands_are_checked() {
+        let good = commands(
+            "[commands]\nmini = \"ms gpt-4o-mini\"\nfresh = [\"cx\", \"! sp Be brief\"]\n",
+        );
+        assert!(check_user_commands(&good).is_ok());
+        assert_eq!(good["fresh"].steps(), ["cx", "sp Be brief"]);
+
+        let shadow = commands("[commands]\nms = \"ms gpt-4o\"\n");
+        assert!(check_user_commands(&shadow)
+            .unwrap_err()
+            .contains("is a meta command"));
+        // An alias is a meta command too
+        let alias = commands("[commands]\nhelp = \"?\"\n");
+        assert!(check_user_commands(&alias).is_err());
+
+        let unknown = commands("[commands]\nx = [\"cx\", \"nonesuch 1\"]\n");
+        assert!(check_user_commands(&unknown)
+            .unwrap_err()
+            .contains("\"nonesuch\", which is not a meta command"));
+
+        let compose = commands("[commands]\nfresh = [\"cx\", \"e\"]\n");
+        assert!(check_user_commands(&compose)
+            .unwrap_err()
+            .contains("\"e\", which can only be typed"));
+
+        let empty = commands("[commands]\nx = []\n");
+        assert!(check_user_commands(&empty)
+            .unwrap_err()
+            .contains("is empty"));
+        let blank = commands("[commands]\nx = [\"\"]\n");
+        assert!(check_user_commands(&blank).is_err());
+    }
+
+    #[test]
+    fn arguments_go_to_the_last_step() {
+        let user = commands("[commands]\nt = [\"cx\", \"o temperature\"]\nm = \"ms\"\n");
+        assert_eq!(
+            user["t"].with_args(&["0.5"]),
+            [vec!["cx"], vec!["o", "temperature", "0.5"]]
+        );
+        assert_eq!(
+            user["t"].with_args(&[]),
+            [vec!["cx"], vec!["o", "temperature"]]
+        );
+        assert_eq!(
+            user["m"].with_args(&["gpt-4o", "x"]),
+            [vec!["ms", "gpt-4o", "x"]]
+        );
+    }
+}
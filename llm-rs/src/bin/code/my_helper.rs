@@ -1,24 +1,85 @@
-use rustyline::completion::FilenameCompleter;
+use crate::code::completion;
+use crate::code::completion::Candidates;
+use crate::code::completion::Completion;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
 use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
-use rustyline::hint::HistoryHinter;
+use rustyline::hint::{Hint, Hinter, HistoryHinter};
 //use rustyline::history::FileHistory;
 use rustyline::validate::MatchingBracketValidator;
+use rustyline::Context;
 use rustyline::Validator;
 //use rustyline::{Cmd, CompletionType, Config, EditMode, Editor, Event, EventHandler, KeyEvent};
-use rustyline::{Completer, Helper, Hinter};
+use rustyline::Helper;
 use std::borrow::Cow::{self, Borrowed, Owned};
 
 /// `MyHelper` is copied from the examples in `RustyLine` crate
-#[derive(Helper, Completer, Hinter, Validator)]
+#[derive(Helper, Validator)]
 pub struct MyHelper {
-    #[rustyline(Completer)]
     pub completer: FilenameCompleter,
     pub highlighter: MatchingBracketHighlighter,
     #[rustyline(Validator)]
     pub validator: MatchingBracketValidator,
-    #[rustyline(Hinter)]
     pub hinter: HistoryHinter,
     pub colored_prompt: String,
+    /// Models, names and file IDs to complete.  Kept up to date by
+    /// the interface
+    pub candidates: Candidates,
+}
+
+/// Paths only where a meta command expects one.  See `completion`
+impl Completer for MyHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        match completion::complete(line, pos, &self.candidates) {
+            Completion::Path => self.completer.complete(line, pos, ctx),
+            Completion::Words(start, pairs) => Ok((start, pairs)),
+        }
+    }
+}
+
+/// A hint from history, or the syntax of a meta command
+pub struct MyHint {
+    display: String,
+    /// How much of `display` is inserted if the hint is accepted.
+    /// Not the syntax
+    completion: usize,
+}
+
+impl Hint for MyHint {
+    fn display(&self) -> &str {
+        self.display.as_str()
+    }
+
+    fn completion(&self) -> Option<&str> {
+        if self.completion > 0 {
+            Some(&self.display[..self.completion])
+        } else {
+            None
+        }
+    }
+}
+
+impl Hinter for MyHelper {
+    type Hint = MyHint;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<MyHint> {
+        match completion::hint(line, pos) {
+            Some((display, completion)) => Some(MyHint {
+                display,
+                completion,
+            }),
+            None => self.hinter.hint(line, pos, ctx).map(|display| MyHint {
+                completion: display.len(),
+                display,
+            }),
+        }
+    }
 }
 
 /// `MyHelper` is copied from the examples in `RustyLine` crate
@@ -0,0 +1,195 @@
+//! Answer one prompt and exit, for shell pipelines and scripts.  Only
+//! the reply is printed to stdout.  Everything else goes to stderr
+use crate::code::shared_state::SharedState;
+use clap::Args;
+use llm_rs::completion_request::DEFAULT_COMPLETIONS_MODEL;
+use llm_rs::context::Context;
+use llm_rs::model_mode::ModelMode;
+use llm_rs::model_registry::Capability;
+use llm_rs::openai_interface::ApiInterface;
+use llm_rs::openai_interface::BUDGET_WARNING_HEADER;
+use serde_json::json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::IsTerminal;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::exit;
+
+/// The request was sent and failed, or was refused before it was
+/// sent (see `--budget-session`)
+pub const EXIT_API_ERROR: i32 = 1;
+
+/// The command line was wrong, or something it names is missing
+pub const EXIT_USAGE_ERROR: i32 = 2;
+
+/// Arguments of the `ask` command
+#[derive(Args, Debug)]
+pub struct OneShot {
+    /// The prompt.  Read from stdin if there is none
+    prompt: Vec<String>,
+
+    /// A conversation saved with `! sx` to carry on.  Chat only
+    #[arg(long)]
+    context: Option<PathBuf>,
+
+    /// The audio file to transcribe, in audio_transcription mode.  The
+    /// prompt is optional
+    #[arg(long)]
+    audio: Option<PathBuf>,
+
+    /// Print the reply, usage and cost as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// What was asked for cannot be done.  Says why and exits
+pub fn usage_error(message: &str) -> ! {
+    eprintln!("{message}");
+    exit(EXIT_USAGE_ERROR);
+}
+
+impl OneShot {
+    /// Send the prompt to `model` in `mode`, print the reply, and
+    /// return the exit code
+    pub fn run(
+        self,
+        api_interface: &mut ApiInterface,
+        model: &str,
+        mode: ModelMode,
+        system_prompt: Option<String>,
+    ) -> i32 {
+        if mode == ModelMode::ImageEdit {
+            usage_error("image_edit is only in the interactive interface");
+        }
+        if let Some(ref path) = self.context {
+            if mode != ModelMode::Chat {
+                usage_error("--context is for chat mode");
+            }
+            match read_context(path) {
+                Ok(context) => api_interface.set_context(context),
+                Err(err) => usage_error(format!("{err}: {}", path.display()).as_str()),
+            }
+        }
+        if mode == ModelMode::AudioTranscription {
+            match self.audio {
+                Some(ref audio) if !audio.exists() => {
+                    usage_error(format!("{} does not exist", audio.display()).as_str())
+                }
+                Some(_) => (),
+                None => usage_error("--audio <path> is needed for audio_transcription"),
+            }
+        }
+        if let Some(system_prompt) = system_prompt {
+            api_interface.context.purpose = system_prompt;
+        }
+        let prompt = self.prompt(&mode);
+
+        // Chat models do not do completions
+        let capabilities = api_interface.model_registry.capabilities(model);
+        let model = if mode == ModelMode::Completions
+            && !capabilities.is_empty()
+            && !capabilities.contains(&Capability::Completions)
+        {
+            DEFAULT_COMPLETIONS_MODEL
+        } else {
+            model
+        };
+
+        let result = match mode {
+            ModelMode::Chat => api_interface.chat(prompt.as_str(), model),
+            ModelMode::Completions => api_interface.completion(prompt.as_str(), model),
+            ModelMode::Image => api_interface.image(prompt.as_str()),
+            ModelMode::AudioTranscription => api_interface.audio_transcription(
+                self.audio.as_ref().unwrap().as_path(),
+                if prompt.is_empty() {
+                    None
+                } else {
+                    Some(prompt.as_str())
+                },
+            ),
+            ModelMode::ImageEdit => unreachable!(),
+        };
+        let result = match result {
+            Ok(result) => result,
+            Err(err) => {
+                eprintln!("{err}");
+                return EXIT_API_ERROR;
+            }
+        };
+        if let Some(warning) = result.headers.get(BUDGET_WARNING_HEADER) {
+            eprintln!("{BUDGET_WARNING_HEADER}: {warning}");
+        }
+        let cost: Option<f64> = result.headers.get("Cost").and_then(|c| c.parse().ok());
+        if let Some(cost) = cost {
+            // The lifetime total the interactive interface shows
+            if let Err(err) = SharedState::read_write_atomic(move |mut ss| {
+                ss.spent += cost;
+                ss
+            }) {
+                eprintln!("{err}: Failed to update costs");
+            }
+        }
+        if self.json {
+            println!(
+                "{}",
+                json!({
+                    "mode": mode.to_string(),
+                    "model": model,
+                    "reply": result.body,
+                    "usage": usage(&result.headers),
+                    "cost": cost,
+                })
+            );
+        } else {
+            println!("{}", result.body.trim_end());
+        }
+        0
+    }
+
+    /// The prompt from the arguments, or stdin if there are none.
+    /// Transcriptions need no prompt
+    fn prompt(&self, mode: &ModelMode) -> String {
+        if !self.prompt.is_empty() {
+            return self.prompt.join(" ");
+        }
+        let stdin = std::io::stdin();
+        if *mode == ModelMode::AudioTranscription && stdin.is_terminal() {
+            return String::new();
+        }
+        let mut prompt = String::new();
+        if let Err(err) = stdin.lock().read_to_string(&mut prompt) {
+            usage_error(format!("{err}: Reading the prompt from stdin").as_str());
+        }
+        let prompt = prompt.trim().to_string();
+        if prompt.is_empty() && *mode != ModelMode::AudioTranscription {
+            usage_error("No prompt.  Give it as arguments or on stdin");
+        }
+        prompt
+    }
+}
+
+/// A conversation saved with `! sx`
+fn read_context(path: &Path) -> Result<Context, Box<dyn Error>> {
+    Ok(serde_json::from_str(fs::read_to_string(path)?.as_str())?)
+}
+
+/// The token counts from the headers of a result, if it has them
+fn usage(headers: &HashMap<String, String>) -> Value {
+    let count = |name: &str| headers.get(name).and_then(|t| t.parse::<u32>().ok());
+    match (
+        count("Tokens prompt"),
+        count("Tokens completion"),
+        count("Tokens total"),
+    ) {
+        (Some(prompt), Some(completion), Some(total)) => json!({
+            "prompt_tokens": prompt,
+            "completion_tokens": completion,
+            "total_tokens": total,
+        }),
+        _ => Value::Null,
+    }
+}
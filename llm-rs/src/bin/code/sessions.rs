@@ -0,0 +1,265 @@
+//! Sessions of the interactive interface, saved after every turn so
+//! they survive it exiting, crashing or being interrupted.  One JSON
+//! file for each in the `sessions` directory of the data directory
+use chrono::Local;
+use llm_rs::context::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use tempfile::NamedTempFile;
+
+/// In the data directory
+pub const SESSIONS_DIR: &str = "sessions";
+
+/// Times are local, and sort as text
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// The longest the first prompt is in a list of sessions
+const SUMMARY_LEN: usize = 40;
+
+/// What is needed to carry on where a session left off
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    /// Set with `! sessions rename`
+    #[serde(default)]
+    pub name: Option<String>,
+    pub created: String,
+    pub updated: String,
+    pub model: String,
+    pub mode: String,
+    pub context: Context,
+    /// Data loaded with `! fl` for `{name}` in prompts
+    #[serde(default)]
+    pub local_data: HashMap<String, String>,
+}
+
+impl Session {
+    /// Called `id`, with nothing in it yet
+    pub fn new(id: String, model: &str, mode: &str) -> Self {
+        let now = Local::now().format(TIME_FORMAT).to_string();
+        Session {
+            id,
+            name: None,
+            created: now.clone(),
+            updated: now,
+            model: model.to_string(),
+            mode: mode.to_string(),
+            context: Context::new(""),
+            local_data: HashMap::new(),
+        }
+    }
+
+    /// Note that the session has changed
+    pub fn touch(&mut self) {
+        self.updated = Local::now().format(TIME_FORMAT).to_string();
+    }
+
+    /// Prompts and replies
+    pub fn turns(&self) -> usize {
+        self.context.len() / 2
+    }
+}
+
+impl fmt::Display for Session {
+    /// One line, for a list
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let about = match self.name {
+            Some(ref name) => name.clone(),
+            None => {
+                let first = self
+                    .context
+                    .prompt_response
+                    .first()
+                    .map(|p| p.split_whitespace().collect::<Vec<&str>>().join(" "))
+                    .unwrap_or_default();
+                if first.chars().count() > SUMMARY_LEN {
+                    format!("{}...", first.chars().take(SUMMARY_LEN).collect::<String>())
+                } else {
+                    first
+                }
+            }
+        };
+        write!(
+            f,
+            "{}  {}  {} turns  {}/{}  {about}",
+            self.id,
+            self.updated.replace('T', " "),
+            self.turns(),
+            self.model,
+            self.mode,
+        )
+    }
+}
+
+/// Where sessions are kept
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: &Path) -> Self {
+        SessionStore {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// An ID no session has, from the time and the process.  Two
+    /// interfaces started in the same second have different IDs
+    pub fn new_id(&self) -> String {
+        let time = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        self.id_for(time.as_str(), std::process::id())
+    }
+
+    fn id_for(&self, time: &str, pid: u32) -> String {
+        let id = format!("{time}-{pid}");
+        let mut candidate = id.clone();
+        let mut n = 1;
+        while self.path(candidate.as_str()).exists() {
+            n += 1;
+            candidate = format!("{id}-{n}");
+        }
+        candidate
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    pub fn exists(&self, id: &str) -> bool {
+        self.path(id).exists()
+    }
+
+    /// Write `session`.  The file is replaced whole, so it is never
+    /// half written
+    pub fn save(&self, session: &Session) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(self.dir.as_path())?;
+        let mut file = NamedTempFile::new_in(self.dir.as_path())?;
+        file.write_all(serde_json::to_string(session)?.as_bytes())?;
+        file.persist(self.path(session.id.as_str()))?;
+        Ok(())
+    }
+
+    /// Every session that can be read, the latest first
+    pub fn list(&self) -> Result<Vec<Session>, Box<dyn Error>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut sessions: Vec<Session> = vec![];
+        for entry in fs::read_dir(self.dir.as_path())? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            match fs::read_to_string(path.as_path())
+                .map_err(|err| err.to_string())
+                .and_then(|s| {
+                    serde_json::from_str::<Session>(s.as_str()).map_err(|err| err.to_string())
+                }) {
+                Ok(session) => sessions.push(session),
+                Err(err) => eprintln!("{err}: Cannot read session {}", path.display()),
+            }
+        }
+        sessions.sort_by(|a, b| b.updated.cmp(&a.updated).then(b.id.cmp(&a.id)));
+        Ok(sessions)
+    }
+
+    /// The session with the ID or name `key`
+    pub fn find(&self, key: &str) -> Result<Session, Box<dyn Error>> {
+        let path = self.path(key);
+        if path.exists() {
+            return Ok(serde_json::from_str(fs::read_to_string(path)?.as_str())?);
+        }
+        self.list()?
+            .into_iter()
+            .find(|s| s.name.as_deref() == Some(key))
+            .ok_or_else(|| format!("No session with ID or name {key}").into())
+    }
+
+    /// The session updated last, if there is one
+    pub fn latest(&self) -> Result<Option<Session>, Box<dyn Error>> {
+        Ok(self.list()?.into_iter().next())
+    }
+
+    /// Name the session `key`
+    pub fn rename(&self, key: &str, name: &str) -> Result<Session, Box<dyn Error>> {
+        let mut session = self.find(key)?;
+        session.name = Some(name.to_string());
+        self.save(&session)?;
+        Ok(session)
+    }
+
+    pub fn delete(&self, key: &str) -> Result<Session, Box<dyn Error>> {
+        let session = self.find(key)?;
+        fs::remove_file(self.path(session.id.as_str()))?;
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(store: &SessionStore, id: &str, prompt: &str) -> Session {
+        let mut session = Session::new(id.to_string(), "gpt-4o-mini", "Chat");
+        session.context.push(prompt.to_string());
+        session.context.push("Hi".to_string());
+        store.save(&session).unwrap();
+        session
+    }
+
+    #[test]
+    fn list_open_rename_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(&dir.path().join(SESSIONS_DIR));
+        assert!(store.list().unwrap().is_empty());
+        assert!(store.latest().unwrap().is_none());
+
+        let mut first = session(&store, "20240101-120000-1", "Hello");
+        first.updated = "2024-01-01T12:00:00".to_string();
+        store.save(&first).unwrap();
+        session(&store, "20240102-120000-1", "Goodbye");
+        fs::write(dir.path().join(SESSIONS_DIR).join("bad.json"), "{").unwrap();
+
+        // The latest first, and files that cannot be read are left out
+        let ids: Vec<String> = store.list().unwrap().into_iter().map(|s| s.id).collect();
+        assert_eq!(ids, ["20240102-120000-1", "20240101-120000-1"]);
+        assert_eq!(store.latest().unwrap().unwrap().id, "20240102-120000-1");
+        assert_eq!(store.find("20240101-120000-1").unwrap().turns(), 1);
+
+        let renamed = store.rename("20240101-120000-1", "greeting").unwrap();
+        assert!(renamed.to_string().ends_with("greeting"));
+        assert_eq!(store.find("greeting").unwrap().id, "20240101-120000-1");
+
+        store.delete("greeting").unwrap();
+        assert!(!store.exists("20240101-120000-1"));
+        assert!(store.find("greeting").is_err());
+        assert!(store.delete("greeting").is_err());
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn ids_do_not_collide() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path());
+        // Two processes in the same second
+        let a = store.id_for("20240101-120000", 10);
+        let b = store.id_for("20240101-120000", 11);
+        assert_ne!(a, b);
+        // A process that was given an ID already used
+        session(&store, a.as_str(), "Hello");
+        let again = store.id_for("20240101-120000", 10);
+        assert_eq!(again, format!("{a}-2"));
+        session(&store, again.as_str(), "Hello");
+        assert_eq!(store.id_for("20240101-120000", 10), format!("{a}-3"));
+        assert!(store
+            .new_id()
+            .ends_with(format!("-{}", std::process::id()).as_str()));
+    }
+}
@@ -40,11 +40,10 @@ impl SharedState {
         let sz = match file.read_to_string(&mut contents) {
             Ok(s) => s,
             Err(err) => {
-                println!("Got error: {:?}", err);
+                eprintln!("Got error: {:?}", err);
                 return Err(Box::new(err));
             }
         };
-        println!("Read {sz} bytes");
         let state: SharedState = if sz != 0 {
             // There was content to read
             serde_json::from_str(&contents)?
@@ -0,0 +1,315 @@
+//! Limits on spending.  A `Budget` has limits for the session, the
+//! day and the month, in cents.  Before a request is sent its cost
+//! is estimated, and if that would take the spending over a limit the
+//! request is refused with an `ApiErrorType::OverBudget` error.
+//! Getting close to a limit gives a warning.
+//!
+//! What was spent in the day and month is kept in a ledger file, so
+//! it is shared by every programme using the same ledger.  The
+//! session is the life of the `Budget`
+use crate::api_error::ApiError;
+use crate::api_error::ApiErrorType;
+use chrono::Local;
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Warn when this fraction of a limit is spent, by default
+pub const DEFAULT_WARN_AT: f64 = 0.8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetPeriod {
+    Session,
+    Day,
+    Month,
+}
+
+impl fmt::Display for BudgetPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetPeriod::Session => write!(f, "session"),
+            BudgetPeriod::Day => write!(f, "day"),
+            BudgetPeriod::Month => write!(f, "month"),
+        }
+    }
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverBudget {
+    pub period: BudgetPeriod,
+    /// All amounts are in cents
+    pub limit: f64,
+    pub spent: f64,
+    /// What the request was estimated to cost
+    pub estimate: f64,
+}
+
+impl fmt::Display for OverBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The {} limit is {:.2}c, {:.2}c is spent and the request could cost {:.2}c",
+            self.period, self.limit, self.spent, self.estimate
+        )
+    }
+}
+
+/// The limits, in cents.  `None` is no limit
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetLimits {
+    pub session: Option<f64>,
+    pub day: Option<f64>,
+    pub month: Option<f64>,
+    /// Warn when this fraction of a limit would be spent
+    pub warn_at: f64,
+}
+
+impl Default for BudgetLimits {
+    fn default() -> Self {
+        BudgetLimits {
+            session: None,
+            day: None,
+            month: None,
+            warn_at: DEFAULT_WARN_AT,
+        }
+    }
+}
+
+/// What has been spent, in cents
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Spent {
+    pub session: f64,
+    pub day: f64,
+    pub month: f64,
+}
+
+/// The ledger file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Ledger {
+    /// Like "2024-06-30"
+    day: String,
+    day_spent: f64,
+    /// Like "2024-06"
+    month: String,
+    month_spent: f64,
+}
+
+impl Ledger {
+    /// Start a new day or month if the ledger is from an earlier one
+    fn roll_over(&mut self) {
+        let now = Local::now();
+        let day = now.format("%Y-%m-%d").to_string();
+        let month = now.format("%Y-%m").to_string();
+        if self.day != day {
+            self.day = day;
+            self.day_spent = 0.0;
+        }
+        if self.month != month {
+            self.month = month;
+            self.month_spent = 0.0;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Budget {
+    pub limits: BudgetLimits,
+    session: f64,
+    ledger: PathBuf,
+}
+
+impl Budget {
+    /// A budget with `limits` that keeps what is spent in `ledger`
+    pub fn new(limits: BudgetLimits, ledger: &Path) -> Self {
+        Budget {
+            limits,
+            session: 0.0,
+            ledger: ledger.to_path_buf(),
+        }
+    }
+
+    /// What has been spent in each period
+    pub fn spent(&self) -> Result<Spent, Box<dyn Error>> {
+        let ledger = self.update(|_| ())?;
+        Ok(Spent {
+            session: self.session,
+            day: ledger.day_spent,
+            month: ledger.month_spent,
+        })
+    }
+
+    /// The least that is left under any limit.  `None` if there are no
+    /// limits
+    pub fn remaining(&self) -> Result<Option<f64>, Box<dyn Error>> {
+        let spent = self.spent()?;
+        Ok(self
+            .periods(&spent)
+            .into_iter()
+            .filter_map(|(_, limit, spent)| limit.map(|l| l - spent))
+            .reduce(f64::min))
+    }
+
+    /// Check a request estimated to cost `estimate` can be afforded.
+    /// An `ApiErrorType::OverBudget` error if not, otherwise a warning
+    /// for each limit it gets close to
+    pub fn check(&self, estimate: f64) -> Result<Vec<String>, Box<dyn Error>> {
+        let spent = self.spent()?;
+        let mut warnings = vec![];
+        for (period, limit, spent) in self.periods(&spent) {
+            if let Some(limit) = limit {
+                if spent + estimate > limit {
+                    return Err(Box::new(ApiError::new(
+                        ApiErrorType::OverBudget(OverBudget {
+                            period,
+                            limit,
+                            spent,
+                            estimate,
+                        }),
+                        HashMap::new(),
+                    )));
+                }
+                if spent + estimate >= limit * self.limits.warn_at {
+                    warnings.push(format!(
+                        "{:.2}c of the {period} limit of {limit:.2}c left",
+                        limit - spent
+                    ));
+                }
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Add `cost` to what has been spent
+    pub fn record(&mut self, cost: f64) -> Result<Spent, Box<dyn Error>> {
+        self.session += cost;
+        let ledger = self.update(|ledger| {
+            ledger.day_spent += cost;
+            ledger.month_spent += cost;
+        })?;
+        Ok(Spent {
+            session: self.session,
+            day: ledger.day_spent,
+            month: ledger.month_spent,
+        })
+    }
+
+    fn periods(&self, spent: &Spent) -> [(BudgetPeriod, Option<f64>, f64); 3] {
+        [
+            (BudgetPeriod::Session, self.limits.session, spent.session),
+            (BudgetPeriod::Day, self.limits.day, spent.day),
+            (BudgetPeriod::Month, self.limits.month, spent.month),
+        ]
+    }
+
+    /// Change the ledger with `f`, with it locked so other programmes
+    /// wait
+    fn update(&self, f: impl FnOnce(&mut Ledger)) -> Result<Ledger, Box<dyn Error>> {
+        if let Some(parent) = self.ledger.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.ledger)?;
+        file.lock_exclusive()?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let mut ledger: Ledger = if contents.trim().is_empty() {
+            Ledger::default()
+        } else {
+            serde_json::from_str(contents.as_str())
+                .map_err(|err| format!("{err}: {}", self.ledger.display()))?
+        };
+        ledger.roll_over();
+        f(&mut ledger);
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(serde_json::to_string(&ledger)?.as_bytes())?;
+        file.unlock()?;
+        Ok(ledger)
+    }
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let spent = self.spent().map_err(|_| fmt::Error)?;
+        let lines: Vec<String> = self
+            .periods(&spent)
+            .iter()
+            .map(|(period, limit, spent)| match limit {
+                Some(limit) => format!("{period}: {spent:.2}c of {limit:.2}c"),
+                None => format!("{period}: {spent:.2}c"),
+            })
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = dir.path().join("budget.json");
+        let limits = BudgetLimits {
+            session: Some(10.0),
+            day: Some(100.0),
+            ..Default::default()
+        };
+        let mut budget = Budget::new(limits.clone(), &ledger);
+        assert!(budget.check(5.0).unwrap().is_empty());
+        budget.record(5.0).unwrap();
+        assert_eq!(budget.check(3.0).unwrap().len(), 1);
+        let err = budget.check(6.0).unwrap_err();
+        match err.downcast_ref::<ApiError>().unwrap().error_type {
+            ApiErrorType::OverBudget(ref over) => {
+                assert_eq!(over.period, BudgetPeriod::Session);
+                assert_eq!(over.spent, 5.0);
+            }
+            ref e => panic!("{e:?}"),
+        }
+        assert_eq!(budget.remaining().unwrap(), Some(5.0));
+
+        // A new session shares the day
+        let mut budget = Budget::new(limits, &ledger);
+        budget.record(93.0).unwrap();
+        assert_eq!(
+            budget.spent().unwrap(),
+            Spent {
+                session: 93.0,
+                day: 98.0,
+                month: 98.0
+            }
+        );
+        assert_eq!(budget.remaining().unwrap(), Some(-83.0));
+        assert!(budget.check(0.0).is_err());
+
+        // An old ledger is from another day
+        fs::write(
+            &ledger,
+            r#"{"day": "2000-01-01", "day_spent": 50, "month": "2000-01", "month_spent": 50}"#,
+        )
+        .unwrap();
+        assert_eq!(budget.spent().unwrap().day, 0.0);
+        assert!(Budget::new(BudgetLimits::default(), &ledger)
+            .remaining()
+            .unwrap()
+            .is_none());
+    }
+}
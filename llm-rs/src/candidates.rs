@@ -0,0 +1,257 @@
+//! Show the choices of a chat response (see `ChatOptions::n`) side by
+//! side, and colour the tokens of a reply by how likely they were: a
+//! heat map of the model's confidence
+use crate::json::ChatChoice;
+
+/// ANSI 256 colour backgrounds for tokens at least this likely.  From
+/// confident green to doubtful red
+const HEAT: [(f64, u8); 5] = [(0.9, 28), (0.7, 100), (0.5, 136), (0.3, 166), (0.0, 124)];
+
+const RESET: &str = "\x1b[0m";
+
+/// Between the columns of `side_by_side`
+const GUTTER: &str = " | ";
+
+/// A piece of a reply: a token if there are log probabilities, a word
+/// otherwise
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    /// From 0 to 1.  `None` if it is not known
+    pub probability: Option<f64>,
+}
+
+impl Span {
+    /// `text` coloured by the probability.  Colour does not cross
+    /// line ends
+    fn paint(&self, text: &str) -> String {
+        match self.probability {
+            Some(p) if !text.is_empty() => {
+                format!("\x1b[97;48;5;{}m{text}{RESET}", colour(p))
+            }
+            _ => text.to_string(),
+        }
+    }
+}
+
+/// The ANSI colour for a token with probability `p`
+fn colour(p: f64) -> u8 {
+    HEAT.iter()
+        .find(|(min, _)| p >= *min)
+        .map(|(_, c)| *c)
+        .unwrap_or(HEAT[HEAT.len() - 1].1)
+}
+
+/// The reply in `choice` in pieces.  Tokens with their probabilities
+/// if `heat` and the log probabilities were returned, or words
+pub fn spans(choice: &ChatChoice, heat: bool) -> Vec<Span> {
+    if heat && !choice.tokens().is_empty() {
+        choice
+            .tokens()
+            .iter()
+            .map(|t| Span {
+                text: t.token.clone(),
+                probability: Some(t.probability()),
+            })
+            .collect()
+    } else {
+        choice
+            .message
+            .content
+            .split_inclusive([' ', '\n'])
+            .map(|w| Span {
+                text: w.to_string(),
+                probability: None,
+            })
+            .collect()
+    }
+}
+
+/// How confident the model was in the whole reply: the geometric
+/// mean of the token probabilities.  `None` without log probabilities
+pub fn confidence(choice: &ChatChoice) -> Option<f64> {
+    let tokens = choice.tokens();
+    if tokens.is_empty() {
+        None
+    } else {
+        Some((tokens.iter().map(|t| t.logprob).sum::<f64>() / tokens.len() as f64).exp())
+    }
+}
+
+/// The reply in `choice` with every token coloured by its probability
+pub fn heat_map(choice: &ChatChoice) -> String {
+    spans(choice, true)
+        .iter()
+        .map(|s| {
+            s.text
+                .split('\n')
+                .map(|line| s.paint(line))
+                .collect::<Vec<String>>()
+                .join("\n")
+        })
+        .collect()
+}
+
+/// What the colours of a heat map mean
+pub fn legend() -> String {
+    let mut max = 1.0;
+    HEAT.iter()
+        .map(|(min, _)| {
+            let span = Span {
+                text: format!(" {:.0}-{:.0}% ", min * 100.0, max * 100.0),
+                probability: Some(*min),
+            };
+            max = *min;
+            span.paint(span.text.as_str())
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// The choices in columns that fit in `width` characters, numbered
+/// from 1 with their confidence, and coloured if `heat`
+pub fn side_by_side(choices: &[ChatChoice], heat: bool, width: usize) -> String {
+    if choices.is_empty() {
+        return String::new();
+    }
+    let gutters = GUTTER.len() * (choices.len() - 1);
+    let column = (width.saturating_sub(gutters) / choices.len()).max(10);
+    let columns: Vec<Vec<(String, usize)>> = choices
+        .iter()
+        .enumerate()
+        .map(|(i, choice)| {
+            let header = match confidence(choice) {
+                Some(c) => format!("[{}] {:.0}%", i + 1, c * 100.0),
+                None => format!("[{}]", i + 1),
+            };
+            let header: String = header.chars().take(column).collect();
+            let mut lines = vec![(header.clone(), header.chars().count())];
+            lines.append(&mut wrap(&spans(choice, heat), column));
+            lines
+        })
+        .collect();
+    let rows = columns.iter().map(|c| c.len()).max().unwrap_or(0);
+    let mut result: Vec<String> = vec![];
+    for row in 0..rows {
+        let line: Vec<String> = columns
+            .iter()
+            .map(|c| match c.get(row) {
+                Some((text, len)) => format!("{text}{}", " ".repeat(column - len)),
+                None => " ".repeat(column),
+            })
+            .collect();
+        result.push(line.join(GUTTER).trim_end().to_string());
+    }
+    result.join("\n")
+}
+
+/// Lay `spans` out in lines no wider than `width`.  Each line is the
+/// painted text and how many characters show
+fn wrap(spans: &[Span], width: usize) -> Vec<(String, usize)> {
+    let mut lines: Vec<(String, usize)> = vec![];
+    let mut line = String::new();
+    let mut len = 0;
+    for span in spans {
+        for (i, part) in span.text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push((line, len));
+                line = String::new();
+                len = 0;
+            }
+            let mut part: Vec<char> = part.chars().collect();
+            if len + part.len() > width && len > 0 {
+                lines.push((line, len));
+                line = String::new();
+                len = 0;
+            }
+            if len == 0 {
+                // No spaces at the start of a wrapped line
+                while part.first() == Some(&' ') {
+                    part.remove(0);
+                }
+            }
+            // Break up pieces too long for a line
+            while len + part.len() > width {
+                let (head, tail) = part.split_at(width - len);
+                line.push_str(span.paint(&head.iter().collect::<String>()).as_str());
+                lines.push((line, width));
+                line = String::new();
+                len = 0;
+                part = tail.to_vec();
+            }
+            len += part.len();
+            line.push_str(span.paint(&part.iter().collect::<String>()).as_str());
+        }
+    }
+    if len > 0 {
+        lines.push((line, len));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn choice(content: &str, logprobs: Option<&[f64]>) -> ChatChoice {
+        let mut choice = serde_json::json!({
+            "index": 0,
+            "message": {"role": "assistant", "content": content},
+            "finish_reason": "stop",
+        });
+        if let Some(logprobs) = logprobs {
+            let tokens: Vec<serde_json::Value> = content
+                .split_inclusive(' ')
+                .zip(logprobs)
+                .map(|(t, l)| serde_json::json!({"token": t, "logprob": l, "top_logprobs": []}))
+                .collect();
+            choice["logprobs"] = serde_json::json!({ "content": tokens });
+        }
+        serde_json::from_value(choice).unwrap()
+    }
+
+    fn plain(text: &str) -> String {
+        Regex::new("\x1b\\[[0-9;]*m")
+            .unwrap()
+            .replace_all(text, "")
+            .to_string()
+    }
+
+    #[test]
+    fn colours() {
+        let c = choice("sure maybe", Some(&[-0.01, -1.5]));
+        let heat = heat_map(&c);
+        assert!(heat.starts_with("\x1b[97;48;5;28msure "));
+        assert!(heat.contains("\x1b[97;48;5;124mmaybe"));
+        assert_eq!(plain(heat.as_str()), "sure maybe");
+        let confidence = confidence(&c).unwrap();
+        assert!((confidence - (-0.755_f64).exp()).abs() < 1e-9);
+        assert_eq!(
+            super::confidence(&choice("No log probabilities", None)),
+            None
+        );
+    }
+
+    #[test]
+    fn columns() {
+        let choices = vec![
+            choice("The quick brown fox jumps over the lazy dog", None),
+            choice("Short", Some(&[-0.1])),
+        ];
+        // Two columns of 18 and a gutter
+        let text = side_by_side(&choices, true, 40);
+        let lines: Vec<String> = text.lines().map(plain).collect();
+        assert_eq!(lines[0], format!("[1]{}| [2] 90%", " ".repeat(16)));
+        assert_eq!(lines[1], "The quick brown    | Short");
+        assert!(lines.iter().all(|l| l.chars().count() <= 40));
+        let left: Vec<String> = lines[1..]
+            .iter()
+            .map(|l| l.chars().take(18).collect::<String>().trim().to_string())
+            .collect();
+        assert_eq!(
+            left.join(" "),
+            "The quick brown fox jumps over the lazy dog"
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! Record the traffic with OpenAI to a file (a "cassette"), and play
+//! it back without the network.  For reproducible demonstrations,
+//! and regression tests of real conversations.
+//!
+//! A cassette is JSONL, one request and its response on each line.
+//! Secrets are redacted before anything is written: the API key
+//! wherever it appears, and the headers that identify the account.
+//!
+//! A cassette is a `Transport`.  When recording it wraps the
+//! transport that does the sending.  When replaying, a request is
+//! answered by the first unused interaction with the same method,
+//! path and body.  If they have all been used the last of them is
+//! used again, so polling works
+use crate::api_error::ApiError;
+use crate::api_error::ApiErrorType;
+use crate::transport::Transport;
+use base64::decode;
+use base64::encode;
+use reqwest::blocking::Request;
+use reqwest::blocking::Response;
+use reqwest::header::AUTHORIZATION;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// What replaces secrets
+pub const REDACTED: &str = "REDACTED";
+
+/// Headers whose values are never written to a cassette
+const SECRET_HEADERS: [&str; 5] = [
+    "authorization",
+    "cookie",
+    "set-cookie",
+    "openai-organization",
+    "openai-project",
+];
+
+/// Headers that describe the body as it was sent, not as it is
+/// stored, so are not played back
+const TRANSPORT_HEADERS: [&str; 3] = ["content-length", "content-encoding", "transfer-encoding"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Send requests and write them, and their responses, to the
+    /// cassette
+    Record,
+    /// Answer requests from the cassette.  Nothing is sent
+    Replay,
+}
+
+impl fmt::Display for CassetteMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CassetteMode::Record => write!(f, "record"),
+            CassetteMode::Replay => write!(f, "replay"),
+        }
+    }
+}
+
+/// A request and its response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    /// The path and query.  Not the host, so a cassette can be
+    /// replayed whatever `ApiInterface::api_url` is
+    pub path: String,
+    pub request_headers: BTreeMap<String, String>,
+    /// Empty if the body could not be read, as for uploads
+    pub request_body: String,
+    pub status: u16,
+    pub response_headers: BTreeMap<String, String>,
+    pub response_body: String,
+    /// `response_body` is base64 because the response was not text
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub base64: bool,
+}
+
+impl Interaction {
+    /// Build an interaction with the secrets (like the API key)
+    /// removed
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        method: &str,
+        url: &str,
+        request_headers: &HashMap<String, String>,
+        request_body: &str,
+        status: u16,
+        response_headers: &HashMap<String, String>,
+        response_body: &[u8],
+        secrets: &[&str],
+    ) -> Self {
+        let (response_body, base64) = match std::str::from_utf8(response_body) {
+            Ok(text) => (redact(text, secrets), false),
+            Err(_) => (encode(response_body), true),
+        };
+        Interaction {
+            method: method.to_uppercase(),
+            path: path_of(url),
+            request_headers: redact_headers(request_headers, secrets),
+            request_body: redact(request_body, secrets),
+            status,
+            response_headers: redact_headers(response_headers, secrets),
+            response_body,
+            base64,
+        }
+    }
+
+    /// The response body as it was received
+    pub fn body(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        if self.base64 {
+            Ok(decode(self.response_body.as_str())?)
+        } else {
+            Ok(self.response_body.clone().into_bytes())
+        }
+    }
+
+    /// The response headers, without those that described how the
+    /// body was sent
+    pub fn headers(&self) -> HashMap<String, String> {
+        self.response_headers
+            .iter()
+            .filter(|(k, _)| !TRANSPORT_HEADERS.contains(&k.to_lowercase().as_str()))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    /// Rebuild the response
+    pub fn to_response(&self) -> Result<Response, Box<dyn Error>> {
+        let mut builder = http::Response::builder().status(self.status);
+        for (name, value) in self.headers() {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        Ok(Response::from(builder.body(self.body()?)?))
+    }
+
+    /// This is the recording of a request for `method`, `path` and
+    /// `body`.  JSON bodies are compared as JSON
+    pub(crate) fn matches(&self, method: &str, path: &str, body: &str) -> bool {
+        if self.method != method.to_uppercase() || self.path != path {
+            return false;
+        }
+        match (
+            serde_json::from_str::<serde_json::Value>(self.request_body.as_str()),
+            serde_json::from_str::<serde_json::Value>(body),
+        ) {
+            (Ok(recorded), Ok(body)) => recorded == body,
+            _ => self.request_body == body,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CassetteState {
+    interactions: Vec<Interaction>,
+    used: Vec<bool>,
+    /// Where recordings are written
+    file: Option<File>,
+}
+
+/// A file of recorded interactions.  Shared between threads, so it
+/// can be used by the web backend
+#[derive(Debug)]
+pub struct Cassette {
+    mode: CassetteMode,
+    path: PathBuf,
+    state: Mutex<CassetteState>,
+    /// What sends requests when recording
+    inner: Option<Arc<dyn Transport>>,
+}
+
+impl Cassette {
+    /// Record to `path` what `inner` sends and receives.  Anything
+    /// already at `path` is replaced
+    pub fn record(path: &Path, inner: Arc<dyn Transport>) -> Result<Self, Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Cassette {
+            mode: CassetteMode::Record,
+            path: path.to_path_buf(),
+            state: Mutex::new(CassetteState {
+                interactions: vec![],
+                used: vec![],
+                file: Some(file),
+            }),
+            inner: Some(inner),
+        })
+    }
+
+    /// Replay what was recorded at `path`
+    pub fn replay(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut interactions: Vec<Interaction> = vec![];
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            if !line.trim().is_empty() {
+                interactions.push(
+                    serde_json::from_str(line)
+                        .map_err(|err| format!("{}:{}: {err}", path.display(), i + 1))?,
+                );
+            }
+        }
+        Ok(Cassette {
+            mode: CassetteMode::Replay,
+            path: path.to_path_buf(),
+            state: Mutex::new(CassetteState {
+                used: vec![false; interactions.len()],
+                interactions,
+                file: None,
+            }),
+            inner: None,
+        })
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// How many interactions are on the cassette
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().interactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add an interaction to the cassette.  It is written straight
+    /// away so nothing is lost if the programme stops
+    pub fn push(&self, interaction: Interaction) -> Result<(), Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(ref mut file) = state.file {
+            writeln!(file, "{}", serde_json::to_string(&interaction)?)?;
+            file.flush()?;
+        }
+        state.interactions.push(interaction);
+        state.used.push(true);
+        Ok(())
+    }
+
+    /// The recorded answer to a request.  An error if there is none
+    pub fn find(&self, method: &str, url: &str, body: &str) -> Result<Interaction, Box<dyn Error>> {
+        let path = path_of(url);
+        let mut state = self.state.lock().unwrap();
+        let matching: Vec<usize> = state
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| i.matches(method, path.as_str(), body))
+            .map(|(n, _)| n)
+            .collect();
+        let found = match matching.iter().find(|n| !state.used[**n]) {
+            Some(n) => *n,
+            None => match matching.last() {
+                Some(n) => *n,
+                None => {
+                    return Err(Box::new(ApiError::new(
+                        ApiErrorType::Error(format!(
+                            "Not on cassette {}: {method} {path}",
+                            self.path.display()
+                        )),
+                        HashMap::new(),
+                    )))
+                }
+            },
+        };
+        state.used[found] = true;
+        Ok(state.interactions[found].clone())
+    }
+}
+
+impl Transport for Cassette {
+    /// Send `request`, and record it, or answer it from the cassette.
+    /// The API key, taken from the request, is redacted
+    fn execute(&self, request: Request) -> Result<Response, Box<dyn Error>> {
+        let method = request.method().to_string();
+        let url = request.url().to_string();
+        let key = api_key_of(&request);
+        let secrets = [key.as_str()];
+        let body = body_of(&request);
+        match self.inner {
+            None => self
+                .find(
+                    method.as_str(),
+                    url.as_str(),
+                    redact(body.as_str(), &secrets).as_str(),
+                )?
+                .to_response(),
+            Some(ref inner) => {
+                let request_headers = header_map(request.headers());
+                let response = inner.execute(request)?;
+                let status = response.status().as_u16();
+                let response_headers = header_map(response.headers());
+                let response_body = response.bytes()?;
+                let interaction = Interaction::new(
+                    method.as_str(),
+                    url.as_str(),
+                    &request_headers,
+                    body.as_str(),
+                    status,
+                    &response_headers,
+                    &response_body,
+                    &secrets,
+                );
+                let response = interaction.to_response()?;
+                self.push(interaction)?;
+                Ok(response)
+            }
+        }
+    }
+}
+
+/// The API key `request` is sent with
+pub(crate) fn api_key_of(request: &Request) -> String {
+    request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("")
+        .to_string()
+}
+
+/// The body of `request` as text.  Multipart bodies are streamed, and
+/// cannot be read here, so are empty
+pub(crate) fn body_of(request: &Request) -> String {
+    request
+        .body()
+        .and_then(|b| b.as_bytes())
+        .map(|b| String::from_utf8_lossy(b).to_string())
+        .unwrap_or_default()
+}
+
+/// The path and query of `url`.  `url` itself if it cannot be parsed
+pub(crate) fn path_of(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(url) => match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        },
+        Err(_) => url.to_string(),
+    }
+}
+
+/// Replace every appearance of a secret in `text`
+pub fn redact(text: &str, secrets: &[&str]) -> String {
+    secrets
+        .iter()
+        .filter(|s| !s.is_empty())
+        .fold(text.to_string(), |text, secret| {
+            text.replace(secret, REDACTED)
+        })
+}
+
+/// Headers with the values of secret headers, and secrets, removed
+fn redact_headers(headers: &HashMap<String, String>, secrets: &[&str]) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SECRET_HEADERS.contains(&name.to_lowercase().as_str()) {
+                REDACTED.to_string()
+            } else {
+                redact(value, secrets)
+            };
+            (name.clone(), value)
+        })
+        .collect()
+}
+
+pub(crate) fn header_map(headers: &reqwest::header::HeaderMap) -> HashMap<String, String> {
+    headers
+        .iter()
+        .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.to_string(), v.to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openai_interface::ApiInterface;
+    use llm_mock::MockServer;
+
+    const KEY: &str = "sk-secret-key";
+
+    #[test]
+    fn record_then_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassette.jsonl");
+        let server = MockServer::start().unwrap();
+
+        let mut recording = ApiInterface::new(KEY.to_string(), 100, 0.5);
+        recording.api_url = server.base_url();
+        recording.transport =
+            Arc::new(Cassette::record(&path, recording.transport.clone()).unwrap());
+        let reply = recording.chat("Hello", "gpt-4o-mini").unwrap().body;
+        let models = recording.models().unwrap().body.data.len();
+        drop(server);
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        assert!(!contents.contains(KEY));
+        assert!(contents.contains(REDACTED));
+
+        // Nothing is listening here
+        let mut replaying = ApiInterface::new(KEY.to_string(), 100, 0.5);
+        replaying.api_url = "http://127.0.0.1:9/v1".to_string();
+        replaying.transport = Arc::new(Cassette::replay(&path).unwrap());
+        assert_eq!(replaying.chat("Hello", "gpt-4o-mini").unwrap().body, reply);
+        assert_eq!(replaying.models().unwrap().body.data.len(), models);
+        assert!(replaying.chat("Something else", "gpt-4o-mini").is_err());
+    }
+
+    #[test]
+    fn redaction() {
+        let headers: HashMap<String, String> = [
+            ("Authorization".to_string(), format!("Bearer {KEY}")),
+            ("x-echo".to_string(), format!("key={KEY}")),
+        ]
+        .into_iter()
+        .collect();
+        let interaction = Interaction::new(
+            "post",
+            "https://api.openai.com/v1/files?purpose=batch",
+            &headers,
+            format!("{{\"key\": \"{KEY}\"}}").as_str(),
+            200,
+            &HashMap::new(),
+            &[0xff, 0xfe],
+            &[KEY],
+        );
+        assert_eq!(interaction.path, "/v1/files?purpose=batch");
+        assert_eq!(interaction.request_headers["Authorization"], REDACTED);
+        assert_eq!(interaction.request_headers["x-echo"], "key=REDACTED");
+        assert!(interaction.matches("POST", "/v1/files?purpose=batch", r#"{"key":"REDACTED"}"#));
+        assert_eq!(interaction.body().unwrap(), vec![0xff, 0xfe]);
+    }
+}
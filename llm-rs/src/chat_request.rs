@@ -0,0 +1,259 @@
+//! Build the body of a chat request to suit the model.  Reasoning
+//! models (o1 and later) reject things older models accept: some
+//! take no "system" message, most take no `temperature`, and all of
+//! them want `max_completion_tokens` instead of `max_tokens`.  Some
+//! reject `stop`, `seed` or `n`, so they are never sent them.  What
+//! a model accepts comes from its capabilities, see `model_registry`.
+//! The sampling parameters of a request are in `ChatOptions`, shared
+//! with the web front and back ends in `llm-chat-options`
+use crate::json::Message;
+use crate::json::Usage;
+use crate::model_registry::Capability;
+pub use llm_chat_options::ChatOptions;
+pub use llm_chat_options::CHAT_OPTION_NAMES;
+use serde_json::json;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Prices in cents per million tokens: (model prefix, prompt,
+/// completion).  The longest prefix of a model's name that is here
+/// decides its price.  Reasoning tokens are billed as completion
+/// tokens, and are counted in `completion_tokens`.  The first GPT-4
+/// models and GPT-3.5 are priced in `ApiInterface::cost`
+const PRICES: [(&str, f64, f64); 20] = [
+    ("gpt-4-turbo", 1_000.0, 3_000.0),
+    ("gpt-4o", 250.0, 1_000.0),
+    ("gpt-4o-mini", 15.0, 60.0),
+    ("gpt-4.1", 200.0, 800.0),
+    ("gpt-4.1-mini", 40.0, 160.0),
+    ("gpt-4.1-nano", 10.0, 40.0),
+    ("gpt-4.5", 7_500.0, 15_000.0),
+    ("o1", 1_500.0, 6_000.0),
+    ("o1-mini", 300.0, 1_200.0),
+    ("o1-preview", 1_500.0, 6_000.0),
+    ("o3", 200.0, 800.0),
+    ("o3-mini", 110.0, 440.0),
+    ("o4-mini", 110.0, 440.0),
+    ("gpt-5", 125.0, 1_000.0),
+    ("gpt-5-mini", 25.0, 200.0),
+    ("gpt-5-nano", 5.0, 40.0),
+    ("gpt-5-chat", 125.0, 1_000.0),
+    ("chatgpt-4o", 500.0, 1_500.0),
+    ("davinci-002", 200.0, 200.0),
+    ("babbage-002", 40.0, 40.0),
+];
+
+/// The JSON for a chat request with `messages` to `model`.  Options
+/// are only sent if the model takes them: models that do not take a
+/// `temperature` take none of the sampling parameters, and reasoning
+/// models take no `stop`, `seed` or `n`.  A model with no known
+/// capabilities is assumed to take everything
+pub fn chat_request_body(
+    model: &str,
+    messages: &[Message],
+    options: &ChatOptions,
+    capabilities: &BTreeSet<Capability>,
+) -> Value {
+    let unknown = capabilities.is_empty();
+    let messages: Vec<Message> = if unknown || capabilities.contains(&Capability::SystemRole) {
+        messages.to_vec()
+    } else {
+        fold_system_prompt(messages)
+    };
+    let mut body = json!({
+        "model": model,
+        "messages": messages,
+    });
+    if unknown || capabilities.contains(&Capability::Temperature) {
+        if let Some(temperature) = options.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = options.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if let Some(presence_penalty) = options.presence_penalty {
+            body["presence_penalty"] = json!(presence_penalty);
+        }
+        if let Some(frequency_penalty) = options.frequency_penalty {
+            body["frequency_penalty"] = json!(frequency_penalty);
+        }
+        if let Some(top_logprobs) = options.logprobs {
+            body["logprobs"] = json!(true);
+            body["top_logprobs"] = json!(top_logprobs);
+        }
+        if !options.logit_bias.is_empty() {
+            body["logit_bias"] = json!(options.logit_bias);
+        }
+    }
+    if let Some(max_tokens) = options.max_tokens {
+        if capabilities.contains(&Capability::Reasoning) {
+            body["max_completion_tokens"] = json!(max_tokens);
+        } else {
+            body["max_tokens"] = json!(max_tokens);
+        }
+    }
+    if !capabilities.contains(&Capability::Reasoning) {
+        if !options.stop.is_empty() {
+            body["stop"] = json!(options.stop);
+        }
+        if let Some(seed) = options.seed {
+            body["seed"] = json!(seed);
+        }
+        if let Some(n) = options.n {
+            body["n"] = json!(n);
+        }
+    }
+    if let Some(ref user) = options.user {
+        body["user"] = json!(user);
+    }
+    body
+}
+
+/// Remove the system messages, and put what they said at the start
+/// of the first user message
+pub fn fold_system_prompt(messages: &[Message]) -> Vec<Message> {
+    let system: Vec<&str> = messages
+        .iter()
+        .filter(|m| m.role == "system" && !m.content.trim().is_empty())
+        .map(|m| m.content.as_str())
+        .collect();
+    let mut result: Vec<Message> = messages
+        .iter()
+        .filter(|m| m.role != "system")
+        .cloned()
+        .collect();
+    if !system.is_empty() {
+        let system = system.join("\n\n");
+        match result.iter_mut().find(|m| m.role == "user") {
+            Some(user) => user.content = format!("{system}\n\n{}", user.content),
+            None => result.insert(
+                0,
+                Message {
+                    role: "user".to_string(),
+                    content: system,
+                },
+            ),
+        }
+    }
+    result
+}
+
+/// The cost in cents of `usage` by `model`.  `None` if the model is
+/// not in `PRICES`
+pub fn listed_cost(usage: &Usage, model: &str) -> Option<f64> {
+    PRICES
+        .iter()
+        .filter(|(prefix, _, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _, _)| prefix.len())
+        .map(|(_, prompt, completion)| {
+            (usage.prompt_tokens as f64 * prompt + usage.completion_tokens as f64 * completion)
+                / 1_000_000.0
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_registry::ModelRegistry;
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message {
+                role: "system".to_string(),
+                content: "Be terse".to_string(),
+            },
+            Message {
+                role: "user".to_string(),
+                content: "Hello".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn classic_model() {
+        let registry = ModelRegistry::default();
+        let body = chat_request_body(
+            "gpt-4o-mini",
+            &messages(),
+            &ChatOptions::new().temperature(0.5).max_tokens(100),
+            &registry.capabilities("gpt-4o-mini"),
+        );
+        assert_eq!(body["messages"].as_array().unwrap().len(), 2);
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["max_tokens"], 100);
+        assert!(body.get("max_completion_tokens").is_none());
+    }
+
+    #[test]
+    fn reasoning_model() {
+        let registry = ModelRegistry::default();
+        let body = chat_request_body(
+            "o1-mini",
+            &messages(),
+            &ChatOptions::new()
+                .temperature(0.5)
+                .max_tokens(100)
+                .top_p(0.9)
+                .stop("END")
+                .seed(7)
+                .n(2),
+            &registry.capabilities("o1-mini"),
+        );
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["role"], "user");
+        assert_eq!(messages[0]["content"], "Be terse\n\nHello");
+        assert!(body.get("temperature").is_none());
+        assert!(body.get("top_p").is_none());
+        assert!(body.get("max_tokens").is_none());
+        assert_eq!(body["max_completion_tokens"], 100);
+        assert!(body.get("stop").is_none());
+        assert!(body.get("seed").is_none());
+        assert!(body.get("n").is_none());
+    }
+
+    #[test]
+    fn all_options() {
+        let mut options = ChatOptions::new()
+            .top_p(0.9)
+            .presence_penalty(0.5)
+            .frequency_penalty(-0.5)
+            .stop("END")
+            .seed(42)
+            .n(3)
+            .logprobs(2)
+            .logit_bias(50256, -100)
+            .user("alice");
+        options.set("stop", Some("\n\n")).unwrap();
+        let body = chat_request_body("gpt-4o", &messages(), &options, &BTreeSet::new());
+        assert_eq!(body["top_p"], json!(0.9_f32));
+        assert_eq!(body["presence_penalty"], 0.5);
+        assert_eq!(body["frequency_penalty"], -0.5);
+        assert_eq!(body["stop"], json!(["END", "\n\n"]));
+        assert_eq!(body["seed"], 42);
+        assert_eq!(body["n"], 3);
+        assert_eq!(body["logprobs"], true);
+        assert_eq!(body["top_logprobs"], 2);
+        assert_eq!(body["logit_bias"], json!({"50256": -100}));
+        assert_eq!(body["user"], "alice");
+        assert!(body.get("temperature").is_none());
+        assert!(body.get("max_tokens").is_none());
+    }
+
+    #[test]
+    fn reasoning_prices() {
+        let usage = Usage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 1_000_000,
+            total_tokens: 2_000_000,
+            completion_tokens_details: None,
+        };
+        assert_eq!(listed_cost(&usage, "o1-mini-2024-09-12"), Some(1_500.0));
+        assert_eq!(listed_cost(&usage, "o1-2024-12-17"), Some(7_500.0));
+        assert_eq!(listed_cost(&usage, "gpt-4o-2024-08-06"), Some(1_250.0));
+        assert_eq!(listed_cost(&usage, "gpt-4o-mini"), Some(75.0));
+        assert_eq!(listed_cost(&usage, "gpt-4.1-nano"), Some(50.0));
+        assert_eq!(listed_cost(&usage, "chatgpt-4o-latest"), Some(2_000.0));
+        assert_eq!(listed_cost(&usage, "gpt-4-0613"), None);
+    }
+}
@@ -0,0 +1,252 @@
+//! Options for the completions endpoint, and filling a gap in a file
+//! from the text around it (fill in the middle).  Completions are for
+//! instruct models like `gpt-3.5-turbo-instruct`: the older
+//! `text-davinci-*` models are gone
+use crate::json::CompletionRequestInfo;
+use serde::Deserialize;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+
+/// A model for completions that takes a `suffix`
+pub const DEFAULT_COMPLETIONS_MODEL: &str = "gpt-3.5-turbo-instruct";
+
+/// Where the gap to fill is in a file
+pub const FILL_MARKER: &str = "<FILL>";
+
+/// At most this many characters from before the gap are sent
+const FILL_PREFIX_CHARS: usize = 8_000;
+
+/// At most this many characters from after the gap are sent
+const FILL_SUFFIX_CHARS: usize = 4_000;
+
+/// The names `CompletionOptions::set` knows
+pub const COMPLETION_OPTION_NAMES: [&str; 5] = ["suffix", "echo", "stop", "best_of", "logprobs"];
+
+/// The optional parameters of a completion request, besides
+/// `temperature` and `max_tokens`.  Unset options are not sent
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompletionOptions {
+    /// The text that comes after the completion
+    pub suffix: Option<String>,
+    /// Return the prompt in front of the completion
+    pub echo: Option<bool>,
+    /// Up to four sequences where generation stops
+    pub stop: Vec<String>,
+    /// Generate this many completions on the server and return the
+    /// one with the highest log probability per token
+    pub best_of: Option<u32>,
+    /// Return the log probabilities of the generated tokens, with this
+    /// many (0 to 5) of the most likely alternatives
+    pub logprobs: Option<u8>,
+}
+
+impl CompletionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn suffix(mut self, suffix: &str) -> Self {
+        self.suffix = Some(suffix.to_string());
+        self
+    }
+    pub fn echo(mut self, echo: bool) -> Self {
+        self.echo = Some(echo);
+        self
+    }
+    pub fn stop(mut self, stop: &str) -> Self {
+        self.stop.push(stop.to_string());
+        self
+    }
+    pub fn best_of(mut self, best_of: u32) -> Self {
+        self.best_of = Some(best_of);
+        self
+    }
+    pub fn logprobs(mut self, logprobs: u8) -> Self {
+        self.logprobs = Some(logprobs);
+        self
+    }
+
+    /// Set the option called `name` from text, as typed by a user.
+    /// `None` clears it.  "stop" adds a sequence
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let value = match value {
+            Some(value) => value,
+            None => {
+                match name {
+                    "suffix" => self.suffix = None,
+                    "echo" => self.echo = None,
+                    "stop" => self.stop.clear(),
+                    "best_of" => self.best_of = None,
+                    "logprobs" => self.logprobs = None,
+                    _ => return Err(format!("Unknown completion option: {name}").into()),
+                };
+                return Ok(());
+            }
+        };
+        match name {
+            "suffix" => self.suffix = Some(value.to_string()),
+            "echo" => self.echo = Some(value.parse()?),
+            "stop" => {
+                if self.stop.len() == 4 {
+                    return Err("At most four stop sequences".into());
+                }
+                self.stop.push(value.to_string());
+            }
+            "best_of" => {
+                let best_of: u32 = value.parse()?;
+                if best_of == 0 {
+                    return Err("best_of must be at least 1".into());
+                }
+                self.best_of = Some(best_of);
+            }
+            "logprobs" => {
+                let logprobs: u8 = value.parse()?;
+                if logprobs > 5 {
+                    return Err("logprobs is from 0 to 5".into());
+                }
+                self.logprobs = Some(logprobs);
+            }
+            _ => return Err(format!("Unknown completion option: {name}").into()),
+        };
+        Ok(())
+    }
+
+    /// Put the options in a request
+    pub fn apply(&self, request: &mut CompletionRequestInfo) {
+        request.suffix = self.suffix.clone();
+        request.echo = self.echo;
+        request.stop = self.stop.clone();
+        request.best_of = self.best_of;
+        request.logprobs = self.logprobs;
+    }
+}
+
+impl fmt::Display for CompletionOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut lines: Vec<String> = vec![];
+        if let Some(ref suffix) = self.suffix {
+            lines.push(format!("suffix: {suffix:?}"));
+        }
+        if let Some(echo) = self.echo {
+            lines.push(format!("echo: {echo}"));
+        }
+        if !self.stop.is_empty() {
+            lines.push(format!("stop: {:?}", self.stop));
+        }
+        if let Some(best_of) = self.best_of {
+            lines.push(format!("best_of: {best_of}"));
+        }
+        if let Some(logprobs) = self.logprobs {
+            lines.push(format!("logprobs: {logprobs}"));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// A text with a gap, marked with `FILL_MARKER`, for a completion
+/// to fill
+#[derive(Debug, Clone, PartialEq)]
+pub struct FillInMiddle {
+    /// The text before the gap
+    pub prefix: String,
+    /// The text after the gap
+    pub suffix: String,
+}
+
+impl FillInMiddle {
+    /// Split `text` at `marker`.  There must be exactly one
+    pub fn new(text: &str, marker: &str) -> Result<Self, Box<dyn Error>> {
+        match text.matches(marker).count() {
+            0 => Err(format!("No {marker} in the text").into()),
+            1 => {
+                let (prefix, suffix) = text.split_once(marker).unwrap();
+                Ok(FillInMiddle {
+                    prefix: prefix.to_string(),
+                    suffix: suffix.to_string(),
+                })
+            }
+            n => Err(format!("{n} of {marker} in the text.  Only one gap at a time").into()),
+        }
+    }
+
+    /// The prompt to send: the end of the text before the gap
+    pub fn prompt(&self) -> &str {
+        tail(self.prefix.as_str(), FILL_PREFIX_CHARS)
+    }
+
+    /// The options to send with `prompt`: the start of the text after
+    /// the gap as the suffix
+    pub fn options(&self, options: &CompletionOptions) -> CompletionOptions {
+        let mut options = options.clone();
+        options.suffix = Some(head(self.suffix.as_str(), FILL_SUFFIX_CHARS).to_string());
+        options.echo = None;
+        options
+    }
+
+    /// The whole text with `middle` in the gap
+    pub fn fill(&self, middle: &str) -> String {
+        format!("{}{middle}{}", self.prefix, self.suffix)
+    }
+}
+
+/// The last `n` characters of `s`
+fn tail(s: &str, n: usize) -> &str {
+    if n == 0 {
+        return "";
+    }
+    match s.char_indices().rev().nth(n - 1) {
+        Some((i, _)) => &s[i..],
+        None => s,
+    }
+}
+
+/// The first `n` characters of `s`
+fn head(s: &str, n: usize) -> &str {
+    match s.char_indices().nth(n) {
+        Some((i, _)) => &s[..i],
+        None => s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_in_request() {
+        let mut options = CompletionOptions::new().echo(true).best_of(3);
+        options.set("stop", Some("\n\n")).unwrap();
+        options.set("logprobs", Some("2")).unwrap();
+        assert!(options.set("logprobs", Some("6")).is_err());
+        assert!(options.set("temperature", Some("1")).is_err());
+        let mut request =
+            CompletionRequestInfo::new("fn main() {", "gpt-3.5-turbo-instruct", 0.0, 50);
+        options.apply(&mut request);
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["echo"], true);
+        assert_eq!(json["best_of"], 3);
+        assert_eq!(json["stop"], serde_json::json!(["\n\n"]));
+        assert_eq!(json["logprobs"], 2);
+        assert!(json.get("suffix").is_none());
+    }
+
+    #[test]
+    fn fill_a_gap() {
+        let text = "fn add(a: i32, b: i32) -> i32 {\n    <FILL>\n}\n";
+        let fim = FillInMiddle::new(text, FILL_MARKER).unwrap();
+        assert_eq!(fim.prompt(), "fn add(a: i32, b: i32) -> i32 {\n    ");
+        let options = fim.options(&CompletionOptions::new().echo(true));
+        assert_eq!(options.suffix.as_deref(), Some("\n}\n"));
+        assert_eq!(options.echo, None);
+        assert_eq!(
+            fim.fill("a + b"),
+            "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n"
+        );
+        assert!(FillInMiddle::new("no gap", FILL_MARKER).is_err());
+        assert!(FillInMiddle::new("<FILL> <FILL>", FILL_MARKER).is_err());
+        assert_eq!(tail("héllo", 4), "éllo");
+        assert_eq!(tail("héllo", 9), "héllo");
+        assert_eq!(head("héllo", 2), "hé");
+    }
+}
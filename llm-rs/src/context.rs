@@ -1,4 +1,5 @@
 /// The context of a GPT Chat
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::mem;
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -6,6 +7,14 @@ pub struct Context {
     pub purpose: String,
     pub cost: f64, // IN cents, and fraction of a cent
     pub prompt_response: Vec<String>,
+    /// The model the conversation was with.  Not in contexts saved
+    /// by older versions
+    #[serde(default)]
+    pub model: Option<String>,
+    /// When each of `prompt_response` was added.  Not in contexts
+    /// saved by older versions
+    #[serde(default)]
+    pub times: Vec<DateTime<Utc>>,
 }
 
 impl Context {
@@ -14,6 +23,8 @@ impl Context {
             purpose: purpose.to_string(),
             cost: 0.0,
             prompt_response: Vec::new(),
+            model: None,
+            times: Vec::new(),
         }
     }
     pub fn len(&self) -> usize {
@@ -37,9 +48,20 @@ impl Context {
     }
     pub fn push(&mut self, s: String) {
         self.prompt_response.push(s);
+        self.times.push(Utc::now());
+    }
+    /// When the `i`th of `prompt_response` was added, if that is known
+    pub fn time(&self, i: usize) -> Option<DateTime<Utc>> {
+        // Older contexts have no times, and restored ones only have
+        // times for what was added after
+        let missing = self.prompt_response.len().checked_sub(self.times.len())?;
+        i.checked_sub(missing)
+            .and_then(|i| self.times.get(i))
+            .copied()
     }
     pub fn clear(&mut self) {
         self.prompt_response.clear();
+        self.times.clear();
         self.cost = 0.0;
     }
     pub fn sz(&self) -> usize {
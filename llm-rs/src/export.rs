@@ -0,0 +1,317 @@
+//! Conversations for people and other programmes: Markdown, a
+//! standalone HTML page, or JSON with the messages as OpenAI takes
+//! them.  Each has the system prompt, the model, the cost and when
+//! the messages were sent, if that is known
+use crate::context::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use pulldown_cmark::{html, Event, Options, Parser};
+use serde_json::json;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// The names of the formats, for help and completion
+pub const EXPORT_FORMATS: [&str; 3] = ["markdown", "html", "json"];
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; max-width: 50em; margin: 2em auto; padding: 0 1em; color: #222; background: #fafafa; line-height: 1.5; }
+header dl { display: grid; grid-template-columns: max-content auto; gap: 0.2em 1em; color: #555; }
+header dt { font-weight: bold; }
+header dd { margin: 0; }
+section { border-radius: 8px; padding: 0.5em 1em; margin: 1em 0; border: 1px solid #ddd; }
+section.system { background: #f0f0f0; }
+section.user { background: #e8f0fe; }
+section.assistant { background: #fff; }
+section h2 { font-size: 0.9em; margin: 0.3em 0; text-transform: uppercase; color: #555; }
+time { font-size: 0.8em; color: #888; }
+pre { background: #272822; color: #f8f8f2; padding: 0.8em; border-radius: 6px; overflow-x: auto; }
+code { font-family: ui-monospace, monospace; }
+:not(pre) > code { background: #eee; padding: 0.1em 0.3em; border-radius: 4px; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.5em; }
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    /// `{"metadata": {...}, "messages": [...]}`
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!(
+                "Unknown format: {s}.  Use {}",
+                EXPORT_FORMATS.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ExportFormat::Markdown => "markdown",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// `context` in `format`
+pub fn export(context: &Context, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Markdown => markdown(context),
+        ExportFormat::Html => html_page(context),
+        ExportFormat::Json => serde_json::to_string_pretty(&messages_json(context)).unwrap(),
+    }
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// The role of the `i`th of `prompt_response`
+fn role(i: usize) -> &'static str {
+    if i % 2 == 1 {
+        "assistant"
+    } else {
+        "user"
+    }
+}
+
+/// Names and values to describe the conversation
+fn metadata(context: &Context) -> Vec<(&'static str, String)> {
+    let mut result = vec![(
+        "Model",
+        context
+            .model
+            .clone()
+            .unwrap_or_else(|| "Unknown".to_string()),
+    )];
+    result.push(("Cost", format!("{:.4} cents", context.cost)));
+    if let Some(started) = (0..context.len()).find_map(|i| context.time(i)) {
+        result.push(("Started", timestamp(started)));
+    }
+    if let Some(ended) = context.len().checked_sub(1).and_then(|i| context.time(i)) {
+        result.push(("Ended", timestamp(ended)));
+    }
+    result.push(("Exported", timestamp(Utc::now())));
+    result
+}
+
+/// `text` with a fence at the end if a code block was left open, so
+/// it does not swallow what comes after it
+fn close_fences(text: &str) -> String {
+    let mut open: Option<String> = None;
+    for line in text.lines() {
+        let line = line.trim_start();
+        let fence: String = line
+            .chars()
+            .take_while(|c| *c == '`' || *c == '~')
+            .collect();
+        if fence.len() < 3 {
+            continue;
+        }
+        match open {
+            Some(ref f) if fence.starts_with(f.as_str()) && line.trim() == fence => open = None,
+            Some(_) => (),
+            None => open = Some(fence),
+        }
+    }
+    let mut result = text.trim_end().to_string();
+    if let Some(fence) = open {
+        result.push('\n');
+        result += fence.as_str();
+    }
+    result
+}
+
+/// The conversation as Markdown.  The messages are as they were,
+/// code fences and all
+pub fn markdown(context: &Context) -> String {
+    let mut result = "# Conversation\n\n".to_string();
+    for (name, value) in metadata(context) {
+        result += format!("* **{name}** {value}\n").as_str();
+    }
+    if !context.purpose.is_empty() {
+        result += format!(
+            "\n## System\n\n{}\n",
+            close_fences(context.purpose.as_str())
+        )
+        .as_str();
+    }
+    for (i, message) in context.prompt_response.iter().enumerate() {
+        let role = if i % 2 == 1 { "Assistant" } else { "User" };
+        result += format!("\n## {role}\n\n").as_str();
+        if let Some(time) = context.time(i) {
+            result += format!("*{}*\n\n", timestamp(time)).as_str();
+        }
+        result += close_fences(message.as_str()).as_str();
+        result.push('\n');
+    }
+    result
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Markdown as HTML.  HTML in it is shown, not used
+fn render(text: &str) -> String {
+    let parser = Parser::new_ext(text, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH).map(
+        |event| match event {
+            Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+            event => event,
+        },
+    );
+    let mut result = String::new();
+    html::push_html(&mut result, parser);
+    result
+}
+
+/// The conversation as a web page, with its styles in it
+pub fn html_page(context: &Context) -> String {
+    let title = format!(
+        "Conversation with {}",
+        context.model.as_deref().unwrap_or("a model")
+    );
+    let mut body = format!("<header>\n<h1>{}</h1>\n<dl>\n", escape_html(title.as_str()));
+    for (name, value) in metadata(context) {
+        body += format!("<dt>{name}</dt><dd>{}</dd>\n", escape_html(value.as_str())).as_str();
+    }
+    body += "</dl>\n</header>\n";
+    if !context.purpose.is_empty() {
+        body += format!(
+            "<section class=\"system\">\n<h2>System</h2>\n{}</section>\n",
+            render(context.purpose.as_str())
+        )
+        .as_str();
+    }
+    for (i, message) in context.prompt_response.iter().enumerate() {
+        let time = match context.time(i) {
+            Some(time) => format!("<time datetime=\"{0}\">{0}</time>\n", timestamp(time)),
+            None => String::new(),
+        };
+        body += format!(
+            "<section class=\"{0}\">\n<h2>{0}</h2>\n{time}{1}</section>\n",
+            role(i),
+            render(message.as_str())
+        )
+        .as_str();
+    }
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        escape_html(title.as_str())
+    )
+}
+
+/// The messages as OpenAI takes them, and what is known about them.
+/// `times` has the time of each message, or null
+pub fn messages_json(context: &Context) -> Value {
+    let mut messages: Vec<Value> = vec![];
+    if !context.purpose.is_empty() {
+        messages.push(json!({"role": "system", "content": context.purpose}));
+    }
+    for (i, content) in context.prompt_response.iter().enumerate() {
+        messages.push(json!({"role": role(i), "content": content}));
+    }
+    let times: Vec<Option<String>> = (0..context.len())
+        .map(|i| context.time(i).map(timestamp))
+        .collect();
+    json!({
+        "metadata": {
+            "system_prompt": context.purpose,
+            "model": context.model,
+            "cost_cents": context.cost,
+            "times": times,
+            "exported": timestamp(Utc::now()),
+        },
+        "messages": messages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> Context {
+        let mut context = Context::new("You <answer> briefly");
+        context.model = Some("gpt-4o".to_string());
+        context.cost = 0.25;
+        context.push("How do I print in Rust?".to_string());
+        context.push("Like this:\n\n```rust\nprintln!(\"<b>hi</b>\");\n```\nDone".to_string());
+        context.push("And badly?".to_string());
+        context.push("```\nnever closed".to_string());
+        context
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(
+            "md".parse::<ExportFormat>().unwrap(),
+            ExportFormat::Markdown
+        );
+        assert_eq!("HTML".parse::<ExportFormat>().unwrap(), ExportFormat::Html);
+        assert!("pdf".parse::<ExportFormat>().is_err());
+        for name in EXPORT_FORMATS {
+            assert_eq!(name.parse::<ExportFormat>().unwrap().to_string(), name);
+        }
+    }
+
+    #[test]
+    fn markdown_keeps_fences() {
+        let text = markdown(&context());
+        assert!(text.contains("* **Model** gpt-4o\n"));
+        assert!(text.contains("* **Cost** 0.2500 cents\n"));
+        assert!(text.contains("* **Started** "));
+        assert!(text.contains("## System\n\nYou <answer> briefly\n"));
+        assert!(text.contains("```rust\nprintln!(\"<b>hi</b>\");\n```\nDone\n"));
+        // The open fence is closed
+        assert!(text.trim_end().ends_with("never closed\n```"));
+        assert_eq!(close_fences("a\n~~~~\nb\n~~~~\n"), "a\n~~~~\nb\n~~~~");
+    }
+
+    #[test]
+    fn html_is_standalone_and_escaped() {
+        let page = html_page(&context());
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<style>"));
+        assert!(page.contains("<title>Conversation with gpt-4o</title>"));
+        assert!(page.contains("<code class=\"language-rust\">"));
+        assert!(page.contains("&lt;answer&gt;"));
+        assert!(!page.contains("<answer>"));
+        assert!(!page.contains("<b>hi"));
+        assert!(page.contains("<section class=\"assistant\">"));
+        assert!(page.contains("<time datetime="));
+    }
+
+    #[test]
+    fn json_messages() {
+        let mut context = context();
+        // Saved before there were times
+        context.times.drain(..2);
+        let value = messages_json(&context);
+        let messages = value["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[1]["role"], "user");
+        assert_eq!(messages[2]["role"], "assistant");
+        assert_eq!(value["metadata"]["model"], "gpt-4o");
+        assert_eq!(value["metadata"]["cost_cents"], 0.25);
+        let times = value["metadata"]["times"].as_array().unwrap();
+        assert!(times[0].is_null() && times[1].is_null());
+        assert!(times[2].is_string() && times[3].is_string());
+    }
+}
@@ -0,0 +1,235 @@
+//! Turn saved conversations (`Context` files written by `! sx`) into
+//! chat format JSONL for fine tuning
+use crate::context::Context;
+use regex::Regex;
+use serde_json::json;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Which conversations to use
+#[derive(Debug, Default)]
+pub struct ExportFilter {
+    /// Only conversations with this model.  Conversations saved
+    /// without a model are left out when this is set
+    pub model: Option<String>,
+
+    /// Only conversations with at least this many prompt/response
+    /// pairs
+    pub min_turns: usize,
+
+    /// Only conversations whose system prompt matches
+    pub purpose: Option<Regex>,
+}
+
+impl ExportFilter {
+    fn accepts(&self, context: &Context) -> bool {
+        if context.len() / 2 < self.min_turns.max(1) {
+            return false;
+        }
+        if let Some(ref model) = self.model {
+            if context.model.as_ref() != Some(model) {
+                return false;
+            }
+        }
+        if let Some(ref purpose) = self.purpose {
+            if !purpose.is_match(context.purpose.as_str()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// What happened in an export
+#[derive(Debug, Default)]
+pub struct ExportReport {
+    /// Files read as conversations
+    pub conversations: usize,
+
+    /// Files that could not be read as conversations, and why
+    pub skipped: Vec<(PathBuf, String)>,
+
+    /// Conversations the filter left out
+    pub filtered: usize,
+
+    /// Conversations that repeated, or were the start of, another
+    pub duplicates: usize,
+
+    /// Examples written
+    pub examples: usize,
+}
+
+impl fmt::Display for ExportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, reason) in self.skipped.iter() {
+            writeln!(f, "Skipped {}: {reason}", path.display())?;
+        }
+        writeln!(
+            f,
+            "Conversations: {} Filtered out: {} Duplicates: {} Examples written: {}",
+            self.conversations, self.filtered, self.duplicates, self.examples
+        )
+    }
+}
+
+/// A conversation as a training example: `{"messages": [...]}`.  A
+/// prompt at the end with no response is left out
+pub fn context_to_example(context: &Context) -> Value {
+    let mut messages: Vec<Value> = vec![];
+    if !context.purpose.is_empty() {
+        messages.push(json!({"role": "system", "content": context.purpose}));
+    }
+    let pairs = context.len() / 2;
+    for (i, content) in context.prompt_response[..pairs * 2].iter().enumerate() {
+        let role = if i % 2 == 0 { "user" } else { "assistant" };
+        messages.push(json!({"role": role, "content": content}));
+    }
+    json!({ "messages": messages })
+}
+
+/// Read every conversation in `paths`.  Directories are searched
+/// recursively.  Files that are not conversations are reported, not
+/// fatal
+pub fn read_contexts(paths: &[PathBuf], report: &mut ExportReport) -> Vec<Context> {
+    let mut result: Vec<Context> = vec![];
+    for path in paths {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+                Ok(rd) => rd.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+                Err(err) => {
+                    report.skipped.push((path.clone(), format!("{err}")));
+                    continue;
+                }
+            };
+            entries.sort();
+            result.extend(read_contexts(&entries, report));
+        } else {
+            match fs::read_to_string(path)
+                .map_err(|e| format!("{e}"))
+                .and_then(|s| serde_json::from_str::<Context>(&s).map_err(|e| format!("{e}")))
+            {
+                Ok(context) => {
+                    report.conversations += 1;
+                    result.push(context);
+                }
+                Err(err) => report.skipped.push((path.clone(), err)),
+            }
+        }
+    }
+    result
+}
+
+/// Filter and deduplicate `contexts`.  A conversation that is
+/// identical to, or the start of, a longer one with the same system
+/// prompt is dropped: `! sx` is often run more than once as a
+/// conversation grows
+pub fn select_contexts(
+    contexts: Vec<Context>,
+    filter: &ExportFilter,
+    report: &mut ExportReport,
+) -> Vec<Context> {
+    let mut candidates: Vec<Context> = vec![];
+    for context in contexts {
+        if filter.accepts(&context) {
+            candidates.push(context);
+        } else {
+            report.filtered += 1;
+        }
+    }
+
+    // Longest first, so a prefix is always seen after what contains it
+    candidates.sort_by_key(|c| Reverse(c.len()));
+    let mut kept: Vec<Context> = vec![];
+    let mut seen: HashSet<String> = HashSet::new();
+    for context in candidates {
+        let pairs = context.len() / 2 * 2;
+        let key = context_to_example(&context).to_string();
+        let is_prefix = kept.iter().any(|k| {
+            k.purpose == context.purpose
+                && k.prompt_response.len() >= pairs
+                && k.prompt_response[..pairs] == context.prompt_response[..pairs]
+        });
+        if is_prefix || !seen.insert(key) {
+            report.duplicates += 1;
+        } else {
+            kept.push(context);
+        }
+    }
+    kept
+}
+
+/// Write the conversations in `paths` that pass `filter` to `output`
+/// as fine tuning JSONL
+pub fn export(
+    paths: &[PathBuf],
+    filter: &ExportFilter,
+    output: &Path,
+) -> Result<ExportReport, Box<dyn Error>> {
+    let mut report = ExportReport::default();
+    let contexts = read_contexts(paths, &mut report);
+    let contexts = select_contexts(contexts, filter, &mut report);
+    let mut file = File::create(output)?;
+    for context in contexts.iter() {
+        writeln!(file, "{}", context_to_example(context))?;
+        report.examples += 1;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(purpose: &str, model: Option<&str>, turns: &[&str]) -> Context {
+        let mut c = Context::new(purpose);
+        c.model = model.map(|m| m.to_string());
+        for t in turns {
+            c.push(t.to_string());
+        }
+        c
+    }
+
+    #[test]
+    fn example_format() {
+        let c = context("Be terse", None, &["Hello", "Hi", "Unanswered"]);
+        let example = context_to_example(&c);
+        let messages = example["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[1]["role"], "user");
+        assert_eq!(messages[2]["role"], "assistant");
+        assert_eq!(messages[2]["content"], "Hi");
+    }
+
+    #[test]
+    fn filter_and_deduplicate() {
+        let contexts = vec![
+            context("Be terse", Some("gpt-4"), &["a", "b"]),
+            context("Be terse", Some("gpt-4"), &["a", "b", "c", "d"]),
+            context("Be terse", Some("gpt-4"), &["a", "b", "c", "d"]),
+            context("Be verbose", Some("gpt-4"), &["a", "b"]),
+            context("Be terse", Some("gpt-3.5-turbo"), &["x", "y"]),
+            context("Be terse", None, &["p", "q"]),
+            context("Be terse", Some("gpt-4"), &["lonely"]),
+        ];
+        let filter = ExportFilter {
+            model: Some("gpt-4".to_string()),
+            min_turns: 1,
+            purpose: Some(Regex::new("terse").unwrap()),
+        };
+        let mut report = ExportReport::default();
+        let kept = select_contexts(contexts, &filter, &mut report);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].len(), 4);
+        assert_eq!(report.filtered, 4);
+        assert_eq!(report.duplicates, 2);
+    }
+}
@@ -0,0 +1,378 @@
+//! The structures for the fine tuning jobs API.  See
+//! https://platform.openai.com/docs/api-reference/fine-tuning
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The model used for fine tuning if the caller does not specify one
+pub const DEFAULT_FINE_TUNE_MODEL: &str = "gpt-4o-mini-2024-07-18";
+
+/// A job that is in one of these states will not change again
+const FINISHED_STATES: [&str; 3] = ["succeeded", "failed", "cancelled"];
+
+/// A hyper parameter is either a number, or left for OpenAI to
+/// choose: "auto".  `n_epochs` and `batch_size` are integers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HyperParameter {
+    Auto(String),
+    Integer(i64),
+    Value(f64),
+}
+
+impl HyperParameter {
+    pub fn auto() -> Self {
+        HyperParameter::Auto("auto".to_string())
+    }
+}
+
+impl fmt::Display for HyperParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HyperParameter::Auto(s) => write!(f, "{s}"),
+            HyperParameter::Integer(n) => write!(f, "{n}"),
+            HyperParameter::Value(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HyperParameters {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n_epochs: Option<HyperParameter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_size: Option<HyperParameter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub learning_rate_multiplier: Option<HyperParameter>,
+}
+
+/// The body of a request to create a fine tuning job
+#[derive(Debug, Clone, Serialize)]
+pub struct FineTuningJobRequest {
+    pub training_file: String,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validation_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hyperparameters: Option<HyperParameters>,
+    /// Up to 64 characters added to the fine tuned model's name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+impl FineTuningJobRequest {
+    pub fn new(training_file: &str, model: &str) -> Self {
+        Self {
+            training_file: training_file.to_string(),
+            model: model.to_string(),
+            validation_file: None,
+            hyperparameters: None,
+            suffix: None,
+            seed: None,
+        }
+    }
+
+    /// Set a parameter from a `key=value` string as the CLI gets it.
+    /// Keys: validation, epochs, batch, lr, suffix, seed
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let hyper = |v: &str| -> Result<HyperParameter, String> {
+            if v == "auto" {
+                Ok(HyperParameter::auto())
+            } else if key == "lr" {
+                v.parse::<f64>()
+                    .map(HyperParameter::Value)
+                    .map_err(|err| format!("{err}: {key}={v}"))
+            } else {
+                v.parse::<i64>()
+                    .map(HyperParameter::Integer)
+                    .map_err(|err| format!("{err}: {key}={v}"))
+            }
+        };
+        match key {
+            "validation" => self.validation_file = Some(value.to_string()),
+            "suffix" => self.suffix = Some(value.to_string()),
+            "seed" => {
+                self.seed = Some(
+                    value
+                        .parse::<i64>()
+                        .map_err(|err| format!("{err}: seed={value}"))?,
+                )
+            }
+            "epochs" => {
+                self.hyperparameters
+                    .get_or_insert_with(Default::default)
+                    .n_epochs = Some(hyper(value)?)
+            }
+            "batch" => {
+                self.hyperparameters
+                    .get_or_insert_with(Default::default)
+                    .batch_size = Some(hyper(value)?)
+            }
+            "lr" => {
+                self.hyperparameters
+                    .get_or_insert_with(Default::default)
+                    .learning_rate_multiplier = Some(hyper(value)?)
+            }
+            _ => return Err(format!("Unknown fine tune parameter: {key}")),
+        };
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FineTuningJobError {
+    pub code: Option<String>,
+    pub message: Option<String>,
+    pub param: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FineTuningJob {
+    pub id: String,
+    pub object: String,
+    pub created_at: i64,
+    pub finished_at: Option<i64>,
+    pub model: String,
+    pub fine_tuned_model: Option<String>,
+    pub organization_id: String,
+    pub status: String,
+    pub hyperparameters: HyperParameters,
+    pub training_file: String,
+    pub validation_file: Option<String>,
+    #[serde(default)]
+    pub result_files: Vec<String>,
+    pub trained_tokens: Option<u64>,
+    pub error: Option<FineTuningJobError>,
+    pub estimated_finish: Option<i64>,
+    pub seed: Option<i64>,
+}
+
+impl FineTuningJob {
+    /// True if the job has succeeded, failed, or been cancelled
+    pub fn is_finished(&self) -> bool {
+        FINISHED_STATES.contains(&self.status.as_str())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FineTuningJobList {
+    pub object: String,
+    pub data: Vec<FineTuningJob>,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FineTuningJobEvent {
+    pub id: String,
+    pub object: String,
+    pub created_at: i64,
+    pub level: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FineTuningJobEventList {
+    pub object: String,
+    pub data: Vec<FineTuningJobEvent>,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointMetrics {
+    pub step: Option<f64>,
+    pub train_loss: Option<f64>,
+    pub train_mean_token_accuracy: Option<f64>,
+    pub valid_loss: Option<f64>,
+    pub valid_mean_token_accuracy: Option<f64>,
+    pub full_valid_loss: Option<f64>,
+    pub full_valid_mean_token_accuracy: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FineTuningJobCheckpoint {
+    pub id: String,
+    pub object: String,
+    pub created_at: i64,
+    pub fine_tuned_model_checkpoint: String,
+    pub step_number: u64,
+    pub metrics: CheckpointMetrics,
+    pub fine_tuning_job_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FineTuningJobCheckpointList {
+    pub object: String,
+    pub data: Vec<FineTuningJobCheckpoint>,
+    pub has_more: bool,
+}
+
+/// Response after a fine tuned model is deleted
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModelDeleted {
+    pub id: String,
+    pub object: String,
+    pub deleted: bool,
+}
+
+/// Format a unix time stamp for display
+pub(crate) fn time_stamp(secs: i64) -> String {
+    match DateTime::<Utc>::from_timestamp(secs, 0) {
+        Some(dt) => dt.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => format!("{secs}"),
+    }
+}
+
+impl fmt::Display for HyperParameters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |h: &Option<HyperParameter>| match h {
+            Some(h) => format!("{h}"),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "n_epochs: {} batch_size: {} learning_rate_multiplier: {}",
+            show(&self.n_epochs),
+            show(&self.batch_size),
+            show(&self.learning_rate_multiplier)
+        )
+    }
+}
+
+impl fmt::Display for FineTuningJob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "ID: {}", self.id)?;
+        writeln!(f, "Model: {}", self.model)?;
+        writeln!(f, "Status: {}", self.status)?;
+        writeln!(f, "Created At: {}", time_stamp(self.created_at))?;
+        if let Some(finished_at) = self.finished_at {
+            writeln!(f, "Finished At: {}", time_stamp(finished_at))?;
+        }
+        if let Some(estimated_finish) = self.estimated_finish {
+            writeln!(f, "Estimated Finish: {}", time_stamp(estimated_finish))?;
+        }
+        writeln!(f, "Training File: {}", self.training_file)?;
+        if let Some(ref validation_file) = self.validation_file {
+            writeln!(f, "Validation File: {validation_file}")?;
+        }
+        writeln!(f, "Hyper Parameters: {}", self.hyperparameters)?;
+        if let Some(trained_tokens) = self.trained_tokens {
+            writeln!(f, "Trained Tokens: {trained_tokens}")?;
+        }
+        if let Some(ref fine_tuned_model) = self.fine_tuned_model {
+            writeln!(f, "Fine Tuned Model: {fine_tuned_model}")?;
+        }
+        if !self.result_files.is_empty() {
+            writeln!(f, "Result Files: {}", self.result_files.join(", "))?;
+        }
+        if let Some(ref error) = self.error {
+            writeln!(
+                f,
+                "Error: {} {}",
+                error.code.as_deref().unwrap_or(""),
+                error.message.as_deref().unwrap_or("")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FineTuningJobList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let jobs = self
+            .data
+            .iter()
+            .map(|x| {
+                format!(
+                    "{} {} {} {} {}",
+                    x.id,
+                    time_stamp(x.created_at),
+                    x.model,
+                    x.status,
+                    x.fine_tuned_model.as_deref().unwrap_or("-")
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        write!(f, "{jobs}")
+    }
+}
+
+impl fmt::Display for FineTuningJobEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} [{}] {}",
+            time_stamp(self.created_at),
+            self.level,
+            self.message
+        )
+    }
+}
+
+impl fmt::Display for FineTuningJobCheckpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metric = |m: Option<f64>| match m {
+            Some(m) => format!("{m:.4}"),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "Step {}: {} train_loss: {} valid_loss: {} full_valid_loss: {}",
+            self.step_number,
+            self.fine_tuned_model_checkpoint,
+            metric(self.metrics.train_loss),
+            metric(self.metrics.valid_loss),
+            metric(self.metrics.full_valid_loss),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_parameters() {
+        let mut request = FineTuningJobRequest::new("file-abc", DEFAULT_FINE_TUNE_MODEL);
+        request.set("epochs", "3").unwrap();
+        request.set("batch", "8").unwrap();
+        request.set("lr", "auto").unwrap();
+        request.set("validation", "file-def").unwrap();
+        assert!(request.set("bogus", "1").is_err());
+        assert!(request.set("batch", "many").is_err());
+        assert!(request.set("epochs", "2.5").is_err());
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["training_file"], "file-abc");
+        assert_eq!(json["validation_file"], "file-def");
+        // Integers, not 3.0
+        assert_eq!(json["hyperparameters"]["n_epochs"].to_string(), "3");
+        assert_eq!(json["hyperparameters"]["batch_size"].to_string(), "8");
+        assert_eq!(json["hyperparameters"]["learning_rate_multiplier"], "auto");
+        assert!(json.get("suffix").is_none());
+    }
+
+    #[test]
+    fn job_status() {
+        let job: FineTuningJob = serde_json::from_str(
+            r#"{"id": "ftjob-abc", "object": "fine_tuning.job", "created_at": 1721764800,
+                "finished_at": null, "model": "gpt-4o-mini-2024-07-18",
+                "fine_tuned_model": null, "organization_id": "org-123",
+                "status": "running",
+                "hyperparameters": {"n_epochs": "auto", "batch_size": 1,
+                                    "learning_rate_multiplier": 1.8},
+                "training_file": "file-abc", "validation_file": null,
+                "result_files": [], "trained_tokens": null, "error": null,
+                "estimated_finish": null, "seed": 42}"#,
+        )
+        .unwrap();
+        assert!(!job.is_finished());
+        assert_eq!(job.hyperparameters.n_epochs, Some(HyperParameter::auto()));
+        assert_eq!(
+            job.hyperparameters.batch_size,
+            Some(HyperParameter::Integer(1))
+        );
+    }
+}
@@ -0,0 +1,418 @@
+//! Check a JSONL file before it is uploaded for fine tuning, and
+//! estimate what training on it will cost.  Chat models train on
+//! messages, davinci-002 and babbage-002 on prompts and completions.
+//! The checks follow https://platform.openai.com/docs/guides/fine-tuning
+use crate::tokens::{estimate_chat_tokens, estimate_tokens};
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// OpenAI will not train on fewer examples than this
+const MIN_EXAMPLES: usize = 10;
+
+/// The roles a message in a training example can have
+const ROLES: [&str; 4] = ["system", "user", "assistant", "tool"];
+
+/// The keys a message in a training example can have
+const MESSAGE_KEYS: [&str; 7] = [
+    "role",
+    "content",
+    "name",
+    "weight",
+    "tool_calls",
+    "tool_call_id",
+    "function_call",
+];
+
+/// When `n_epochs` is "auto" OpenAI aims for about this many passes
+/// over the data, adjusted so small and large data sets train for a
+/// sensible number of examples
+const TARGET_EPOCHS: usize = 3;
+const MIN_TARGET_EXAMPLES: usize = 100;
+const MAX_TARGET_EXAMPLES: usize = 25_000;
+const MIN_DEFAULT_EPOCHS: usize = 1;
+const MAX_DEFAULT_EPOCHS: usize = 25;
+
+/// Training price in cents per million tokens, and the most tokens
+/// one example can have.  Matched against the start of the model name
+const TRAINING_MODELS: [(&str, f64, usize); 5] = [
+    ("gpt-4o-mini", 300.0, 65_536),
+    ("gpt-4o", 2_500.0, 65_536),
+    ("gpt-3.5-turbo", 800.0, 16_385),
+    ("davinci-002", 600.0, 16_384),
+    ("babbage-002", 40.0, 16_384),
+];
+
+/// Models that train on `{"prompt": ..., "completion": ...}` lines
+const COMPLETION_MODELS: [&str; 2] = ["davinci-002", "babbage-002"];
+
+/// The result of checking a fine tuning data set
+#[derive(Debug, Clone)]
+pub struct DatasetReport {
+    pub model: String,
+
+    /// Lines that held an example (blank lines are skipped)
+    pub examples: usize,
+
+    /// Problems that will make OpenAI reject the file: (line, problem)
+    pub errors: Vec<(usize, String)>,
+
+    /// Things that are legal but probably mistakes: (line, problem)
+    pub warnings: Vec<(usize, String)>,
+
+    /// Estimated tokens for each example, in file order
+    pub example_tokens: Vec<usize>,
+
+    /// Lines of examples longer than the model's context.  OpenAI
+    /// truncates them
+    pub over_limit: Vec<usize>,
+
+    /// The context limit for an example with this model
+    pub context_limit: usize,
+
+    /// Epochs used for the estimate
+    pub epochs: usize,
+
+    /// Tokens that will be billed: every example, capped at the
+    /// context limit, once per epoch
+    pub billed_tokens: usize,
+
+    /// Estimated cost of training in cents.  `None` if the price for
+    /// the model is unknown
+    pub cost: Option<f64>,
+}
+
+impl DatasetReport {
+    /// The file can be uploaded
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// The epochs OpenAI chooses when `n_epochs` is "auto"
+pub fn default_epochs(examples: usize) -> usize {
+    if examples == 0 {
+        TARGET_EPOCHS
+    } else if examples * TARGET_EPOCHS < MIN_TARGET_EXAMPLES {
+        MAX_DEFAULT_EPOCHS.min(MIN_TARGET_EXAMPLES.div_ceil(examples))
+    } else if examples * TARGET_EPOCHS > MAX_TARGET_EXAMPLES {
+        MIN_DEFAULT_EPOCHS.max(MAX_TARGET_EXAMPLES / examples)
+    } else {
+        TARGET_EPOCHS
+    }
+}
+
+/// Check the file at `path` for training `model`.  If `epochs` is
+/// `None` the number OpenAI would choose is used for the estimate
+pub fn validate_file(
+    path: &Path,
+    model: &str,
+    epochs: Option<usize>,
+) -> Result<DatasetReport, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(validate(contents.as_str(), model, epochs))
+}
+
+/// Check the JSONL in `contents` for training `model`
+pub fn validate(contents: &str, model: &str, epochs: Option<usize>) -> DatasetReport {
+    let (price, context_limit) = match TRAINING_MODELS
+        .iter()
+        .find(|(prefix, _, _)| model.starts_with(prefix))
+    {
+        Some((_, price, limit)) => (Some(*price), *limit),
+        None => (None, TRAINING_MODELS[0].2),
+    };
+    let mut report = DatasetReport {
+        model: model.to_string(),
+        examples: 0,
+        errors: vec![],
+        warnings: vec![],
+        example_tokens: vec![],
+        over_limit: vec![],
+        context_limit,
+        epochs: 0,
+        billed_tokens: 0,
+        cost: None,
+    };
+    if price.is_none() {
+        report
+            .warnings
+            .push((0, format!("No price or context limit known for {model}")));
+    }
+    let check = if COMPLETION_MODELS.iter().any(|m| model.starts_with(m)) {
+        check_completion_example
+    } else {
+        check_example
+    };
+
+    for (i, line) in contents.lines().enumerate() {
+        let line_number = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        report.examples += 1;
+        match check(line) {
+            Ok((tokens, warnings)) => {
+                for w in warnings {
+                    report.warnings.push((line_number, w));
+                }
+                if tokens > context_limit {
+                    report.over_limit.push(line_number);
+                }
+                report.example_tokens.push(tokens);
+            }
+            Err(errors) => {
+                for e in errors {
+                    report.errors.push((line_number, e));
+                }
+            }
+        }
+    }
+    if report.examples < MIN_EXAMPLES {
+        report.errors.push((
+            0,
+            format!(
+                "{} examples.  At least {MIN_EXAMPLES} are needed",
+                report.examples
+            ),
+        ));
+    }
+
+    report.epochs = epochs.unwrap_or_else(|| default_epochs(report.examples));
+    report.billed_tokens = report
+        .example_tokens
+        .iter()
+        .map(|t| (*t).min(context_limit))
+        .sum::<usize>()
+        * report.epochs;
+    report.cost = price.map(|p| report.billed_tokens as f64 * p / 1_000_000.0);
+    report
+}
+
+/// Check one line of the file.  Return the estimated tokens and any
+/// warnings, or the reasons the example is invalid
+fn check_example(line: &str) -> Result<(usize, Vec<String>), Vec<String>> {
+    let example: Value = match serde_json::from_str(line) {
+        Ok(v) => v,
+        Err(err) => return Err(vec![format!("Not JSON: {err}")]),
+    };
+    let messages = match example.get("messages") {
+        Some(Value::Array(m)) => m,
+        Some(_) => return Err(vec!["`messages` is not an array".to_string()]),
+        None => return Err(vec!["No `messages`".to_string()]),
+    };
+    if messages.is_empty() {
+        return Err(vec!["`messages` is empty".to_string()]);
+    }
+
+    let mut errors: Vec<String> = vec![];
+    let mut warnings: Vec<String> = vec![];
+    let mut contents: Vec<&str> = vec![];
+    let mut assistant = false;
+    for (j, message) in messages.iter().enumerate() {
+        let message = match message.as_object() {
+            Some(m) => m,
+            None => {
+                errors.push(format!("Message {j} is not an object"));
+                continue;
+            }
+        };
+        for key in message.keys() {
+            if !MESSAGE_KEYS.contains(&key.as_str()) {
+                warnings.push(format!("Message {j} has unrecognised key `{key}`"));
+            }
+        }
+        let role = message.get("role").and_then(|r| r.as_str()).unwrap_or("");
+        if !ROLES.contains(&role) {
+            errors.push(format!("Message {j} has invalid role `{role}`"));
+        }
+        if role == "assistant" {
+            assistant = true;
+        }
+        match message.get("content") {
+            Some(Value::String(c)) => {
+                if c.trim().is_empty() {
+                    errors.push(format!("Message {j} ({role}) is empty"));
+                }
+                contents.push(c.as_str());
+            }
+            Some(Value::Null) | None => {
+                // An assistant can call a tool instead of replying
+                if !(role == "assistant"
+                    && (message.contains_key("tool_calls")
+                        || message.contains_key("function_call")))
+                {
+                    errors.push(format!("Message {j} ({role}) has no content"));
+                }
+            }
+            Some(_) => errors.push(format!("Message {j} ({role}) content is not text")),
+        }
+        if let Some(weight) = message.get("weight") {
+            if role != "assistant" {
+                warnings.push(format!("Message {j}: `weight` only matters for assistant"));
+            } else if weight != 0 && weight != 1 {
+                errors.push(format!("Message {j}: `weight` must be 0 or 1"));
+            }
+        }
+    }
+    if !assistant {
+        errors.push("No assistant message".to_string());
+    }
+    if errors.is_empty() {
+        Ok((estimate_chat_tokens(contents.into_iter()), warnings))
+    } else {
+        Err(errors)
+    }
+}
+
+/// Check one prompt and completion line, as `check_example` does
+fn check_completion_example(line: &str) -> Result<(usize, Vec<String>), Vec<String>> {
+    let example: Value = match serde_json::from_str(line) {
+        Ok(v) => v,
+        Err(err) => return Err(vec![format!("Not JSON: {err}")]),
+    };
+    let example = match example.as_object() {
+        Some(e) => e,
+        None => return Err(vec!["Not an object".to_string()]),
+    };
+    let mut errors: Vec<String> = vec![];
+    let mut warnings: Vec<String> = vec![];
+    let mut tokens = 0;
+    for key in ["prompt", "completion"] {
+        match example.get(key) {
+            Some(Value::String(text)) => tokens += estimate_tokens(text.as_str()),
+            Some(_) => errors.push(format!("`{key}` is not text")),
+            None => errors.push(format!("No `{key}`")),
+        }
+    }
+    if let Some(Value::String(completion)) = example.get("completion") {
+        if completion.trim().is_empty() {
+            errors.push("`completion` is empty".to_string());
+        }
+    }
+    for key in example.keys() {
+        if key != "prompt" && key != "completion" {
+            warnings.push(format!("Unrecognised key `{key}`"));
+        }
+    }
+    if errors.is_empty() {
+        Ok((tokens, warnings))
+    } else {
+        Err(errors)
+    }
+}
+
+impl fmt::Display for DatasetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Examples: {}", self.examples)?;
+        for (line, error) in self.errors.iter() {
+            writeln!(f, "Error line {line}: {error}")?;
+        }
+        for (line, warning) in self.warnings.iter() {
+            writeln!(f, "Warning line {line}: {warning}")?;
+        }
+        if !self.example_tokens.is_empty() {
+            let total: usize = self.example_tokens.iter().sum();
+            writeln!(
+                f,
+                "Tokens per example (estimated): min {} max {} mean {} total {total}",
+                self.example_tokens.iter().min().unwrap_or(&0),
+                self.example_tokens.iter().max().unwrap_or(&0),
+                total / self.example_tokens.len(),
+            )?;
+        }
+        if !self.over_limit.is_empty() {
+            writeln!(
+                f,
+                "Over the {} token limit for {}, will be truncated: lines {}",
+                self.context_limit,
+                self.model,
+                self.over_limit
+                    .iter()
+                    .map(|l| l.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )?;
+        }
+        write!(
+            f,
+            "Training {} for {} epochs: ~{} billed tokens",
+            self.model, self.epochs, self.billed_tokens
+        )?;
+        if let Some(cost) = self.cost {
+            write!(f, " ~{cost:.2} cents")?;
+        }
+        writeln!(f, "\n{}", if self.is_valid() { "Valid" } else { "Invalid" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOOD: &str = r#"{"messages": [{"role": "system", "content": "Be terse"}, {"role": "user", "content": "Hello"}, {"role": "assistant", "content": "Hi"}]}"#;
+
+    #[test]
+    fn valid_file() {
+        let contents = [GOOD; 10].join("\n");
+        let report = validate(contents.as_str(), "gpt-4o-mini-2024-07-18", Some(2));
+        assert!(report.is_valid(), "{report}");
+        assert_eq!(report.examples, 10);
+        assert_eq!(report.epochs, 2);
+        assert_eq!(
+            report.billed_tokens,
+            report.example_tokens.iter().sum::<usize>() * 2
+        );
+        assert!(report.cost.unwrap() > 0.0);
+    }
+
+    #[test]
+    fn invalid_examples() {
+        let mut lines = vec![GOOD; 10];
+        lines.push("not json");
+        lines.push(r#"{"messages": []}"#);
+        lines.push(r#"{"messages": [{"role": "user", "content": "Hello"}]}"#);
+        lines.push(r#"{"messages": [{"role": "robot", "content": "x"}, {"role": "assistant", "content": " "}]}"#);
+        let report = validate(lines.join("\n").as_str(), "gpt-4o-mini", None);
+        assert!(!report.is_valid());
+        let error_lines: Vec<usize> = report.errors.iter().map(|e| e.0).collect();
+        assert_eq!(error_lines, vec![11, 12, 13, 14, 14]);
+    }
+
+    #[test]
+    fn completion_models_take_prompts() {
+        let good = r#"{"prompt": "Hello ->", "completion": " Hi"}"#;
+        let contents = [good; 10].join("\n");
+        let report = validate(contents.as_str(), "davinci-002", None);
+        assert!(report.is_valid(), "{report}");
+        assert!(report.cost.unwrap() > 0.0);
+        // Chat data is not for davinci-002, and prompts are not for gpt-4o-mini
+        assert!(!validate([GOOD; 10].join("\n").as_str(), "davinci-002", None).is_valid());
+        assert!(!validate(contents.as_str(), "gpt-4o-mini", None).is_valid());
+
+        let mut lines = vec![good; 10];
+        lines.push(r#"{"prompt": "Hello"}"#);
+        lines.push(r#"{"prompt": 1, "completion": " "}"#);
+        let report = validate(lines.join("\n").as_str(), "babbage-002", None);
+        let error_lines: Vec<usize> = report.errors.iter().map(|e| e.0).collect();
+        assert_eq!(error_lines, vec![11, 12, 12]);
+    }
+
+    #[test]
+    fn too_few_and_too_long() {
+        let long = format!(
+            r#"{{"messages": [{{"role": "user", "content": "{}"}}, {{"role": "assistant", "content": "ok"}}]}}"#,
+            "word ".repeat(20_000)
+        );
+        let report = validate(long.as_str(), "gpt-3.5-turbo", None);
+        assert!(!report.is_valid());
+        assert_eq!(report.over_limit, vec![1]);
+        assert_eq!(report.epochs, MAX_DEFAULT_EPOCHS);
+        assert_eq!(
+            report.billed_tokens,
+            report.context_limit * MAX_DEFAULT_EPOCHS
+        );
+    }
+}
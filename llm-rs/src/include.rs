@@ -0,0 +1,608 @@
+//! Text to put in prompts as `{name}`.  From files, globs like
+//! `src/**/*.rs`, directory trees and http(s) URLs.  Directories and
+//! globs skip what `.gitignore` and `.ignore` files, and hidden
+//! files, would.  PDF and docx files are converted to text, and so
+//! are HTML pages.  When there is more than one file each is wrapped
+//! with its name:
+//!
+//! ```text
+//! <file name="src/lib.rs">
+//! ...
+//! </file>
+//! ```
+//!
+//! The text has a budget of tokens.  Going over it is an error, or
+//! the text is cut short
+use crate::tokens::estimate_tokens;
+use crate::transport::Transport;
+use globset::GlobBuilder;
+use ignore::WalkBuilder;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_TYPE;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::panic;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The most tokens included if no other limit is given
+pub const DEFAULT_MAX_TOKENS: usize = 32_000;
+
+/// The width HTML is wrapped to when converted to text
+const HTML_WIDTH: usize = 100;
+
+/// Put at the end of text that was cut short
+const TRUNCATED: &str = "[Truncated]";
+
+/// A file with a NUL in this much of its start is binary
+const BINARY_CHECK: usize = 8_000;
+
+/// The most bytes of a page fetched for each token of the budget.
+/// Text is about four bytes a token, and HTML and PDF carry a lot
+/// besides the text
+const BYTES_PER_TOKEN: usize = 16;
+
+/// What to do with more text than the budget
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// It is an error
+    Refuse,
+    /// Cut it short
+    Truncate,
+}
+
+#[derive(Debug, Clone)]
+pub struct IncludeOptions {
+    pub max_tokens: usize,
+    pub overflow: Overflow,
+}
+
+impl Default for IncludeOptions {
+    fn default() -> Self {
+        IncludeOptions {
+            max_tokens: DEFAULT_MAX_TOKENS,
+            overflow: Overflow::Refuse,
+        }
+    }
+}
+
+/// The text, and what went into it
+#[derive(Debug, Default)]
+pub struct Inclusion {
+    pub text: String,
+    /// About how many tokens `text` is
+    pub tokens: usize,
+    /// The files and URLs in `text`
+    pub included: Vec<String>,
+    /// Files in directories and globs that were not text, and why
+    pub skipped: Vec<(String, String)>,
+    /// The file that was cut short, if one was
+    pub truncated: Option<String>,
+    /// Files and URLs left out as the budget was spent
+    pub omitted: Vec<String>,
+}
+
+impl fmt::Display for Inclusion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}, about {} tokens",
+            self.included.len(),
+            if self.included.len() == 1 {
+                "file"
+            } else {
+                "files"
+            },
+            self.tokens
+        )?;
+        if !self.skipped.is_empty() {
+            write!(
+                f,
+                "\nSkipped: {}",
+                self.skipped
+                    .iter()
+                    .map(|(source, why)| format!("{source} ({why})"))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )?;
+        }
+        if let Some(ref source) = self.truncated {
+            write!(f, "\nTruncated: {source}")?;
+        }
+        if !self.omitted.is_empty() {
+            write!(f, "\nLeft out: {}", self.omitted.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Somewhere text comes from
+#[derive(Debug, PartialEq)]
+enum Item {
+    Url(String),
+    /// A file, and if it was named rather than found in a directory
+    /// or by a glob.  Named files that cannot be read are errors
+    File(PathBuf, bool),
+}
+
+impl Item {
+    fn name(&self) -> String {
+        match self {
+            Item::Url(url) => url.clone(),
+            Item::File(path, _) => path.display().to_string(),
+        }
+    }
+}
+
+/// The text of `sources`: files, globs, directories and URLs.  URLs
+/// are fetched with `transport`
+pub fn include(
+    sources: &[&str],
+    options: &IncludeOptions,
+    transport: &dyn Transport,
+) -> Result<Inclusion, Box<dyn Error>> {
+    let mut items: Vec<Item> = vec![];
+    for source in sources.iter() {
+        items.append(&mut expand(source)?);
+    }
+    // One file or page is as it is.  Anything else is wrapped
+    let wrapped = items.len() > 1 || matches!(items.first(), Some(Item::File(_, false)));
+    let mut inclusion = Inclusion::default();
+    let mut items = items.into_iter();
+    while let Some(item) = items.next() {
+        let name = item.name();
+        // A download can be cut short
+        let (text, cut) = match item {
+            Item::Url(ref url) => {
+                let max_bytes = options.max_tokens.saturating_mul(BYTES_PER_TOKEN);
+                match fetch(url.as_str(), max_bytes, transport)? {
+                    (text, false) => (text, false),
+                    _ if options.overflow == Overflow::Refuse => {
+                        return Err(format!("More than {max_bytes} bytes: {url}").into())
+                    }
+                    (text, true) => (format!("{text}\n{TRUNCATED}\n"), true),
+                }
+            }
+            Item::File(ref path, named) => match read_file(path) {
+                Ok(text) => (text, false),
+                Err(err) if named => return Err(format!("{err}: {name}").into()),
+                Err(err) => {
+                    inclusion.skipped.push((name, err.to_string()));
+                    continue;
+                }
+            },
+        };
+        let text = if wrapped { wrap(&name, &text) } else { text };
+        let tokens = estimate_tokens(text.as_str());
+        if inclusion.tokens + tokens <= options.max_tokens {
+            inclusion.text += text.as_str();
+            inclusion.tokens += tokens;
+            if cut {
+                // Nothing more after what was cut short
+                inclusion.truncated = Some(name.clone());
+                inclusion.omitted.extend(items.by_ref().map(|i| i.name()));
+            }
+            inclusion.included.push(name);
+            continue;
+        }
+        if options.overflow == Overflow::Refuse {
+            return Err(format!(
+                "More than {} tokens with {name} (about {})",
+                options.max_tokens,
+                inclusion.tokens + tokens
+            )
+            .into());
+        }
+        // What fits of this one, then nothing more
+        let room = options.max_tokens - inclusion.tokens;
+        let cut = prefix_within(
+            text.as_str(),
+            room.saturating_sub(estimate_tokens(TRUNCATED) + 1),
+        );
+        if !cut.is_empty() {
+            let text = format!("{cut}\n{TRUNCATED}\n");
+            inclusion.tokens += estimate_tokens(text.as_str());
+            inclusion.text += text.as_str();
+            inclusion.truncated = Some(name.clone());
+            inclusion.included.push(name);
+        } else {
+            inclusion.omitted.push(name);
+        }
+        inclusion.omitted.extend(items.by_ref().map(|i| i.name()));
+    }
+    Ok(inclusion)
+}
+
+/// Wrap `text` with the name of the file it came from
+fn wrap(name: &str, text: &str) -> String {
+    format!(
+        "<file name=\"{name}\">\n{text}{}</file>\n",
+        if text.ends_with('\n') { "" } else { "\n" }
+    )
+}
+
+/// The longest start of `text` that is `tokens` or fewer
+fn prefix_within(text: &str, tokens: usize) -> &str {
+    let bounds: Vec<usize> = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    // The number of characters that fit
+    let (mut low, mut high) = (0, bounds.len() - 1);
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        if estimate_tokens(&text[..bounds[middle]]) <= tokens {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    &text[..bounds[low]]
+}
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+fn is_glob(source: &str) -> bool {
+    source.contains(['*', '?', '[', '{'])
+}
+
+/// The URLs and files that `source` names
+fn expand(source: &str) -> Result<Vec<Item>, Box<dyn Error>> {
+    if is_url(source) {
+        return Ok(vec![Item::Url(source.to_string())]);
+    }
+    let path = Path::new(source);
+    if path.is_file() {
+        Ok(vec![Item::File(path.to_path_buf(), true)])
+    } else if path.is_dir() {
+        Ok(walk(path, |_| true)?
+            .into_iter()
+            .map(|p| Item::File(p, false))
+            .collect())
+    } else if is_glob(source) {
+        let matcher = GlobBuilder::new(source.trim_start_matches("./"))
+            .literal_separator(true)
+            .build()?
+            .compile_matcher();
+        // Walk from the directories before the first wild card
+        let base: PathBuf = path
+            .components()
+            .take_while(|c| !is_glob(c.as_os_str().to_string_lossy().as_ref()))
+            .collect();
+        let base = if base.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            base
+        };
+        let paths = walk(base.as_path(), |p| {
+            matcher.is_match(p.strip_prefix("./").unwrap_or(p))
+        })?;
+        if paths.is_empty() {
+            return Err(format!("Nothing matches {source}").into());
+        }
+        Ok(paths.into_iter().map(|p| Item::File(p, false)).collect())
+    } else {
+        Err(format!("{source} does not exist").into())
+    }
+}
+
+/// The files under `dir` that pass `filter`, in order, skipping
+/// ignored and hidden files
+fn walk(dir: &Path, filter: impl Fn(&Path) -> bool) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut paths = vec![];
+    for entry in WalkBuilder::new(dir)
+        .require_git(false)
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .build()
+    {
+        let entry = entry?;
+        if entry.file_type().is_some_and(|t| t.is_file()) && filter(entry.path()) {
+            paths.push(entry.into_path());
+        }
+    }
+    Ok(paths)
+}
+
+/// The text in the file at `path`.  PDF and docx are converted.
+/// Other files must be UTF-8
+pub fn read_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let bytes = fs::read(path)?;
+    match extension.as_str() {
+        "pdf" => pdf_text(bytes.as_slice()),
+        "docx" => docx_text(bytes.as_slice()),
+        _ => utf8_text(bytes),
+    }
+}
+
+fn utf8_text(bytes: Vec<u8>) -> Result<String, Box<dyn Error>> {
+    if bytes[..bytes.len().min(BINARY_CHECK)].contains(&0) {
+        return Err("Binary".into());
+    }
+    String::from_utf8(bytes).map_err(|_| "Not UTF-8".into())
+}
+
+fn pdf_text(bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+    // `pdf_extract` panics on some PDFs
+    match panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes)) {
+        Ok(text) => Ok(text?),
+        Err(_) => Err("Cannot read the PDF".into()),
+    }
+}
+
+/// The paragraphs of a Word document, a line each
+fn docx_text(bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")?
+        .read_to_string(&mut xml)?;
+    let mut reader = Reader::from_str(xml.as_str());
+    let mut text = String::new();
+    // Only text in <w:t> is the document's
+    let mut in_text = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.name().as_ref() == b"w:t" => in_text = true,
+            Event::End(e) if e.name().as_ref() == b"w:t" => in_text = false,
+            Event::End(e) if e.name().as_ref() == b"w:p" => text.push('\n'),
+            Event::Empty(e) if e.name().as_ref() == b"w:tab" => text.push('\t'),
+            Event::Empty(e) if e.name().as_ref() == b"w:br" => text.push('\n'),
+            Event::Text(t) if in_text => text += t.unescape()?.as_ref(),
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(text)
+}
+
+/// The text of the page at `url`, and if it was cut short.  HTML is
+/// converted.  No more than `max_bytes` are read, and text is cut
+/// there.  A PDF cannot be cut, so a longer one is an error
+fn fetch(
+    url: &str,
+    max_bytes: usize,
+    transport: &dyn Transport,
+) -> Result<(String, bool), Box<dyn Error>> {
+    let request = Client::new().get(url).build()?;
+    let response = transport.execute(request)?.error_for_status()?;
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .unwrap_or("")
+        .to_lowercase();
+    let mut bytes: Vec<u8> = vec![];
+    response
+        .take(max_bytes as u64 + 1)
+        .read_to_end(&mut bytes)?;
+    let cut = bytes.len() > max_bytes;
+    if cut {
+        if content_type.contains("pdf") {
+            return Err(format!("More than {max_bytes} bytes: {url}").into());
+        }
+        bytes.truncate(max_bytes);
+        // Not part of a character
+        if let Err(err) = std::str::from_utf8(&bytes) {
+            if err.error_len().is_none() {
+                bytes.truncate(err.valid_up_to());
+            }
+        }
+    }
+    let text = if content_type.contains("html") {
+        html_text(bytes.as_slice())
+    } else if content_type.contains("pdf") {
+        pdf_text(bytes.as_slice())?
+    } else {
+        utf8_text(bytes).map_err(|err| format!("{err}: {url}"))?
+    };
+    Ok((text, cut))
+}
+
+/// HTML as plain text
+pub fn html_text(html: &[u8]) -> String {
+    html2text::from_read(html, HTML_WIDTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::blocking::Request;
+    use reqwest::blocking::Response;
+    use std::io::Write;
+
+    /// Answers with an HTML page
+    #[derive(Debug)]
+    struct FakeTransport;
+
+    impl Transport for FakeTransport {
+        fn execute(&self, _request: Request) -> Result<Response, Box<dyn Error>> {
+            Ok(Response::from(
+                http::Response::builder()
+                    .status(200)
+                    .header("content-type", "text/html; charset=utf-8")
+                    .body("<html><body><h1>Title</h1><p>Some <b>text</b></p></body></html>")?,
+            ))
+        }
+    }
+
+    /// A directory with some files in it
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/deep")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".ignore"), "target\n").unwrap();
+        fs::write(root.join("src/a.rs"), "fn a() {}\n").unwrap();
+        fs::write(root.join("src/deep/b.rs"), "fn b() {}\n").unwrap();
+        fs::write(root.join("src/c.txt"), "Some text").unwrap();
+        fs::write(root.join("src/d.png"), [137, 80, 78, 71, 0, 0]).unwrap();
+        fs::write(root.join("target/e.rs"), "fn e() {}\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn one_file_is_not_wrapped() {
+        let dir = tree();
+        let path = dir.path().join("src/a.rs");
+        let inclusion = include(
+            &[path.to_str().unwrap()],
+            &IncludeOptions::default(),
+            &FakeTransport,
+        )
+        .unwrap();
+        assert_eq!(inclusion.text, "fn a() {}\n");
+        let binary = dir.path().join("src/d.png");
+        assert!(include(
+            &[binary.to_str().unwrap()],
+            &IncludeOptions::default(),
+            &FakeTransport
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn directories_and_globs() {
+        let dir = tree();
+        let src = dir.path().join("src");
+        let inclusion = include(
+            &[dir.path().to_str().unwrap()],
+            &IncludeOptions::default(),
+            &FakeTransport,
+        )
+        .unwrap();
+        // Sorted, without the ignored directory, and the binary file
+        // skipped
+        assert_eq!(inclusion.included.len(), 3);
+        assert!(inclusion.included[0].ends_with("src/a.rs"));
+        assert_eq!(inclusion.skipped.len(), 1);
+        assert!(inclusion.text.starts_with(&format!(
+            "<file name=\"{}\">\nfn a() {{}}\n</file>\n",
+            src.join("a.rs").display()
+        )));
+        assert!(!inclusion.text.contains("fn e"));
+
+        let glob = format!("{}/**/*.rs", src.display());
+        let inclusion =
+            include(&[glob.as_str()], &IncludeOptions::default(), &FakeTransport).unwrap();
+        assert_eq!(inclusion.included.len(), 2);
+        let glob = format!("{}/*.rs", src.display());
+        let inclusion =
+            include(&[glob.as_str()], &IncludeOptions::default(), &FakeTransport).unwrap();
+        assert_eq!(inclusion.included.len(), 1);
+        let glob = format!("{}/*.nothing", src.display());
+        assert!(include(&[glob.as_str()], &IncludeOptions::default(), &FakeTransport).is_err());
+    }
+
+    #[test]
+    fn budget() {
+        let dir = tree();
+        let root = dir.path().to_str().unwrap();
+        let refuse = IncludeOptions {
+            max_tokens: 20,
+            overflow: Overflow::Refuse,
+        };
+        assert!(include(&[root], &refuse, &FakeTransport).is_err());
+        let truncate = IncludeOptions {
+            max_tokens: 20,
+            overflow: Overflow::Truncate,
+        };
+        let inclusion = include(&[root], &truncate, &FakeTransport).unwrap();
+        assert!(inclusion.tokens <= 20);
+        assert!(inclusion.truncated.is_some());
+        assert!(inclusion.text.ends_with(&format!("{TRUNCATED}\n")));
+        assert!(!inclusion.omitted.is_empty());
+        assert_eq!(prefix_within("one two three", 2), "one two ");
+        assert_eq!(prefix_within("one", 0), "");
+    }
+
+    #[test]
+    fn urls_are_text() {
+        let inclusion = include(
+            &["https://example.com/page"],
+            &IncludeOptions::default(),
+            &FakeTransport,
+        )
+        .unwrap();
+        assert!(inclusion.text.contains("Title"));
+        assert!(inclusion.text.contains("Some text"));
+        assert!(!inclusion.text.contains("<p>"));
+    }
+
+    /// Answers with a long page of `content_type`
+    #[derive(Debug)]
+    struct LongPage(&'static str);
+
+    impl Transport for LongPage {
+        fn execute(&self, _request: Request) -> Result<Response, Box<dyn Error>> {
+            Ok(Response::from(
+                http::Response::builder()
+                    .status(200)
+                    .header("content-type", self.0)
+                    .body("é word ".repeat(100_000))?,
+            ))
+        }
+    }
+
+    #[test]
+    fn downloads_are_capped() {
+        let url = "https://example.com/long";
+        let (text, cut) = fetch(url, 1001, &LongPage("text/plain")).unwrap();
+        // Cut where a character would be split
+        assert_eq!(text.len(), 1000);
+        assert!(cut);
+        assert!(fetch(url, 1000, &LongPage("application/pdf"))
+            .unwrap_err()
+            .to_string()
+            .starts_with("More than 1000 bytes"));
+
+        let truncate = IncludeOptions {
+            max_tokens: 100,
+            overflow: Overflow::Truncate,
+        };
+        let inclusion = include(&[url], &truncate, &LongPage("text/plain")).unwrap();
+        assert!(inclusion.tokens <= 100);
+        assert_eq!(inclusion.truncated.as_deref(), Some(url));
+        assert!(inclusion.text.ends_with(&format!("{TRUNCATED}\n")));
+
+        // Cut short even if the text would fit
+        let refuse = IncludeOptions {
+            max_tokens: 100,
+            overflow: Overflow::Refuse,
+        };
+        assert_eq!(
+            include(&[url], &refuse, &LongPage("text/html"))
+                .unwrap_err()
+                .to_string(),
+            format!("More than 1600 bytes: {url}")
+        );
+        let inclusion = include(&[url], &truncate, &LongPage("text/html")).unwrap();
+        assert_eq!(inclusion.truncated.as_deref(), Some(url));
+        assert!(inclusion.text.ends_with(&format!("{TRUNCATED}\n")));
+    }
+
+    #[test]
+    fn docx() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.docx");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        zip.start_file("word/document.xml", zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(
+            br#"<?xml version="1.0"?><w:document xmlns:w="x"><w:body><w:p><w:r><w:t>Fish &amp; chips</w:t></w:r></w:p><w:p><w:r><w:t>Two</w:t><w:tab/><w:t>columns</w:t></w:r></w:p></w:body></w:document>"#,
+        )
+        .unwrap();
+        zip.finish().unwrap();
+        assert_eq!(read_file(&path).unwrap(), "Fish & chips\nTwo\tcolumns\n");
+    }
+}
@@ -0,0 +1,213 @@
+//! Hooks around every call `ApiInterface` makes, for logging,
+//! metrics, redaction and the like without changing the calls
+//! themselves.
+//!
+//! An `Interceptor` sees the JSON payload before a request is sent,
+//! and can change it.  After the call it sees the result, with the
+//! body as JSON, or the error.  The interceptors in an
+//! `InterceptorChain` are called in the order they were added before
+//! a request, and in the reverse order after it.
+//!
+//! Endpoints that send a form (image edits, transcriptions and file
+//! uploads) have their text fields in the payload: files are sent as
+//! they are.  Endpoints that send nothing have the IDs that go in the
+//! URL, if any
+use crate::api_result::ApiResult;
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Which endpoint a call is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Chat,
+    Completion,
+    Image,
+    ImageEdit,
+    AudioTranscription,
+    FileInfo,
+    FileContents,
+    FileDelete,
+    FileList,
+    FileUpload,
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Endpoint::Chat => "chat",
+            Endpoint::Completion => "completion",
+            Endpoint::Image => "image",
+            Endpoint::ImageEdit => "image_edit",
+            Endpoint::AudioTranscription => "audio_transcription",
+            Endpoint::FileInfo => "file_info",
+            Endpoint::FileContents => "file_contents",
+            Endpoint::FileDelete => "file_delete",
+            Endpoint::FileList => "file_list",
+            Endpoint::FileUpload => "file_upload",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A call to `ApiInterface`, as interceptors see it
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub endpoint: Endpoint,
+    /// The model, for endpoints that take one
+    pub model: Option<String>,
+    /// When the call started
+    pub started: Instant,
+}
+
+impl Call {
+    pub fn new(endpoint: Endpoint, model: Option<&str>) -> Self {
+        Call {
+            endpoint,
+            model: model.map(str::to_string),
+            started: Instant::now(),
+        }
+    }
+}
+
+/// Hooks called around a call.  Both do nothing unless implemented
+pub trait Interceptor: fmt::Debug + Send + Sync {
+    /// Called before `call` sends `payload`, which can be changed.  An
+    /// error stops the call: nothing is sent, and the error is what
+    /// the call returns
+    fn before_request(&self, _call: &Call, _payload: &mut Value) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Called with the result of `call`, or the error, just before it
+    /// is returned
+    fn after_response(&self, _call: &Call, _result: Result<&ApiResult<Value>, &dyn Error>) {}
+}
+
+/// The interceptors for an `ApiInterface`
+#[derive(Debug, Clone, Default)]
+pub struct InterceptorChain {
+    interceptors: Vec<Arc<dyn Interceptor>>,
+}
+
+impl InterceptorChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `interceptor` to the end of the chain
+    pub fn push(&mut self, interceptor: Arc<dyn Interceptor>) {
+        self.interceptors.push(interceptor);
+    }
+
+    pub fn len(&self) -> usize {
+        self.interceptors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.interceptors.is_empty()
+    }
+
+    /// Give every interceptor, in order, the chance to change
+    /// `payload`.  Stops at the first error
+    pub fn before(&self, call: &Call, payload: &mut Value) -> Result<(), Box<dyn Error>> {
+        for interceptor in self.interceptors.iter() {
+            interceptor.before_request(call, payload)?;
+        }
+        Ok(())
+    }
+
+    /// Show every interceptor, last first, the result of `call`
+    pub fn after<T: Serialize>(&self, call: &Call, result: &Result<ApiResult<T>, Box<dyn Error>>) {
+        if self.interceptors.is_empty() {
+            return;
+        }
+        match result {
+            Ok(result) => {
+                let result = ApiResult {
+                    headers: result.headers.clone(),
+                    body: serde_json::to_value(&result.body).unwrap_or(Value::Null),
+                };
+                for interceptor in self.interceptors.iter().rev() {
+                    interceptor.after_response(call, Ok(&result));
+                }
+            }
+            Err(err) => {
+                for interceptor in self.interceptors.iter().rev() {
+                    interceptor.after_response(call, Err(err.as_ref()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Notes what it sees, and adds its name to the payload
+    #[derive(Debug)]
+    struct Note {
+        name: &'static str,
+        notes: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Interceptor for Note {
+        fn before_request(&self, call: &Call, payload: &mut Value) -> Result<(), Box<dyn Error>> {
+            self.notes
+                .lock()
+                .unwrap()
+                .push(format!("{} before {} {payload}", self.name, call.endpoint));
+            payload["seen"] = Value::String(self.name.to_string());
+            Ok(())
+        }
+
+        fn after_response(&self, call: &Call, result: Result<&ApiResult<Value>, &dyn Error>) {
+            let result = match result {
+                Ok(result) => result.body.to_string(),
+                Err(err) => err.to_string(),
+            };
+            self.notes
+                .lock()
+                .unwrap()
+                .push(format!("{} after {} {result}", self.name, call.endpoint));
+        }
+    }
+
+    #[test]
+    fn order() {
+        let notes = Arc::new(Mutex::new(vec![]));
+        let mut chain = InterceptorChain::new();
+        for name in ["a", "b"] {
+            chain.push(Arc::new(Note {
+                name,
+                notes: notes.clone(),
+            }));
+        }
+        let call = Call::new(Endpoint::Image, None);
+        let mut payload = serde_json::json!({});
+        chain.before(&call, &mut payload).unwrap();
+        assert_eq!(payload["seen"], "b");
+        chain.after(
+            &call,
+            &Ok(ApiResult::new("url".to_string(), HashMap::new())),
+        );
+        chain.after::<()>(&call, &Err("Failed".into()));
+        assert_eq!(
+            *notes.lock().unwrap(),
+            [
+                "a before image {}",
+                "b before image {\"seen\":\"a\"}",
+                "b after image \"url\"",
+                "a after image \"url\"",
+                "b after image Failed",
+                "a after image Failed",
+            ]
+        );
+    }
+}
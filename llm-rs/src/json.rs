@@ -1,13 +1,26 @@
 //! The structures for building the Json prompts
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Deserialize)]
 pub struct Choice {
     // Field that are present in the response but that are not used here
-    // logprobs: Option<Vec<f32>>,
     // index: i32,
     pub text: String,
     pub finish_reason: String,
+    /// Only if asked for with `logprobs`
+    #[serde(default)]
+    pub logprobs: Option<CompletionLogprobs>,
+}
+
+/// The log probabilities of a completion's tokens
+#[derive(Debug, Deserialize)]
+pub struct CompletionLogprobs {
+    pub tokens: Vec<String>,
+    pub token_logprobs: Vec<Option<f64>>,
+    /// For each token, the most likely alternatives
+    #[serde(default)]
+    pub top_logprobs: Vec<Option<HashMap<String, f64>>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -15,6 +28,26 @@ pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    /// Only from newer models
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct CompletionTokensDetails {
+    /// Tokens a reasoning model used thinking.  They are not in the
+    /// reply, but are part of `completion_tokens` and are paid for
+    #[serde(default)]
+    pub reasoning_tokens: u32,
+}
+
+impl Usage {
+    pub fn reasoning_tokens(&self) -> u32 {
+        self.completion_tokens_details
+            .as_ref()
+            .map(|d| d.reasoning_tokens)
+            .unwrap_or(0)
+    }
 }
 
 /// Response for a completions request.  See
@@ -33,6 +66,22 @@ pub struct CompletionRequestInfo {
     temperature: f32,
     #[serde(skip_deserializing)]
     max_tokens: u32,
+    /// The text after the completion
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+    /// Return the prompt in front of the completion
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub echo: Option<bool>,
+    #[serde(skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// Generate this many completions on the server and return the
+    /// best
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub best_of: Option<u32>,
+    /// Return the log probabilities of this many (0 to 5) of the
+    /// most likely tokens at each position
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<u8>,
     #[serde(skip_serializing)]
     pub usage: Usage,
 }
@@ -40,17 +89,62 @@ pub struct CompletionRequestInfo {
 /// Response for a chats request.  See
 /// https://platform.openai.com/docs/api-reference/chat/create
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub role: String,
     pub content: String,
 }
-#[derive(Serialize, Debug, Deserialize)]
+#[derive(Serialize, Debug, Deserialize, Clone)]
 #[serde(tag = "t")]
 pub struct ChatChoice {
-    index: u32,
+    pub index: u32,
     pub message: Message,
     pub finish_reason: String,
+    /// Only if asked for with `logprobs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<ChoiceLogprobs>,
+}
+
+impl ChatChoice {
+    /// The log probabilities of the tokens in the reply.  Empty if
+    /// they were not asked for
+    pub fn tokens(&self) -> &[TokenLogprob] {
+        match self.logprobs {
+            Some(ChoiceLogprobs {
+                content: Some(ref content),
+            }) => content.as_slice(),
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct ChoiceLogprobs {
+    /// `None` for a refusal
+    pub content: Option<Vec<TokenLogprob>>,
+}
+
+/// A token in a reply, how likely it was, and the most likely
+/// alternatives
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+impl TokenLogprob {
+    /// From 0 to 1
+    pub fn probability(&self) -> f64 {
+        self.logprob.exp()
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -74,6 +168,31 @@ pub struct ChatRequestInfo {
     pub choices: Vec<ChatChoice>,
 }
 
+/// A piece of a streamed chat reply.  The last has the usage, if it
+/// was asked for, and no choices
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatChunk {
+    #[serde(default)]
+    pub choices: Vec<ChunkChoice>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChunkChoice {
+    pub index: u32,
+    pub delta: Delta,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+/// What a chunk adds to a choice
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Delta {
+    #[serde(default)]
+    pub content: Option<String>,
+}
+
 /// To facilitate testing the front end whithout bothering the LLM
 impl ChatRequestInfo {
     pub fn test_instance() -> Self {
@@ -86,6 +205,7 @@ impl ChatRequestInfo {
                 prompt_tokens: 0,
                 completion_tokens: 0,
                 total_tokens: 0,
+                completion_tokens_details: None,
             },
             choices: vec![ChatChoice {
                 index: 0,
@@ -94,6 +214,7 @@ impl ChatRequestInfo {
                     content: "Test content".to_string(),
                 },
                 finish_reason: "Test reason".to_string(),
+                logprobs: None,
             }],
         }
     }
@@ -167,6 +288,7 @@ impl CompletionRequestInfo {
                 prompt_tokens: 0,
                 completion_tokens: 0,
                 total_tokens: 0,
+                completion_tokens_details: None,
             },
             // id: String::new(),
             object: String::new(),
@@ -174,6 +296,11 @@ impl CompletionRequestInfo {
             model: model.to_string(),
             temperature,
             max_tokens,
+            suffix: None,
+            echo: None,
+            stop: vec![],
+            best_of: None,
+            logprobs: None,
         }
     }
 }
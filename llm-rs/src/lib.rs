@@ -1,10 +1,25 @@
 pub mod api_error;
 pub mod api_result;
+pub mod batch;
+pub mod budget;
+pub mod candidates;
+pub mod cassette;
+pub mod chat_request;
+pub mod completion_request;
 pub mod context;
-pub mod fine_tune_create;
-pub mod fine_tune_list;
-pub mod fine_tune_retrieve;
+pub mod export;
+pub mod fine_tuning_export;
+pub mod fine_tuning_job;
+pub mod fine_tuning_validate;
+pub mod include;
+pub mod interceptor;
 pub mod json;
+pub mod markdown;
 pub mod model_info;
 pub mod model_mode;
+pub mod model_registry;
 pub mod openai_interface;
+pub mod response_cache;
+pub mod script;
+pub mod tokens;
+pub mod transport;
@@ -0,0 +1,635 @@
+//! Show Markdown in a terminal as it streams in.  Text is shown as it
+//! arrives, and each line is redrawn with ANSI colours when it is
+//! finished: headings, lists, quotes, rules, emphasis, inline code
+//! and links.  Tables are drawn, aligned, when they end.  Fenced code
+//! is highlighted for the language named after the fence.
+//!
+//! Without ANSI the text is passed through as it is
+use regex::Regex;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+
+/// Headings, by level
+const HEADING: [&str; 3] = ["\x1b[1;4;35m", "\x1b[1;35m", "\x1b[1;34m"];
+
+const KEYWORD: &str = "\x1b[35m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const COMMENT: &str = "\x1b[90m";
+
+/// Inline code
+const CODE: &str = "\x1b[36m";
+
+/// Where a line is
+#[derive(Debug, Clone, PartialEq)]
+enum Block {
+    Text,
+    /// In a fenced code block, in the language given
+    Code(String),
+}
+
+/// Renders Markdown as it streams in.  `push` each piece of text and
+/// write what it returns, then write what `finish` returns
+#[derive(Debug)]
+pub struct MarkdownStream {
+    ansi: bool,
+    /// Columns in the terminal, for erasing lines that wrapped and
+    /// drawing rules
+    width: usize,
+    /// The unfinished line
+    line: String,
+    /// How many characters of `line` are on the screen
+    shown: usize,
+    block: Block,
+    /// The rows of a table, until it ends
+    table: Vec<String>,
+    inline: Inline,
+}
+
+impl MarkdownStream {
+    /// Render for a terminal `width` columns wide.  If not `ansi` the
+    /// text is not changed
+    pub fn new(width: usize, ansi: bool) -> Self {
+        MarkdownStream {
+            ansi,
+            width: width.max(1),
+            line: String::new(),
+            shown: 0,
+            block: Block::Text,
+            table: vec![],
+            inline: Inline::new(),
+        }
+    }
+
+    /// Add `text` to the stream.  Returns what to write to the
+    /// terminal
+    pub fn push(&mut self, text: &str) -> String {
+        if !self.ansi {
+            self.shown = match text.rfind('\n') {
+                Some(i) => text[i + 1..].len(),
+                None => self.shown + text.len(),
+            };
+            return text.to_string();
+        }
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(i) = rest.find('\n') {
+            self.line.push_str(&rest[..i]);
+            out += self.erase().as_str();
+            let line = std::mem::take(&mut self.line);
+            out += self.render_line(line.as_str()).as_str();
+            rest = &rest[i + 1..];
+        }
+        self.line.push_str(rest);
+        self.shown += rest.chars().count();
+        out + rest
+    }
+
+    /// The end of the stream.  Returns what is left to write, which
+    /// leaves the cursor at the start of a line
+    pub fn finish(&mut self) -> String {
+        if !self.ansi {
+            let out = if self.shown > 0 { "\n" } else { "" };
+            self.shown = 0;
+            return out.to_string();
+        }
+        let mut out = self.erase();
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            out += self.render_line(line.as_str()).as_str();
+        }
+        out += self.end_table().as_str();
+        self.block = Block::Text;
+        out
+    }
+
+    /// Take the unfinished line off the screen
+    fn erase(&mut self) -> String {
+        if self.shown == 0 {
+            return String::new();
+        }
+        let rows = self.shown.div_ceil(self.width);
+        self.shown = 0;
+        if rows > 1 {
+            format!("\r\x1b[{}A\x1b[J", rows - 1)
+        } else {
+            "\r\x1b[J".to_string()
+        }
+    }
+
+    /// A finished line, rendered, with its line end.  Empty if it is
+    /// held back, like the rows of a table
+    fn render_line(&mut self, line: &str) -> String {
+        let fence = line.trim_start().starts_with("```");
+        if let Block::Code(ref language) = self.block {
+            if fence {
+                self.block = Block::Text;
+                return format!("{DIM}{line}{RESET}\n");
+            }
+            return format!("{}\n", highlight(line, language.as_str()));
+        }
+        if line.trim_start().starts_with('|') {
+            self.table.push(line.to_string());
+            return String::new();
+        }
+        let mut out = self.end_table();
+        if fence {
+            let language = line.trim_start().trim_start_matches('`').trim();
+            self.block = Block::Code(language.to_lowercase());
+            out += format!("{DIM}{line}{RESET}\n").as_str();
+        } else {
+            out += self.render_text(line).as_str();
+            out.push('\n');
+        }
+        out
+    }
+
+    /// A line that is not code or a table
+    fn render_text(&self, line: &str) -> String {
+        let trimmed = line.trim_start();
+        let indent = &line[..line.len() - trimmed.len()];
+        if let Some(c) = self.inline.heading.captures(line) {
+            let level = c[1].len();
+            let style = HEADING[(level - 1).min(HEADING.len() - 1)];
+            return format!("{style}{}{RESET}", self.inline.render(&c[2]));
+        }
+        if self.inline.rule.is_match(line) {
+            return format!("{DIM}{}{RESET}", "─".repeat(self.width.min(80)));
+        }
+        if let Some(quote) = trimmed.strip_prefix('>') {
+            return format!(
+                "{indent}{DIM}│{RESET} \x1b[3m{}{RESET}",
+                self.inline.render(quote.trim_start())
+            );
+        }
+        if let Some(c) = self.inline.bullet.captures(trimmed) {
+            return format!("{indent}{BOLD}•{RESET} {}", self.inline.render(&c[1]));
+        }
+        if let Some(c) = self.inline.numbered.captures(trimmed) {
+            return format!(
+                "{indent}{BOLD}{}{RESET} {}",
+                &c[1],
+                self.inline.render(&c[2])
+            );
+        }
+        self.inline.render(line)
+    }
+
+    /// Draw the table, if there is one
+    fn end_table(&mut self) -> String {
+        if self.table.is_empty() {
+            return String::new();
+        }
+        let rows = std::mem::take(&mut self.table);
+        let mut cells: Vec<Vec<String>> = vec![];
+        // The header is the rows above the line under it
+        let mut header = 0;
+        for row in rows.iter() {
+            if self.inline.table_rule.is_match(row) {
+                header = cells.len();
+                continue;
+            }
+            let row = row.trim().trim_start_matches('|');
+            let row = row.strip_suffix('|').unwrap_or(row);
+            cells.push(
+                row.split('|')
+                    .map(|c| self.inline.render(c.trim()))
+                    .collect(),
+            );
+        }
+        let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for row in cells.iter() {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(visible_len(cell));
+            }
+        }
+        let border = |left: &str, middle: &str, right: &str| {
+            let lines: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+            format!("{DIM}{left}{}{right}{RESET}\n", lines.join(middle))
+        };
+        let mut out = border("┌", "┬", "┐");
+        for (r, row) in cells.iter().enumerate() {
+            if r == header && r > 0 {
+                out += border("├", "┼", "┤").as_str();
+            }
+            out += format!("{DIM}│{RESET}").as_str();
+            for (i, width) in widths.iter().enumerate() {
+                let cell = row.get(i).map(String::as_str).unwrap_or("");
+                let pad = " ".repeat(width - visible_len(cell));
+                if r < header {
+                    out += format!(" {BOLD}{cell}{RESET}{pad} {DIM}│{RESET}").as_str();
+                } else {
+                    out += format!(" {cell}{pad} {DIM}│{RESET}").as_str();
+                }
+            }
+            out.push('\n');
+        }
+        out + border("└", "┴", "┘").as_str()
+    }
+}
+
+/// The patterns for rendering lines
+#[derive(Debug)]
+struct Inline {
+    heading: Regex,
+    rule: Regex,
+    bullet: Regex,
+    numbered: Regex,
+    table_rule: Regex,
+    code: Regex,
+    /// The styles of text outside inline code, with what they become
+    styles: Vec<(Regex, String)>,
+}
+
+impl Inline {
+    fn new() -> Self {
+        let re = |r: &str| Regex::new(r).unwrap();
+        Inline {
+            heading: re(r"^\s{0,3}(#{1,6})\s+(.*?)(\s+#+)?\s*$"),
+            rule: re(r"^\s{0,3}([-*_])(\s*([-*_])){2,}\s*$"),
+            bullet: re(r"^[-*+]\s+(.*)$"),
+            numbered: re(r"^(\d+[.)])\s+(.*)$"),
+            table_rule: re(r"^\s*\|?\s*:?-+:?\s*(\|\s*:?-+:?\s*)*\|?\s*$"),
+            code: re(r"`([^`]+)`"),
+            styles: vec![
+                (
+                    re(r"\[([^\]]+)\]\(([^)\s]+)\)"),
+                    format!("\x1b[4m$1\x1b[24m {DIM}($2){RESET}"),
+                ),
+                (re(r"\*\*([^*]+)\*\*"), "\x1b[1m$1\x1b[22m".to_string()),
+                (re(r"__([^_]+)__"), "\x1b[1m$1\x1b[22m".to_string()),
+                (re(r"~~([^~]+)~~"), "\x1b[9m$1\x1b[29m".to_string()),
+                (re(r"\*([^*\s][^*]*)\*"), "\x1b[3m$1\x1b[23m".to_string()),
+                (
+                    re(r"(^|[^\w])_([^_\s][^_]*)_([^\w]|$)"),
+                    "$1\x1b[3m$2\x1b[23m$3".to_string(),
+                ),
+            ],
+        }
+    }
+
+    /// Emphasis, links and inline code in `text`
+    fn render(&self, text: &str) -> String {
+        let mut out = String::new();
+        let mut last = 0;
+        for c in self.code.captures_iter(text) {
+            let whole = c.get(0).unwrap();
+            out += self.style(&text[last..whole.start()]).as_str();
+            out += format!("{CODE}{}{RESET}", &c[1]).as_str();
+            last = whole.end();
+        }
+        out + self.style(&text[last..]).as_str()
+    }
+
+    fn style(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (pattern, replacement) in self.styles.iter() {
+            text = pattern
+                .replace_all(text.as_str(), replacement.as_str())
+                .to_string();
+        }
+        text
+    }
+}
+
+/// The columns `text` takes, not counting ANSI escapes
+fn visible_len(text: &str) -> usize {
+    let mut len = 0;
+    let mut escape = false;
+    for c in text.chars() {
+        match (escape, c) {
+            (false, '\x1b') => escape = true,
+            (false, _) => len += 1,
+            (true, 'm') => escape = false,
+            (true, _) => (),
+        }
+    }
+    len
+}
+
+/// The keywords, the start of a line comment and the quotes of
+/// strings in `language`.  `None` if it is not known
+fn syntax(language: &str) -> Option<(&'static [&'static str], &'static str, &'static str)> {
+    Some(match language {
+        "rust" | "rs" => (
+            &[
+                "as", "break", "const", "continue", "crate", "else", "enum", "false", "fn", "for",
+                "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+                "return", "self", "Self", "static", "struct", "trait", "true", "type", "unsafe",
+                "use", "where", "while", "async", "await", "dyn",
+            ],
+            "//",
+            "\"",
+        ),
+        "python" | "py" => (
+            &[
+                "and", "as", "assert", "async", "await", "break", "class", "continue", "def",
+                "del", "elif", "else", "except", "False", "finally", "for", "from", "global", "if",
+                "import", "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise",
+                "return", "True", "try", "while", "with", "yield",
+            ],
+            "#",
+            "\"'",
+        ),
+        "javascript" | "js" | "typescript" | "ts" | "jsx" | "tsx" => (
+            &[
+                "async",
+                "await",
+                "break",
+                "case",
+                "catch",
+                "class",
+                "const",
+                "continue",
+                "default",
+                "delete",
+                "else",
+                "export",
+                "extends",
+                "false",
+                "finally",
+                "for",
+                "function",
+                "if",
+                "import",
+                "in",
+                "instanceof",
+                "interface",
+                "let",
+                "new",
+                "null",
+                "of",
+                "return",
+                "switch",
+                "this",
+                "throw",
+                "true",
+                "try",
+                "type",
+                "typeof",
+                "undefined",
+                "var",
+                "void",
+                "while",
+                "yield",
+            ],
+            "//",
+            "\"'`",
+        ),
+        "c" | "h" | "cpp" | "c++" | "java" | "cs" | "csharp" => (
+            &[
+                "auto",
+                "bool",
+                "break",
+                "case",
+                "catch",
+                "char",
+                "class",
+                "const",
+                "continue",
+                "default",
+                "delete",
+                "do",
+                "double",
+                "else",
+                "enum",
+                "extends",
+                "false",
+                "final",
+                "float",
+                "for",
+                "if",
+                "implements",
+                "import",
+                "int",
+                "long",
+                "namespace",
+                "new",
+                "null",
+                "nullptr",
+                "private",
+                "protected",
+                "public",
+                "return",
+                "short",
+                "static",
+                "struct",
+                "switch",
+                "template",
+                "this",
+                "throw",
+                "true",
+                "try",
+                "typedef",
+                "unsigned",
+                "using",
+                "void",
+                "while",
+            ],
+            "//",
+            "\"'",
+        ),
+        "go" => (
+            &[
+                "break",
+                "case",
+                "chan",
+                "const",
+                "continue",
+                "default",
+                "defer",
+                "else",
+                "fallthrough",
+                "false",
+                "for",
+                "func",
+                "go",
+                "goto",
+                "if",
+                "import",
+                "interface",
+                "map",
+                "nil",
+                "package",
+                "range",
+                "return",
+                "select",
+                "struct",
+                "switch",
+                "true",
+                "type",
+                "var",
+            ],
+            "//",
+            "\"'`",
+        ),
+        "sh" | "bash" | "shell" | "zsh" | "console" => (
+            &[
+                "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function",
+                "if", "in", "local", "return", "then", "until", "while",
+            ],
+            "#",
+            "\"'",
+        ),
+        "sql" => (
+            &[
+                "select", "from", "where", "insert", "into", "values", "update", "set", "delete",
+                "create", "table", "drop", "alter", "join", "left", "right", "inner", "outer",
+                "on", "group", "by", "order", "having", "limit", "and", "or", "not", "null", "as",
+                "distinct", "union", "primary", "key", "index",
+            ],
+            "--",
+            "'\"",
+        ),
+        _ => return None,
+    })
+}
+
+/// A line of code in `language`, with keywords, strings, numbers and
+/// comments coloured.  Unknown languages are not coloured
+fn highlight(line: &str, language: &str) -> String {
+    let (keywords, comment, quotes) = match syntax(language) {
+        Some(syntax) => syntax,
+        None => return line.to_string(),
+    };
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if line[char_offset(line, i)..].starts_with(comment) {
+            let rest: String = chars[i..].iter().collect();
+            out += format!("{COMMENT}{rest}{RESET}").as_str();
+            break;
+        }
+        if quotes.contains(c) {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(chars.len());
+            let text: String = chars[start..i].iter().collect();
+            out += format!("{STRING}{text}{RESET}").as_str();
+            continue;
+        }
+        if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            let keyword = if language == "sql" {
+                keywords.contains(&word.to_lowercase().as_str())
+            } else {
+                keywords.contains(&word.as_str())
+            };
+            if keyword {
+                out += format!("{KEYWORD}{word}{RESET}").as_str();
+            } else if c.is_ascii_digit() {
+                out += format!("{NUMBER}{word}{RESET}").as_str();
+            } else {
+                out += word.as_str();
+            }
+            continue;
+        }
+        out.push(c);
+        i += 1;
+    }
+    out
+}
+
+/// The byte offset of the character at `index`
+fn char_offset(text: &str, index: usize) -> usize {
+    text.char_indices()
+        .nth(index)
+        .map(|(o, _)| o)
+        .unwrap_or(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(text: &str, pieces: usize) -> String {
+        let mut stream = MarkdownStream::new(80, true);
+        let chars: Vec<char> = text.chars().collect();
+        let mut out = String::new();
+        for piece in chars.chunks(pieces) {
+            out += stream
+                .push(piece.iter().collect::<String>().as_str())
+                .as_str();
+        }
+        out + stream.finish().as_str()
+    }
+
+    /// What is left on the screen: the erased lines taken out
+    fn screen(out: &str) -> String {
+        let erased = Regex::new("[^\n]*\r\x1b\\[J").unwrap();
+        erased.replace_all(out, "").to_string()
+    }
+
+    #[test]
+    fn plain() {
+        let mut stream = MarkdownStream::new(80, false);
+        assert_eq!(stream.push("# Title\n**bold"), "# Title\n**bold");
+        assert_eq!(stream.finish(), "\n");
+        assert_eq!(stream.finish(), "");
+    }
+
+    #[test]
+    fn lines() {
+        let out = screen(&render(
+            "# Title\n- one *two* `x`\n> quote\n---\nA [link](http://x) and **bold**",
+            3,
+        ));
+        assert_eq!(
+            out,
+            "\x1b[1;4;35mTitle\x1b[0m\n\
+             \x1b[1m•\x1b[0m one \x1b[3mtwo\x1b[23m \x1b[36mx\x1b[0m\n\
+             \x1b[2m│\x1b[0m \x1b[3mquote\x1b[0m\n\
+             \x1b[2m"
+                .to_string()
+                + "─".repeat(80).as_str()
+                + "\x1b[0m\n\
+             A \x1b[4mlink\x1b[24m \x1b[2m(http://x)\x1b[0m and \x1b[1mbold\x1b[22m\n"
+        );
+    }
+
+    #[test]
+    fn table() {
+        let out = screen(&render("| a | bb |\n|---|---|\n| ccc | d |\nafter", 4));
+        assert!(out.starts_with(
+            "\x1b[2m┌─────┬────┐\x1b[0m\n\
+             \x1b[2m│\x1b[0m \x1b[1ma\x1b[0m   \x1b[2m│\x1b[0m \x1b[1mbb\x1b[0m \x1b[2m│\x1b[0m\n\
+             \x1b[2m├─────┼────┤\x1b[0m\n"
+        ));
+        assert!(out.ends_with("\x1b[2m└─────┴────┘\x1b[0m\nafter\n"));
+    }
+
+    #[test]
+    fn code() {
+        let out = screen(&render("```rust\nlet x = \"a\"; // 1\n```\n", 5));
+        assert_eq!(
+            out,
+            "\x1b[2m```rust\x1b[0m\n\
+             \x1b[35mlet\x1b[0m x = \x1b[32m\"a\"\x1b[0m; \x1b[90m// 1\x1b[0m\n\
+             \x1b[2m```\x1b[0m\n"
+        );
+        assert_eq!(
+            highlight("SELECT 1", "sql"),
+            "\x1b[35mSELECT\x1b[0m \x1b[33m1\x1b[0m"
+        );
+        assert_eq!(highlight("x = 1", "unknown"), "x = 1");
+    }
+
+    #[test]
+    fn wrapped() {
+        let mut stream = MarkdownStream::new(10, true);
+        stream.push("a".repeat(25).as_str());
+        assert!(stream.push("\n").starts_with("\r\x1b[2A\x1b[J"));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::model_registry::Capability;
+use crate::model_registry::ModelEntry;
 use std::str::FromStr;
 /// The modes for the interaction with Open AI
 #[derive(Debug, Clone, PartialEq)]
@@ -8,18 +10,6 @@ pub enum ModelMode {
     ImageEdit,
     AudioTranscription,
 }
-const MODELS_COMPLETIONS: [&str; 6] = [
-    "text-babbage-001",
-    "text-curie-001",
-    "text-davinci-001",
-    "text-davinci-002",
-    "text-davinci-003",
-    "text-davinci-edit-001",
-];
-const MODELS_CHAT: [&str; 2] = ["gpt-3.5-turbo", "gpt-4"];
-
-const MODELS_AUDIOTRANSCRIPTION: [&str; 1] = ["whisper-1"];
-
 impl std::fmt::Display for ModelMode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let str = match self {
@@ -51,13 +41,23 @@ impl FromStr for ModelMode {
 }
 
 impl ModelMode {
-    pub fn models_available(&self) -> Vec<&str> {
+    /// What a model needs to be able to do to be used in this mode
+    pub fn capability(&self) -> Capability {
         match self {
-            ModelMode::Completions => MODELS_COMPLETIONS.to_vec(),
-            ModelMode::Chat => MODELS_CHAT.to_vec(),
-            ModelMode::Image => [].to_vec(),
-            ModelMode::ImageEdit => [].to_vec(),
-            ModelMode::AudioTranscription => MODELS_AUDIOTRANSCRIPTION.to_vec(),
+            ModelMode::Completions => Capability::Completions,
+            ModelMode::Chat => Capability::Chat,
+            ModelMode::Image => Capability::Image,
+            ModelMode::ImageEdit => Capability::Image,
+            ModelMode::AudioTranscription => Capability::Transcription,
         }
     }
+
+    /// The models in `models` that can be used in this mode
+    pub fn models_available<'a>(&self, models: &'a [ModelEntry]) -> Vec<&'a str> {
+        models
+            .iter()
+            .filter(|m| m.can(self.capability()))
+            .map(|m| m.id.as_str())
+            .collect()
+    }
 }
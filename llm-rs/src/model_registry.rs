@@ -0,0 +1,356 @@
+//! What models there are and what they can do.  The list of models
+//! comes from OpenAI (https://platform.openai.com/docs/api-reference/models)
+//! and is cached on disk.  OpenAI does not say what a model can do,
+//! so that comes from a registry of model name prefixes that can be
+//! overridden from a JSON file
+use crate::openai_interface::ApiInterface;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long the cached list of models is used before it is fetched
+/// again
+pub const MODEL_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The things a model can do, or accept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Chat,
+    Completions,
+    Vision,
+    Tools,
+    Audio,
+    Transcription,
+    Image,
+    Embeddings,
+    Moderation,
+    /// Accepts a "system" message
+    SystemRole,
+    /// Accepts `temperature`
+    Temperature,
+    /// Thinks before answering.  Takes `max_completion_tokens`, not
+    /// `max_tokens`, and reports reasoning tokens in its usage
+    Reasoning,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            Capability::Chat => "chat",
+            Capability::Completions => "completions",
+            Capability::Vision => "vision",
+            Capability::Tools => "tools",
+            Capability::Audio => "audio",
+            Capability::Transcription => "transcription",
+            Capability::Image => "image",
+            Capability::Embeddings => "embeddings",
+            Capability::Moderation => "moderation",
+            Capability::SystemRole => "system_role",
+            Capability::Temperature => "temperature",
+            Capability::Reasoning => "reasoning",
+        };
+        write!(f, "{str}")
+    }
+}
+
+/// What is known about models OpenAI has not told us about.  The
+/// longest prefix of a model's name that is here decides its
+/// capabilities
+const BUILT_IN: [(&str, &[Capability]); 34] = {
+    use Capability::*;
+    [
+        ("gpt-5", &[Chat, Vision, Tools, SystemRole, Reasoning]),
+        ("gpt-5-chat", &[Chat, Vision, SystemRole, Temperature]),
+        ("gpt-4.1", &[Chat, Vision, Tools, SystemRole, Temperature]),
+        ("gpt-4.5", &[Chat, Vision, Tools, SystemRole, Temperature]),
+        ("gpt-4o", &[Chat, Vision, Tools, SystemRole, Temperature]),
+        (
+            "gpt-4o-audio",
+            &[Chat, Audio, Tools, SystemRole, Temperature],
+        ),
+        (
+            "gpt-4o-mini-audio",
+            &[Chat, Audio, Tools, SystemRole, Temperature],
+        ),
+        ("gpt-4o-realtime", &[Audio]),
+        ("gpt-4o-mini-realtime", &[Audio]),
+        ("gpt-4o-search", &[Chat, SystemRole]),
+        ("gpt-4o-mini-search", &[Chat, SystemRole]),
+        ("gpt-4o-transcribe", &[Transcription]),
+        ("gpt-4o-mini-transcribe", &[Transcription]),
+        ("gpt-4o-mini-tts", &[Audio]),
+        ("chatgpt-4o", &[Chat, Vision, SystemRole, Temperature]),
+        (
+            "gpt-4-turbo",
+            &[Chat, Vision, Tools, SystemRole, Temperature],
+        ),
+        ("gpt-4", &[Chat, Tools, SystemRole, Temperature]),
+        ("gpt-3.5-turbo", &[Chat, Tools, SystemRole, Temperature]),
+        ("gpt-3.5-turbo-instruct", &[Completions, Temperature]),
+        ("o1", &[Chat, Vision, Tools, SystemRole, Reasoning]),
+        ("o1-mini", &[Chat, Reasoning]),
+        ("o1-preview", &[Chat, Reasoning]),
+        ("o3", &[Chat, Vision, Tools, SystemRole, Reasoning]),
+        ("o3-mini", &[Chat, Tools, SystemRole, Reasoning]),
+        ("o4-mini", &[Chat, Vision, Tools, SystemRole, Reasoning]),
+        ("davinci-002", &[Completions, Temperature]),
+        ("babbage-002", &[Completions, Temperature]),
+        ("text-embedding", &[Embeddings]),
+        ("whisper", &[Transcription]),
+        ("tts-", &[Audio]),
+        ("dall-e", &[Image]),
+        ("gpt-image", &[Image]),
+        ("omni-moderation", &[Moderation]),
+        ("text-moderation", &[Moderation]),
+    ]
+};
+
+/// Maps model name prefixes to capabilities
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelRegistry {
+    rules: HashMap<String, BTreeSet<Capability>>,
+}
+
+impl Default for ModelRegistry {
+    fn default() -> Self {
+        let rules = BUILT_IN
+            .iter()
+            .map(|(prefix, capabilities)| {
+                (prefix.to_string(), capabilities.iter().copied().collect())
+            })
+            .collect();
+        ModelRegistry { rules }
+    }
+}
+
+impl ModelRegistry {
+    /// The built in registry, with the rules in the JSON file at
+    /// `path` added.  The file maps prefixes to lists of
+    /// capabilities: `{"my-model": ["chat", "system_role"]}`.  A rule
+    /// in the file replaces a built in rule for the same prefix.  It
+    /// is not an error if there is no file
+    pub fn with_overrides(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut registry = Self::default();
+        if path.exists() {
+            let overrides: HashMap<String, BTreeSet<Capability>> =
+                serde_json::from_str(fs::read_to_string(path)?.as_str())?;
+            registry.rules.extend(overrides);
+        }
+        Ok(registry)
+    }
+
+    /// Add or replace the rule for `prefix`
+    pub fn set(&mut self, prefix: &str, capabilities: BTreeSet<Capability>) {
+        self.rules.insert(prefix.to_string(), capabilities);
+    }
+
+    /// What the model called `id` can do.  Fine tuned models
+    /// ("ft:<base model>:<org>:...") can do what their base model can.
+    /// A model that matches no rule can do nothing
+    pub fn capabilities(&self, id: &str) -> BTreeSet<Capability> {
+        let id = match id.strip_prefix("ft:") {
+            Some(rest) => rest.split(':').next().unwrap_or(rest),
+            None => id,
+        };
+        self.rules
+            .iter()
+            .filter(|(prefix, _)| id.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, capabilities)| capabilities.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// A model as listed by OpenAI
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelSummary {
+    pub id: String,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub owned_by: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelSummaryList {
+    pub data: Vec<ModelSummary>,
+}
+
+/// A model and what it can do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelEntry {
+    pub id: String,
+    pub created: i64,
+    pub owned_by: String,
+    pub capabilities: BTreeSet<Capability>,
+}
+
+impl ModelEntry {
+    pub fn can(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+impl fmt::Display for ModelEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({})",
+            self.id,
+            self.capabilities
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    }
+}
+
+/// The list of models as stored on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelCache {
+    /// Unix time the list was fetched
+    pub fetched_at: i64,
+    pub models: Vec<ModelSummary>,
+}
+
+impl ModelCache {
+    /// The cache at `path`, if there is one that can be read
+    pub fn load(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        serde_json::from_str(contents.as_str()).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Fetched less than `ttl` ago
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        Utc::now().timestamp() - self.fetched_at < ttl.as_secs() as i64
+    }
+}
+
+/// Every model, with its capabilities, sorted by name.  The list
+/// cached at `cache` is used if it is younger than `ttl`, otherwise
+/// it is fetched and the cache rewritten.  If fetching fails a stale
+/// cache is better than nothing
+pub fn available_models(
+    api_interface: &ApiInterface,
+    cache: &Path,
+    ttl: Duration,
+    registry: &ModelRegistry,
+) -> Result<Vec<ModelEntry>, Box<dyn Error>> {
+    let cached = ModelCache::load(cache);
+    let models = match cached {
+        Some(cached) if cached.is_fresh(ttl) => cached.models,
+        cached => match api_interface.models() {
+            Ok(res) => {
+                let fetched = ModelCache {
+                    fetched_at: Utc::now().timestamp(),
+                    models: res.body.data,
+                };
+                fetched.save(cache)?;
+                fetched.models
+            }
+            Err(err) => match cached {
+                Some(cached) => cached.models,
+                None => return Err(err),
+            },
+        },
+    };
+    Ok(classify(models, registry))
+}
+
+/// Attach capabilities to models, sorted by name
+pub fn classify(models: Vec<ModelSummary>, registry: &ModelRegistry) -> Vec<ModelEntry> {
+    let mut result: Vec<ModelEntry> = models
+        .into_iter()
+        .map(|m| ModelEntry {
+            capabilities: registry.capabilities(m.id.as_str()),
+            id: m.id,
+            created: m.created,
+            owned_by: m.owned_by,
+        })
+        .collect();
+    result.sort_by(|a, b| a.id.cmp(&b.id));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::Usage;
+
+    #[test]
+    fn every_model_that_chats_or_completes_has_a_price() {
+        let usage = Usage {
+            prompt_tokens: 1000,
+            completion_tokens: 1000,
+            total_tokens: 2000,
+            completion_tokens_details: None,
+        };
+        for (prefix, capabilities) in BUILT_IN.iter() {
+            if capabilities.contains(&Capability::Chat)
+                || capabilities.contains(&Capability::Completions)
+            {
+                let cost = ApiInterface::cost(usage.clone(), prefix);
+                assert!(cost.is_some_and(|c| c > 0.0), "No price for {prefix}");
+            }
+        }
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let registry = ModelRegistry::default();
+        assert!(registry
+            .capabilities("gpt-4o-2024-08-06")
+            .contains(&Capability::Vision));
+        let transcribe = registry.capabilities("gpt-4o-mini-transcribe");
+        assert!(transcribe.contains(&Capability::Transcription));
+        assert!(!transcribe.contains(&Capability::Chat));
+        let o1_mini = registry.capabilities("o1-mini-2024-09-12");
+        assert!(o1_mini.contains(&Capability::Chat));
+        assert!(!o1_mini.contains(&Capability::SystemRole));
+        assert!(!o1_mini.contains(&Capability::Temperature));
+        assert!(registry
+            .capabilities("ft:gpt-4o-mini-2024-07-18:org::abc123")
+            .contains(&Capability::Chat));
+        assert!(registry.capabilities("mystery").is_empty());
+    }
+
+    #[test]
+    fn overrides() {
+        let mut registry = ModelRegistry::default();
+        registry.set("mystery", [Capability::Chat].into_iter().collect());
+        registry.set("gpt-4o", BTreeSet::new());
+        assert!(registry
+            .capabilities("mystery-1")
+            .contains(&Capability::Chat));
+        assert!(registry.capabilities("gpt-4o").is_empty());
+        let overrides: HashMap<String, BTreeSet<Capability>> =
+            serde_json::from_str(r#"{"x": ["chat", "system_role"]}"#).unwrap();
+        assert_eq!(overrides["x"].len(), 2);
+    }
+
+    #[test]
+    fn cache_freshness() {
+        let cache = ModelCache {
+            fetched_at: Utc::now().timestamp() - 100,
+            models: vec![],
+        };
+        assert!(cache.is_fresh(Duration::from_secs(1000)));
+        assert!(!cache.is_fresh(Duration::from_secs(10)));
+    }
+}
@@ -1,11 +1,36 @@
 use crate::api_error::ApiError;
 use crate::api_error::ApiErrorType;
 use crate::api_result::ApiResult;
+use crate::batch::chat_batch_requests;
+use crate::batch::parse_output;
+use crate::batch::to_jsonl;
+use crate::batch::Batch;
+use crate::batch::BatchList;
+use crate::batch::BatchOutputLine;
+use crate::batch::BatchRequestLine;
+use crate::batch::BATCH_CHAT_ENDPOINT;
+use crate::batch::BATCH_COMPLETION_WINDOW;
+use crate::budget::Budget;
+use crate::chat_request::chat_request_body;
+use crate::chat_request::listed_cost;
+use crate::chat_request::ChatOptions;
+use crate::completion_request::CompletionOptions;
+use crate::completion_request::FillInMiddle;
 use crate::context::Context;
-use crate::fine_tune_create::FineTuneCreate;
-use crate::fine_tune_list::FineTuneList;
-use crate::fine_tune_retrieve::FineTuneRetrieve;
+use crate::fine_tuning_job::FineTuningJob;
+use crate::fine_tuning_job::FineTuningJobCheckpointList;
+use crate::fine_tuning_job::FineTuningJobEvent;
+use crate::fine_tuning_job::FineTuningJobEventList;
+use crate::fine_tuning_job::FineTuningJobList;
+use crate::fine_tuning_job::FineTuningJobRequest;
+use crate::fine_tuning_job::ModelDeleted;
+use crate::fine_tuning_validate::validate_file;
+use crate::interceptor::Call;
+use crate::interceptor::Endpoint;
+use crate::interceptor::InterceptorChain;
 use crate::json::AudioTranscriptionResponse;
+use crate::json::ChatChoice;
+use crate::json::ChatChunk;
 use crate::json::ChatRequestInfo;
 use crate::json::CompletionRequestInfo;
 use crate::json::FileDeletedResponse;
@@ -16,24 +41,41 @@ use crate::json::ImageRequestInfo;
 use crate::json::Message;
 use crate::json::Usage;
 use crate::model_info::ModelInfo;
+use crate::model_registry::ModelRegistry;
+use crate::model_registry::ModelSummaryList;
+use crate::response_cache::CACHE_HEADER;
+use crate::tokens::estimate_chat_tokens;
+use crate::tokens::estimate_tokens;
+use crate::transport::HttpTransport;
+use crate::transport::Transport;
 use chrono::{NaiveDateTime, TimeZone, Utc};
-use curl::easy::Easy;
-use curl::easy::List;
 use reqwest::blocking::multipart;
 use reqwest::blocking::Client;
-use reqwest::blocking::ClientBuilder;
+use reqwest::blocking::Request;
 use reqwest::blocking::RequestBuilder;
+use reqwest::blocking::Response;
 use reqwest::header::HeaderMap;
 use reqwest::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::Method;
 use reqwest::StatusCode;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
 use serde_json::json;
+use serde_json::Value;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
+use std::io::BufRead;
+use std::io::BufReader;
 use std::io::Read;
+use std::io::Write;
 use std::path::Path;
 use std::result::Result;
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::Duration;
 use std::time::Instant;
 
 // URLS:
@@ -51,33 +93,75 @@ use std::time::Instant;
 // * Files, delete: DELETE https://api.openai.com/v1/files/{file_id}
 // * Files, retrieve: GET https://api.openai.com/v1/files/{file_id}
 // * Files, retrieve content: GET https://api.openai.com/v1/files/{file_id}/content
-// * Fine tune, create: POST https://api.openai.com/v1/fine-tunes
-// * Fine tune, list: GET https://api.openai.com/v1/fine-tunes
-// Fine tune, retrieve: GET https://api.openai.com/v1/fine-tunes/{fine_tune_id}
-// Fine tune, cancel: POST https://api.openai.com/v1/fine-tunes/{fine_tune_id}/cancel
-// Fine tune, events: GET https://api.openai.com/v1/fine-tunes/{fine_tune_id}/events
-// Fine tune, delete: DELETE https://api.openai.com/v1/models/{model}
+// * Fine tuning, create: POST https://api.openai.com/v1/fine_tuning/jobs
+// * Fine tuning, list: GET https://api.openai.com/v1/fine_tuning/jobs
+// * Fine tuning, retrieve: GET https://api.openai.com/v1/fine_tuning/jobs/{fine_tuning_job_id}
+// * Fine tuning, cancel: POST https://api.openai.com/v1/fine_tuning/jobs/{fine_tuning_job_id}/cancel
+// * Fine tuning, events: GET https://api.openai.com/v1/fine_tuning/jobs/{fine_tuning_job_id}/events
+// * Fine tuning, checkpoints: GET https://api.openai.com/v1/fine_tuning/jobs/{fine_tuning_job_id}/checkpoints
+// * Models, list: GET https://api.openai.com/v1/models
+// * Fine tuned model, delete: DELETE https://api.openai.com/v1/models/{model}
+// * Batch, create: POST https://api.openai.com/v1/batches
+// * Batch, retrieve: GET https://api.openai.com/v1/batches/{batch_id}
+// * Batch, cancel: POST https://api.openai.com/v1/batches/{batch_id}/cancel
+// * Batch, list: GET https://api.openai.com/v1/batches
 // Moderations: POST https://api.openai.com/v1/moderations
 
+/// Set on results when spending gets close to a limit of the budget
+pub const BUDGET_WARNING_HEADER: &str = "Budget warning";
+
+/// What an image costs, in cents.  1024x1024 from DALL·E 2, the
+/// default model for generations and edits
+pub const IMAGE_COST: f64 = 2.0;
+
 /// Bas URI for requests
-const API_URL: &str = "https://api.openai.com/v1";
+pub const API_URL: &str = "https://api.openai.com/v1";
 
 #[derive(Debug)]
 pub struct ApiInterface {
-    /// Handles the communications with OpenAI
+    /// Builds requests.  They are sent by `transport`
     client: Client,
 
+    /// Sends requests.  Replace it to change timeouts, use a proxy,
+    /// record traffic (see `cassette`) or talk to a mock
+    pub transport: Arc<dyn Transport>,
+
     /// The secret key from OpenAI
     api_key: String,
 
+    /// Where requests are sent.  `API_URL` unless pointed somewhere
+    /// else, like a mock server for testing
+    pub api_url: String,
+
     /// Restricts the amount of text returned
     pub tokens: u32,
 
     /// Influences the predictability/repeatability of the model
     pub temperature: f32,
 
+    /// The parameters of chat requests.  Its `temperature` and
+    /// `max_tokens`, if set, are used instead of `temperature` and
+    /// `tokens`
+    pub chat_options: ChatOptions,
+
+    /// The parameters of completion requests, besides `temperature`
+    /// and `tokens`
+    pub completion_options: CompletionOptions,
+
     /// Chat keeps its state here.
     pub context: Context,
+
+    /// What models can do.  Chat requests are shaped to suit the
+    /// model
+    pub model_registry: ModelRegistry,
+
+    /// Limits on spending.  Chats and completions that could cost
+    /// more than is left are refused before they are sent
+    pub budget: Option<Budget>,
+
+    /// Called around every chat, completion, image, audio and file
+    /// call
+    pub interceptors: InterceptorChain,
 }
 
 impl Display for ApiInterface {
@@ -88,43 +172,62 @@ impl Display for ApiInterface {
 		     Tokens: {}\n\
 		     Context length: {}\n\
 		     Context cost: {:2}\n\
-		     System prompt: {}",
+		     System prompt: {}\n\
+		     Chat options:\n{}\n\
+		     Completion options:\n{}",
             self.temperature,
             self.tokens,
             self.context.len(),
             self.context.cost,
             self.context.purpose,
+            self.chat_options,
+            self.completion_options,
         )
     }
 }
 
 impl ApiInterface {
     pub fn new(api_key: String, tokens: u32, temperature: f32) -> ApiInterface {
+        let transport = HttpTransport::default();
         ApiInterface {
-            client: ClientBuilder::new()
-                .timeout(std::time::Duration::from_secs(1200))
-                .pool_idle_timeout(None)
-                .connection_verbose(false)
-                .build()
-                .unwrap(),
+            client: transport.client().clone(),
+            transport: Arc::new(transport),
             api_key,
+            api_url: API_URL.to_string(),
             tokens,
             temperature,
+            chat_options: ChatOptions::default(),
+            completion_options: CompletionOptions::default(),
             // model: model.to_string(),
             context: Context::new(""),
+            model_registry: ModelRegistry::default(),
+            budget: None,
+            interceptors: InterceptorChain::new(),
         }
     }
 
     /// Get information about a file
     pub fn file_info(&self, file_id: String) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let call = Call::new(Endpoint::FileInfo, None);
+        let result = self.file_info_call(&call, file_id);
+        self.interceptors.after(&call, &result);
+        result
+    }
+
+    fn file_info_call(
+        &self,
+        call: &Call,
+        file_id: String,
+    ) -> Result<ApiResult<String>, Box<dyn Error>> {
         // GET https://api.openai.com/v1/files/{file_id}
-        let uri = format!("{API_URL}/files/{file_id}");
-        let response = self
-            .client
-            .get(uri.as_str())
-            .header("Content-Type", "application/json")
-            .header(AUTHORIZATION, format!("Bearer {}", self.api_key))
-            .send()?;
+        let file_id = self.intercept_file_id(call, file_id)?;
+        let uri = format!("{}/files/{file_id}", self.api_url);
+        let response = self.send(
+            self.client
+                .get(uri.as_str())
+                .header("Content-Type", "application/json")
+                .header(AUTHORIZATION, format!("Bearer {}", self.api_key)),
+        )?;
         let headers = Self::header_map_to_hash_map(response.headers());
         if response.status() != StatusCode::OK {
             let reason = response
@@ -157,14 +260,26 @@ impl ApiInterface {
 
     /// Get file cotents
     pub fn file_contents(&self, file_id: String) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let call = Call::new(Endpoint::FileContents, None);
+        let result = self.file_contents_call(&call, file_id);
+        self.interceptors.after(&call, &result);
+        result
+    }
+
+    fn file_contents_call(
+        &self,
+        call: &Call,
+        file_id: String,
+    ) -> Result<ApiResult<String>, Box<dyn Error>> {
         // GET https://api.openai.com/v1/files/{file_id}/content
-        let uri = format!("{API_URL}/files/{file_id}/content");
-        let response = self
-            .client
-            .get(uri.as_str())
-            .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .send()?;
+        let file_id = self.intercept_file_id(call, file_id)?;
+        let uri = format!("{}/files/{file_id}/content", self.api_url);
+        let response = self.send(
+            self.client
+                .get(uri.as_str())
+                .header("Content-Type", "application/json")
+                .header("Authorization", format!("Bearer {}", self.api_key)),
+        )?;
         let headers = Self::header_map_to_hash_map(response.headers());
         if response.status() != StatusCode::OK {
             let reason = response
@@ -183,14 +298,26 @@ impl ApiInterface {
 
     /// Delete a file
     pub fn files_delete(&self, file_id: String) -> Result<ApiResult<()>, Box<dyn Error>> {
+        let call = Call::new(Endpoint::FileDelete, None);
+        let result = self.files_delete_call(&call, file_id);
+        self.interceptors.after(&call, &result);
+        result
+    }
+
+    fn files_delete_call(
+        &self,
+        call: &Call,
+        file_id: String,
+    ) -> Result<ApiResult<()>, Box<dyn Error>> {
         // DELETE https://api.openai.com/v1/files/{file_id}
-        let uri = format!("{API_URL}/files/{file_id}");
-        let response = self
-            .client
-            .delete(uri.as_str())
-            .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .send()?;
+        let file_id = self.intercept_file_id(call, file_id)?;
+        let uri = format!("{}/files/{file_id}", self.api_url);
+        let response = self.send(
+            self.client
+                .delete(uri.as_str())
+                .header("Content-Type", "application/json")
+                .header("Authorization", format!("Bearer {}", self.api_key)),
+        )?;
         let headers = Self::header_map_to_hash_map(response.headers());
         if response.status() != StatusCode::OK {
             let reason = response
@@ -219,13 +346,25 @@ impl ApiInterface {
 
     /// Get a list of all files stored on OpenAI
     pub fn files_list(&self) -> Result<ApiResult<Vec<(String, String)>>, Box<dyn Error>> {
+        let call = Call::new(Endpoint::FileList, None);
+        let result = self.files_list_call(&call);
+        self.interceptors.after(&call, &result);
+        result
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn files_list_call(
+        &self,
+        call: &Call,
+    ) -> Result<ApiResult<Vec<(String, String)>>, Box<dyn Error>> {
         // GET https://api.openai.com/v1/files
-        let uri = format!("{}/files", API_URL);
-        let response = self
-            .client
-            .get(uri)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .send()?;
+        self.interceptors.before(call, &mut json!({}))?;
+        let uri = format!("{}/files", self.api_url);
+        let response = self.send(
+            self.client
+                .get(uri)
+                .header("Authorization", format!("Bearer {}", self.api_key)),
+        )?;
 
         let headers = Self::header_map_to_hash_map(response.headers());
         let response_strings: Vec<(String, String)> = if response.status() != StatusCode::OK {
@@ -248,10 +387,42 @@ impl ApiInterface {
         Ok(ApiResult::new_v(response_strings, headers))
     }
 
-    /// Upload a file for fine-tuning.
+    /// Upload a file for fine-tuning `model`.  The file is checked
+    /// first and not sent if OpenAI would reject it
     pub fn files_upload_fine_tuning(
         &self,
         file: &Path,
+        model: &str,
+    ) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let report = validate_file(file, model, None)?;
+        if !report.is_valid() {
+            return Err(Box::new(ApiError::new(
+                ApiErrorType::InvalidData(format!("{report}")),
+                HashMap::new(),
+            )));
+        }
+        self.files_upload(file, "fine-tune")
+    }
+
+    /// Upload a file.  `purpose` is what it will be used for:
+    /// "fine-tune", "batch", "assistants", "vision" or "user_data".
+    /// Returns the file ID
+    pub fn files_upload(
+        &self,
+        file: &Path,
+        purpose: &str,
+    ) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let call = Call::new(Endpoint::FileUpload, None);
+        let result = self.files_upload_call(&call, file, purpose);
+        self.interceptors.after(&call, &result);
+        result
+    }
+
+    fn files_upload_call(
+        &self,
+        call: &Call,
+        file: &Path,
+        purpose: &str,
     ) -> Result<ApiResult<String>, Box<dyn Error>> {
         // Request
         // curl https://api.openai.com/v1/files \
@@ -269,19 +440,18 @@ impl ApiInterface {
         //   "purpose": "fine-tune"
         // }
 
-        let uri = format!("{}/files", API_URL);
+        let uri = format!("{}/files", self.api_url);
 
+        let mut payload = json!({ "purpose": purpose });
+        self.interceptors.before(call, &mut payload)?;
         let file_field = multipart::Part::file(file)?;
-        let purpose_field = multipart::Part::text("fine-tune");
-        let form = multipart::Form::new()
-            .part("file", file_field)
-            .part("purpose", purpose_field);
-        let response = self
-            .client
-            .post(uri)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .multipart(form)
-            .send()?;
+        let form = Self::form_text(multipart::Form::new().part("file", file_field), &payload);
+        let response = self.send(
+            self.client
+                .post(uri)
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .multipart(form),
+        )?;
         let headers = Self::header_map_to_hash_map(response.headers());
         let response_text: String = if response.status() != StatusCode::OK {
             let reason = response
@@ -306,6 +476,18 @@ impl ApiInterface {
         &mut self,
         audio_file: &Path,
         prompt: Option<&str>,
+    ) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let call = Call::new(Endpoint::AudioTranscription, Some("whisper-1"));
+        let result = self.audio_transcription_call(&call, audio_file, prompt);
+        self.interceptors.after(&call, &result);
+        result
+    }
+
+    fn audio_transcription_call(
+        &mut self,
+        call: &Call,
+        audio_file: &Path,
+        prompt: Option<&str>,
     ) -> Result<ApiResult<String>, Box<dyn Error>> {
         // Request
         // curl https://api.openai.com/v1/audio/transcriptions \
@@ -319,54 +501,29 @@ impl ApiInterface {
         //   "text": "Imagine the....that."
         // }
 
-        let uri = format!("{}/audio/transcriptions", API_URL);
+        let uri = format!("{}/audio/transcriptions", self.api_url);
 
-        let file_field = multipart::Part::file(audio_file)?;
-        let model_field = multipart::Part::text("whisper-1");
-        let mut form = multipart::Form::new()
-            .part("file", file_field)
-            .part("model", model_field);
+        let mut payload = json!({ "model": "whisper-1" });
         if let Some(prompt) = prompt {
-            let p: String = prompt.to_string();
-            let prompt_field = multipart::Part::text(p);
-            form = form.part("prompt", prompt_field);
+            payload["prompt"] = json!(prompt);
         }
+        self.interceptors.before(call, &mut payload)?;
+        // How long the audio is, and so what it costs, is not known.
+        // Only refuse if a limit has been reached
+        let warnings = self.check_estimate(0.0)?;
+        let file_field = multipart::Part::file(audio_file)?;
+        let form = Self::form_text(multipart::Form::new().part("file", file_field), &payload);
 
         // let client = reqwest::blocking::Client::new();
-        let response = self
-            .client
-            .post(uri)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .multipart(form)
-            .send()?;
+        let response = self.send(
+            self.client
+                .post(uri)
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .multipart(form),
+        )?;
 
-        let headers = Self::header_map_to_hash_map(response.headers());
+        let mut headers = Self::header_map_to_hash_map(response.headers());
         let response_text: String = if response.status() != StatusCode::OK {
-            format!(
-                "Failed: Status: {}.\nResponse.path({})",
-                response
-                    .status()
-                    .canonical_reason()
-                    .unwrap_or("Unknown Reason"),
-                response.url().path(),
-            )
-        } else {
-            response.json::<AudioTranscriptionResponse>()?.text
-        };
-
-        Ok(ApiResult::new(response_text, headers))
-    }
-
-    pub fn fine_tune_retrieve(&self, id: &str) -> Result<ApiResult<String>, Box<dyn Error>> {
-        let uri = format!("{API_URL}/fine-tunes/{id}");
-        let response = self
-            .client
-            .get(uri)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .send()?;
-
-        let headers = Self::header_map_to_hash_map(response.headers());
-        let body: String = if response.status() != StatusCode::OK {
             let reason = response
                 .status()
                 .canonical_reason()
@@ -376,62 +533,406 @@ impl ApiInterface {
                 headers,
             )));
         } else {
-            format!("{}", response.json::<FineTuneRetrieve>()?)
+            response.json::<AudioTranscriptionResponse>()?.text
         };
+        self.record_spending(0.0, warnings, &mut headers);
 
-        Ok(ApiResult { headers, body })
+        Ok(ApiResult::new(response_text, headers))
     }
 
+    /// Create a fine tuning job.  The training file (and optional
+    /// validation file) must already be uploaded
     pub fn fine_tune_create(
         &self,
-        training_file_id: String,
-    ) -> Result<ApiResult<String>, Box<dyn Error>> {
-        let uri = format!("{API_URL}/fine-tunes");
-        let request_body = json!({
-                "training_file": training_file_id.as_str()
+        request: &FineTuningJobRequest,
+    ) -> Result<ApiResult<FineTuningJob>, Box<dyn Error>> {
+        // POST https://api.openai.com/v1/fine_tuning/jobs
+        let uri = format!("{}/fine_tuning/jobs", self.api_url);
+        self.post_json(uri.as_str(), &serde_json::to_value(request)?)
+    }
+
+    /// List the fine tuning jobs for the organisation
+    pub fn fine_tune_list(&self) -> Result<ApiResult<FineTuningJobList>, Box<dyn Error>> {
+        // GET https://api.openai.com/v1/fine_tuning/jobs
+        let uri = format!("{}/fine_tuning/jobs", self.api_url);
+        self.get_json(uri.as_str())
+    }
+
+    /// Get the state of a fine tuning job
+    pub fn fine_tune_retrieve(&self, id: &str) -> Result<ApiResult<FineTuningJob>, Box<dyn Error>> {
+        // GET https://api.openai.com/v1/fine_tuning/jobs/{fine_tuning_job_id}
+        let uri = format!("{}/fine_tuning/jobs/{id}", self.api_url);
+        self.get_json(uri.as_str())
+    }
+
+    /// Get status updates for a fine tuning job.  `after` is the ID
+    /// of the last event already seen.  OpenAI returns the newest
+    /// events first
+    pub fn fine_tune_events(
+        &self,
+        id: &str,
+        after: Option<&str>,
+    ) -> Result<ApiResult<FineTuningJobEventList>, Box<dyn Error>> {
+        // GET https://api.openai.com/v1/fine_tuning/jobs/{fine_tuning_job_id}/events
+        let uri = match after {
+            Some(after) => {
+                format!(
+                    "{}/fine_tuning/jobs/{id}/events?limit=100&after={after}",
+                    self.api_url
+                )
+            }
+            None => format!("{}/fine_tuning/jobs/{id}/events?limit=100", self.api_url),
+        };
+        self.get_json(uri.as_str())
+    }
+
+    /// Cancel a fine tuning job that has not finished
+    pub fn fine_tune_cancel(&self, id: &str) -> Result<ApiResult<FineTuningJob>, Box<dyn Error>> {
+        // POST https://api.openai.com/v1/fine_tuning/jobs/{fine_tuning_job_id}/cancel
+        let uri = format!("{}/fine_tuning/jobs/{id}/cancel", self.api_url);
+        self.post_json(uri.as_str(), &json!({}))
+    }
+
+    /// List the checkpoints created during a fine tuning job
+    pub fn fine_tune_checkpoints(
+        &self,
+        id: &str,
+    ) -> Result<ApiResult<FineTuningJobCheckpointList>, Box<dyn Error>> {
+        // GET https://api.openai.com/v1/fine_tuning/jobs/{fine_tuning_job_id}/checkpoints
+        let uri = format!("{}/fine_tuning/jobs/{id}/checkpoints", self.api_url);
+        self.get_json(uri.as_str())
+    }
+
+    /// Delete a fine tuned model.  Needs the "owner" role in the
+    /// organisation
+    pub fn fine_tune_delete_model(&self, model: &str) -> Result<ApiResult<()>, Box<dyn Error>> {
+        // DELETE https://api.openai.com/v1/models/{model}
+        let uri = format!("{}/models/{model}", self.api_url);
+        let response = self.send(
+            self.client
+                .delete(uri.as_str())
+                .header(AUTHORIZATION, format!("Bearer {}", self.api_key)),
+        )?;
+        let result: ApiResult<ModelDeleted> = Self::json_result(response)?;
+        if !result.body.deleted {
+            Err(Box::new(ApiError::new(
+                ApiErrorType::Error(format!("Model delete response: {:?}", result.body)),
+                result.headers,
+            )))
+        } else {
+            Ok(ApiResult::new_e(result.headers))
+        }
+    }
+
+    /// Poll a fine tuning job until it finishes.  Every new event is
+    /// passed to `on_event`, oldest first.  Returns the finished job
+    pub fn fine_tune_monitor(
+        &self,
+        id: &str,
+        interval: Duration,
+        mut on_event: impl FnMut(&FineTuningJobEvent),
+    ) -> Result<ApiResult<FineTuningJob>, Box<dyn Error>> {
+        // `after` pages to older events, so each poll gets the newest
+        // and skips those already seen
+        let mut seen: HashSet<String> = HashSet::new();
+        loop {
+            // The job first, so the events of a finished job are all
+            // there
+            let job = self.fine_tune_retrieve(id)?;
+            let events = self.fine_tune_events(id, None)?;
+            // Events arrive newest first
+            for event in events.body.data.iter().rev() {
+                if seen.insert(event.id.clone()) {
+                    on_event(event);
+                }
+            }
+            if job.body.is_finished() {
+                return Ok(job);
+            }
+            sleep(interval);
+        }
+    }
+
+    /// Create a batch from an uploaded input file (purpose "batch")
+    pub fn batch_create(
+        &self,
+        input_file_id: &str,
+        endpoint: &str,
+    ) -> Result<ApiResult<Batch>, Box<dyn Error>> {
+        // POST https://api.openai.com/v1/batches
+        let uri = format!("{}/batches", self.api_url);
+        let data = json!({
+            "input_file_id": input_file_id,
+            "endpoint": endpoint,
+            "completion_window": BATCH_COMPLETION_WINDOW,
         });
+        self.post_json(uri.as_str(), &data)
+    }
 
-        let mut response = self
-            .client
-            .post(uri)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
-            .json(&request_body)
-            .send()?;
-        let mut s = String::new();
-        _ = response.read_to_string(&mut s)?;
-        let headers = Self::header_map_to_hash_map(response.headers());
-        let st = s.as_str();
-        let fine_tune: FineTuneCreate = serde_json::from_str(st)?;
+    /// Get the state of a batch
+    pub fn batch_retrieve(&self, id: &str) -> Result<ApiResult<Batch>, Box<dyn Error>> {
+        // GET https://api.openai.com/v1/batches/{batch_id}
+        let uri = format!("{}/batches/{id}", self.api_url);
+        self.get_json(uri.as_str())
+    }
 
-        Ok(ApiResult {
-            headers,
-            body: format!("Fine Tune: {fine_tune}"),
-        })
+    /// Cancel a batch.  It is "cancelling" for up to ten minutes
+    /// before it is "cancelled".  Requests already done are in the
+    /// output file
+    pub fn batch_cancel(&self, id: &str) -> Result<ApiResult<Batch>, Box<dyn Error>> {
+        // POST https://api.openai.com/v1/batches/{batch_id}/cancel
+        let uri = format!("{}/batches/{id}/cancel", self.api_url);
+        self.post_json(uri.as_str(), &json!({}))
     }
 
-    pub fn fine_tune_list(&self) -> Result<ApiResult<String>, Box<dyn Error>> {
-        let uri = format!("{API_URL}/fine-tunes");
+    /// List the batches for the organisation, newest first
+    pub fn batch_list(&self) -> Result<ApiResult<BatchList>, Box<dyn Error>> {
+        // GET https://api.openai.com/v1/batches
+        let uri = format!("{}/batches", self.api_url);
+        self.get_json(uri.as_str())
+    }
 
-        let mut response = self
-            .client
-            .get(uri)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
-            .send()?;
-        let mut s = String::new();
-        _ = response.read_to_string(&mut s)?;
-        let st = s.as_str();
-        let fine_tune_list: FineTuneList = serde_json::from_str(st)?;
-        let headers = Self::header_map_to_hash_map(response.headers());
+    /// Poll a batch until it finishes.  `on_status` is passed the
+    /// batch each time it is polled.  Returns the finished batch
+    pub fn batch_monitor(
+        &self,
+        id: &str,
+        interval: Duration,
+        mut on_status: impl FnMut(&Batch),
+    ) -> Result<ApiResult<Batch>, Box<dyn Error>> {
+        loop {
+            let batch = self.batch_retrieve(id)?;
+            on_status(&batch.body);
+            if batch.body.is_finished() {
+                return Ok(batch);
+            }
+            sleep(interval);
+        }
+    }
+
+    /// Download and parse the output and error files of a batch.
+    /// Lines from the error file follow those from the output file
+    pub fn batch_results(
+        &self,
+        batch: &Batch,
+    ) -> Result<ApiResult<Vec<BatchOutputLine>>, Box<dyn Error>> {
+        let mut headers: HashMap<String, String> = HashMap::new();
+        let mut lines: Vec<BatchOutputLine> = vec![];
+        for file_id in [&batch.output_file_id, &batch.error_file_id]
+            .into_iter()
+            .flatten()
+        {
+            let contents = self.file_contents(file_id.clone())?;
+            headers.extend(contents.headers);
+            lines.extend(parse_output(contents.body.as_str())?);
+        }
         Ok(ApiResult {
             headers,
-            body: fine_tune_list.as_string(),
+            body: lines,
         })
     }
 
+    /// Send `requests` (which must all be for the chat endpoint) as a
+    /// batch, wait for it to finish, and return the results.  The
+    /// input file is left on the server
+    pub fn batch_run(
+        &self,
+        requests: &[BatchRequestLine],
+        interval: Duration,
+        on_status: impl FnMut(&Batch),
+    ) -> Result<ApiResult<Vec<BatchOutputLine>>, Box<dyn Error>> {
+        let mut input = tempfile::Builder::new().suffix(".jsonl").tempfile()?;
+        input.write_all(to_jsonl(requests)?.as_bytes())?;
+        input.flush()?;
+        let input_file_id = self.files_upload(input.path(), "batch")?.body;
+        let batch = self.batch_create(input_file_id.as_str(), BATCH_CHAT_ENDPOINT)?;
+        let batch = self.batch_monitor(batch.body.id.as_str(), interval, on_status)?;
+        self.batch_results(&batch.body)
+    }
+
     /// Documented [here](https://platform.openai.com/docs/api-reference/chat)
     pub fn chat(&mut self, prompt: &str, model: &str) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let result = self.chat_choices(prompt, model)?;
+        let content = result
+            .body
+            .first()
+            .ok_or("No choices in the response")?
+            .message
+            .content
+            .clone();
+        self.commit(prompt, content.as_str(), model);
+        Ok(ApiResult::new(content, result.headers))
+    }
+
+    /// Send `prompt` in the conversation and return every choice (see
+    /// `ChatOptions::n`), with their log probabilities if they were
+    /// asked for.  The cost is added to the context, but the
+    /// conversation is not: `commit` the choice to carry on with
+    pub fn chat_choices(
+        &mut self,
+        prompt: &str,
+        model: &str,
+    ) -> Result<ApiResult<Vec<ChatChoice>>, Box<dyn Error>> {
+        let call = Call::new(Endpoint::Chat, Some(model));
+        let result = self.chat_call(&call, prompt, model);
+        self.interceptors.after(&call, &result);
+        result
+    }
+
+    fn chat_call(
+        &mut self,
+        call: &Call,
+        prompt: &str,
+        model: &str,
+    ) -> Result<ApiResult<Vec<ChatChoice>>, Box<dyn Error>> {
+        let (messages, options) = self.chat_messages(prompt);
+        let mut data = chat_request_body(
+            model,
+            &messages,
+            &options,
+            &self.model_registry.capabilities(model),
+        );
+        self.interceptors.before(call, &mut data)?;
+        // The most this could cost: the whole conversation, and every
+        // choice as long as allowed
+        let mut warnings = self.check_budget(
+            model,
+            estimate_chat_tokens(messages.iter().map(|m| m.content.as_str())),
+            options.max_tokens.unwrap_or(self.tokens) * options.n.unwrap_or(1),
+        )?;
+        let headers_json: (HashMap<String, String>, ChatRequestInfo) = Self::send_chat(
+            self.transport.as_ref(),
+            self.api_url.as_str(),
+            self.api_key.as_str(),
+            &data,
+        )?;
+        let mut headers_ret = Self::usage_headers(headers_json.1.usage.clone());
+        // Answers from the cache are free
+        let cost: f64 = if headers_json.0.contains_key(CACHE_HEADER) {
+            0.0
+        } else {
+            Self::charge(headers_json.1.usage, model, &mut warnings)
+        };
+        self.context.cost += cost;
+        headers_ret.insert("Cost".to_string(), format!("{cost}"));
+        headers_ret.extend(headers_json.0);
+        self.record_spending(cost, warnings, &mut headers_ret);
+
+        Ok(ApiResult {
+            headers: headers_ret,
+            body: headers_json.1.choices,
+        })
+    }
+
+    /// Send `prompt` in the conversation, and call `on_token` with
+    /// each piece of the reply as it arrives.  Only one choice is
+    /// streamed, and there are no log probabilities.  Returns the
+    /// whole reply, which is added to the conversation like `chat`
+    pub fn chat_stream(
+        &mut self,
+        prompt: &str,
+        model: &str,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let call = Call::new(Endpoint::Chat, Some(model));
+        let result = self.chat_stream_call(&call, prompt, model, on_token);
+        self.interceptors.after(&call, &result);
+        result
+    }
+
+    fn chat_stream_call(
+        &mut self,
+        call: &Call,
+        prompt: &str,
+        model: &str,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let (messages, mut options) = self.chat_messages(prompt);
+        options.n = None;
+        options.logprobs = None;
+        let mut data = chat_request_body(
+            model,
+            &messages,
+            &options,
+            &self.model_registry.capabilities(model),
+        );
+        data["stream"] = json!(true);
+        data["stream_options"] = json!({"include_usage": true});
+        self.interceptors.before(call, &mut data)?;
+        let prompt_tokens = estimate_chat_tokens(messages.iter().map(|m| m.content.as_str()));
+        let mut warnings = self.check_budget(
+            model,
+            prompt_tokens,
+            options.max_tokens.unwrap_or(self.tokens),
+        )?;
+
+        let uri = format!("{}/chat/completions", self.api_url);
+        let response = self.send(
+            self.client
+                .post(uri)
+                .header(AUTHORIZATION, format!("Bearer {}", self.api_key))
+                .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                .body(data.to_string()),
+        )?;
+        if !response.status().is_success() {
+            // Explains itself in JSON, not events
+            return Self::json_result::<Value>(response)
+                .map(|result| ApiResult::new(result.body.to_string(), result.headers));
+        }
+        let mut headers = Self::header_map_to_hash_map(response.headers());
+
+        // Server-sent events: "data: <JSON>" lines, then "data: [DONE]"
+        let mut reply = String::new();
+        let mut usage: Option<Usage> = None;
+        for line in BufReader::new(response).lines() {
+            let line = line?;
+            let data = match line.strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => continue,
+            };
+            if data == "[DONE]" {
+                break;
+            }
+            let chunk: ChatChunk = serde_json::from_str(data).map_err(|err| {
+                ApiError::new(
+                    ApiErrorType::BadJson(format!("{err}: {data}")),
+                    HashMap::new(),
+                )
+            })?;
+            for choice in chunk.choices.iter().filter(|c| c.index == 0) {
+                if let Some(ref content) = choice.delta.content {
+                    on_token(content.as_str());
+                    reply.push_str(content.as_str());
+                }
+            }
+            if chunk.usage.is_some() {
+                usage = chunk.usage;
+            }
+        }
+
+        // Without the usage, estimate it
+        let usage = usage.unwrap_or_else(|| {
+            let prompt_tokens = prompt_tokens as u32;
+            let completion_tokens = estimate_tokens(reply.as_str()) as u32;
+            Usage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+                completion_tokens_details: None,
+            }
+        });
+        headers.extend(Self::usage_headers(usage.clone()));
+        let cost = Self::charge(usage, model, &mut warnings);
+        self.context.cost += cost;
+        headers.insert("Cost".to_string(), format!("{cost}"));
+        self.record_spending(cost, warnings, &mut headers);
+        self.commit(prompt, reply.as_str(), model);
+        Ok(ApiResult::new(reply, headers))
+    }
+
+    /// The messages of a chat, the conversation so far then `prompt`,
+    /// and the options to send them with
+    fn chat_messages(&self, prompt: &str) -> (Vec<Message>, ChatOptions) {
         // An ongoing conversation with the LLM
 
         // Model can be any of: gpt-4, gpt-4-0314, gpt-4-32k,
@@ -441,24 +942,16 @@ impl ApiInterface {
         // Put the conversation so far in here
         let mut messages: Vec<Message> = vec![]; // = [Message { role, content }];
 
-        // If here is any context, supply it
-        if self.context.is_empty() {
-            // Conversation starting.  Append system prompt to context
-            messages.push(Message {
-                role: "system".to_string(),
-                content: "".to_string(),
-            });
-        } else {
+        // The system prompt, then any context
+        messages.push(Message {
+            role: "system".to_string(),
+            content: self.context.purpose.clone(),
+        });
+        for i in 0..self.context.len() {
             messages.push(Message {
-                role: "system".to_string(),
-                content: self.context.purpose.clone(),
+                role: if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+                content: self.context.prompt_response[i].clone(),
             });
-            for i in 0..self.context.len() {
-                messages.push(Message {
-                    role: if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
-                    content: self.context.prompt_response[i].clone(),
-                });
-            }
         }
 
         // Add in the latest installment, the prompt for this function
@@ -466,38 +959,75 @@ impl ApiInterface {
         let content = prompt.to_string();
         messages.push(Message { role, content });
 
-        // The payload
-        let data = json!({
-            "messages": messages,
-            "model": model,
-        });
-        let headers_json: (HashMap<String, String>, ChatRequestInfo) =
-            Self::send_chat(self.api_key.as_str(), &data)?;
-        let mut headers_ret = Self::usage_headers(headers_json.1.usage.clone());
-        let cost: f64 = Self::cost(headers_json.1.usage, model);
-        self.context.cost += cost;
-        headers_ret.insert("Cost".to_string(), format!("{cost}"));
-        headers_ret.extend(headers_json.0);
+        (messages, self.request_options())
+    }
 
-        let content = headers_json.1.choices[0].message.content.clone();
-        self.context.push(prompt.to_string());
-        self.context.push(content.clone());
+    /// The chat options for a request: `chat_options`, with
+    /// `temperature` and `tokens` if they are not set there
+    fn request_options(&self) -> ChatOptions {
+        let mut options = self.chat_options.clone();
+        options.temperature = options.temperature.or(Some(self.temperature));
+        options.max_tokens = options.max_tokens.or(Some(self.tokens));
+        options
+    }
+
+    /// A batch request to `model` for each of `prompts`, each on its
+    /// own with the system prompt.  Shaped for the model like `chat`,
+    /// but with one choice and no log probabilities
+    pub fn chat_batch_requests(&self, prompts: &[String], model: &str) -> Vec<BatchRequestLine> {
+        let mut options = self.request_options();
+        options.n = None;
+        options.logprobs = None;
+        chat_batch_requests(
+            prompts,
+            self.context.purpose.as_str(),
+            model,
+            &options,
+            &self.model_registry.capabilities(model),
+        )
+    }
 
-        Ok(ApiResult::new(content, headers_ret))
+    /// Add `prompt`, and the `reply` to it from `model`, to the
+    /// conversation
+    pub fn commit(&mut self, prompt: &str, reply: &str, model: &str) {
+        self.context.push(prompt.to_string());
+        self.context.push(reply.to_string());
+        self.context.model = Some(model.to_string());
     }
 
-    /// Send the data to the OpenAI server and return the response with headers.  This maintains no state
+    /// Send the data to the OpenAI server, with `transport`, and return the response with headers.  This maintains no state
     pub fn send_chat(
+        transport: &dyn Transport,
+        api_url: &str,
         api_key: &str,
         data: &serde_json::Value,
     ) -> Result<(HashMap<String, String>, ChatRequestInfo), Box<dyn Error>> {
         // Send the request and get the Json data as a String, convert
         // into ``ChatRequestInfo` endpoint
-        let uri = format!("{}/chat/completions", API_URL);
+        let uri = format!("{api_url}/chat/completions");
         // eprintln!("Sending {uri}");
-        let (headers, response_string) = Self::send_curl(api_key, data, uri.as_str())?;
+        let mut request = Request::new(Method::POST, Url::parse(uri.as_str())?);
+        request.headers_mut().insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(format!("Bearer {api_key}").as_str())?,
+        );
+        request
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        *request.body_mut() = Some(data.to_string().into());
+        let response = transport.execute(request)?;
+        let headers = Self::header_map_to_hash_map(response.headers());
+        let response_string = response.text()?;
         // eprintln!("Got back {uri}: {}", response_string.as_str());
-        let json: ChatRequestInfo = serde_json::from_str(response_string.as_str())?;
+        let value: serde_json::Value = serde_json::from_str(response_string.as_str())?;
+        if let Some(message) = value["error"]["message"].as_str() {
+            // OpenAI refused the request, and said why
+            return Err(Box::new(ApiError::new(
+                ApiErrorType::Error(message.to_string()),
+                headers,
+            )));
+        }
+        let json: ChatRequestInfo = serde_json::from_value(value)?;
         // eprintln!("Response from: {uri} {json:?}");
         Ok((headers, json))
     }
@@ -520,30 +1050,72 @@ impl ApiInterface {
         prompt: &str,
         model: &str,
     ) -> Result<ApiResult<String>, Box<dyn Error>> {
-        let uri: String = format!("{}/completions", API_URL);
+        let options = self.completion_options.clone();
+        self.completion_with(prompt, model, &options)
+    }
 
-        let payload = CompletionRequestInfo::new(prompt, model, self.temperature, self.tokens);
+    /// Fill the gap in `fim` with a completion from `model`, which
+    /// must take a `suffix`.  Returns what goes in the gap
+    pub fn fill_in_middle(
+        &mut self,
+        fim: &FillInMiddle,
+        model: &str,
+    ) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let options = fim.options(&self.completion_options);
+        self.completion_with(fim.prompt(), model, &options)
+    }
 
-        let response = self
-            .client
-            .post(uri)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&payload)
-            .send()?;
+    fn completion_with(
+        &mut self,
+        prompt: &str,
+        model: &str,
+        options: &CompletionOptions,
+    ) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let call = Call::new(Endpoint::Completion, Some(model));
+        let result = self.completion_call(&call, prompt, model, options);
+        self.interceptors.after(&call, &result);
+        result
+    }
+
+    fn completion_call(
+        &mut self,
+        call: &Call,
+        prompt: &str,
+        model: &str,
+        options: &CompletionOptions,
+    ) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let uri: String = format!("{}/completions", self.api_url);
+
+        let mut request = CompletionRequestInfo::new(prompt, model, self.temperature, self.tokens);
+        options.apply(&mut request);
+        let mut payload = serde_json::to_value(&request)?;
+        self.interceptors.before(call, &mut payload)?;
+        let mut warnings = self.check_budget(
+            model,
+            estimate_tokens(prompt) + options.suffix.as_deref().map(estimate_tokens).unwrap_or(0),
+            self.tokens * options.best_of.unwrap_or(1),
+        )?;
+
+        let response = self.send(
+            self.client
+                .post(uri)
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .header("Content-Type", "application/json")
+                .json(&payload),
+        )?;
 
         let mut headers = Self::header_map_to_hash_map(response.headers());
         let response_text: String = if response.status() != StatusCode::OK {
             // There was some sort of failure.  Probably a network
             // failure
-            format!(
-                "Failed: Status: {}.\nResponse.path({})",
-                response
-                    .status()
-                    .canonical_reason()
-                    .unwrap_or("Unknown Reason"),
-                response.url().path(),
-            )
+            let reason = response
+                .status()
+                .canonical_reason()
+                .unwrap_or("Unknown Reason");
+            return Err(Box::new(ApiError::new(
+                ApiErrorType::Status(response.status(), reason.to_string()),
+                headers,
+            )));
         } else {
             // Got a good response from the LLM
             let response_debug = format!("{:?}", &response);
@@ -560,36 +1132,56 @@ impl ApiInterface {
             if finish_reason != "stop" {
                 headers.insert("finsh reason".to_string(), finish_reason.to_string());
             }
+            headers.extend(Self::usage_headers(json.usage.clone()));
 
-            if json.choices[0].text.is_empty() {
-                panic!("Empty json.choices[0].  {:?}", &json);
+            // Completions are only priced for the budget
+            let cost = if self.budget.is_none() || headers.contains_key(CACHE_HEADER) {
+                0.0
             } else {
-                json.choices[0].text.clone()
-            }
+                Self::charge(json.usage.clone(), model, &mut warnings)
+            };
+            self.record_spending(cost, warnings, &mut headers);
+
+            // With a suffix, nothing can be the answer
+            json.choices[0].text.clone()
         };
         Ok(ApiResult::new(response_text, headers))
     }
 
     /// Handle image mode prompts
     pub fn image(&mut self, prompt: &str) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let call = Call::new(Endpoint::Image, None);
+        let result = self.image_call(&call, prompt);
+        self.interceptors.after(&call, &result);
+        result
+    }
+
+    fn image_call(
+        &mut self,
+        call: &Call,
+        prompt: &str,
+    ) -> Result<ApiResult<String>, Box<dyn Error>> {
         // Endpoint
-        let uri: String = format!("{}/images/generations", API_URL);
+        let uri: String = format!("{}/images/generations", self.api_url);
 
         // Payload
-        let data = json!({
+        let mut data = json!({
                   "prompt":  prompt,
                   "size": "1024x1024",
         });
+        self.interceptors.before(call, &mut data)?;
+        let warnings = self.check_estimate(IMAGE_COST)?;
 
         // Set up network comms
-        let res = Client::new()
+        let res = self
+            .client
             .post(uri)
             .header("Authorization", format!("Bearer {}", self.api_key).as_str())
             .header("Content-Type", "application/json")
             .json(&data);
 
         // Send network request
-        let response = match res.send() {
+        let response = match self.send(res) {
             Ok(r) => r,
             Err(err) => {
                 return Ok(ApiResult::new(
@@ -600,7 +1192,7 @@ impl ApiInterface {
         };
 
         // Prepare diagnostic data
-        let headers = Self::header_map_to_hash_map(&response.headers().clone());
+        let mut headers = Self::header_map_to_hash_map(&response.headers().clone());
         if !response.status().is_success() {
             let reason = response
                 .status()
@@ -625,6 +1217,7 @@ impl ApiInterface {
         };
 
         // Success.
+        self.record_spending(IMAGE_COST, warnings, &mut headers);
         Ok(ApiResult::new(json.data[0].url.clone(), headers))
     }
 
@@ -637,9 +1230,22 @@ impl ApiInterface {
         prompt: &str,
         image: &Path,
         mask: &Path,
+    ) -> Result<ApiResult<String>, Box<dyn Error>> {
+        let call = Call::new(Endpoint::ImageEdit, None);
+        let result = self.image_edit_call(&call, prompt, image, mask);
+        self.interceptors.after(&call, &result);
+        result
+    }
+
+    fn image_edit_call(
+        &mut self,
+        call: &Call,
+        prompt: &str,
+        image: &Path,
+        mask: &Path,
     ) -> Result<ApiResult<String>, Box<dyn Error>> {
         // Endpoint
-        let uri = format!("{}/images/edits", API_URL);
+        let uri = format!("{}/images/edits", self.api_url);
 
         // Some timeing.  TODO: Why here, in this function, and not everywhere?
         let start = Instant::now();
@@ -652,12 +1258,16 @@ impl ApiInterface {
         // let mask_path = mask_file.path().to_owned();
 
         // Prepare the payload to send to OpenAI
+        let mut payload = json!({
+            "prompt": prompt,
+            "size": "1024x1024",
+        });
+        self.interceptors.before(call, &mut payload)?;
+        let warnings = self.check_estimate(IMAGE_COST)?;
         let form = multipart::Form::new();
         let form = match form.file("image", image) {
             Ok(f) => match f.file("mask", mask) {
-                Ok(s) => s
-                    .text("prompt", prompt.to_string())
-                    .text("size", "1024x1024"),
+                Ok(s) => Self::form_text(s, &payload),
                 Err(err) => {
                     return Err(Box::new(ApiError::new(
                         ApiErrorType::Error(format!("{err}")),
@@ -675,22 +1285,22 @@ impl ApiInterface {
         };
 
         // Set up network comms
-        let req_build: RequestBuilder = Client::new()
+        let req_build: RequestBuilder = self
+            .client
             .post(uri.as_str())
-            .timeout(std::time::Duration::from_secs(1200))
             .header("Authorization", format!("Bearer {}", self.api_key).as_str())
             .multipart(form);
 
         // Send request
-        let response = match req_build.send() {
+        let response = match self.send(req_build) {
             Ok(r) => r,
             Err(err) => {
                 println!("Failed url: {uri} Err: {err}");
-                return Err(Box::new(err));
+                return Err(err);
             }
         };
 
-        let headers = Self::header_map_to_hash_map(&response.headers().clone());
+        let mut headers = Self::header_map_to_hash_map(&response.headers().clone());
         println!("Sent message: {:?}", start.elapsed());
         if !response.status().is_success() {
             let reason = response
@@ -712,20 +1322,20 @@ impl ApiInterface {
                 return Err(Box::new(err));
             }
         };
-
+        self.record_spending(IMAGE_COST, warnings, &mut headers);
         Ok(ApiResult::new(json.data[0].url.clone(), headers))
     }
 
     /// Handle the response if the user queries what models there are
     /// ("! mm" prompt in cli).  
     pub fn model_list(&self) -> Result<ApiResult<String>, Box<dyn Error>> {
-        let uri: String = format!("{}/models", API_URL);
-        let mut response = self
-            .client
-            .get(uri.as_str())
-            .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .send()?;
+        let uri: String = format!("{}/models", self.api_url);
+        let mut response = self.send(
+            self.client
+                .get(uri.as_str())
+                .header("Content-Type", "application/json")
+                .header("Authorization", format!("Bearer {}", self.api_key)),
+        )?;
         if !response.status().is_success() {
             // If it were not a success the previous cal will have failed
             // This will not happen
@@ -740,16 +1350,195 @@ impl ApiInterface {
         Ok(ApiResult { headers, body })
     }
 
-    /// Convert the usege into a price.
-    fn cost(usage: Usage, model: &str) -> f64 {
-        // GPT-4is more expensive
-        if model.starts_with("gpt-4") {
-            usage.completion_tokens as f64 / 1000.0 * 12.0
-                + usage.prompt_tokens as f64 / 1000.0 * 0.06
+    /// List the models available, without their details
+    pub fn models(&self) -> Result<ApiResult<ModelSummaryList>, Box<dyn Error>> {
+        // GET https://api.openai.com/v1/models
+        let uri = format!("{}/models", self.api_url);
+        self.get_json(uri.as_str())
+    }
+
+    /// Show interceptors the ID of the file `call` is for, and return
+    /// it as they leave it
+    fn intercept_file_id(&self, call: &Call, file_id: String) -> Result<String, Box<dyn Error>> {
+        let mut payload = json!({ "file_id": file_id });
+        self.interceptors.before(call, &mut payload)?;
+        match payload["file_id"].as_str() {
+            Some(file_id) => Ok(file_id.to_string()),
+            None => Err(format!("No file ID in {payload}").into()),
+        }
+    }
+
+    /// Add the fields of `payload`, after interceptors have seen it,
+    /// to `form` as text
+    fn form_text(mut form: multipart::Form, payload: &Value) -> multipart::Form {
+        if let Some(fields) = payload.as_object() {
+            for (name, value) in fields {
+                let text = match value {
+                    Value::String(text) => text.clone(),
+                    value => value.to_string(),
+                };
+                form = form.text(name.clone(), text);
+            }
+        }
+        form
+    }
+
+    /// Send a request with the transport.  Every endpoint comes
+    /// through here
+    fn send(&self, request: RequestBuilder) -> Result<Response, Box<dyn Error>> {
+        self.transport.execute(request.build()?)
+    }
+
+    /// GET `uri` and deserialise the JSON that comes back
+    fn get_json<T: DeserializeOwned>(&self, uri: &str) -> Result<ApiResult<T>, Box<dyn Error>> {
+        let response = self.send(
+            self.client
+                .get(uri)
+                .header(AUTHORIZATION, format!("Bearer {}", self.api_key)),
+        )?;
+        Self::json_result(response)
+    }
+
+    /// POST `data` to `uri` and deserialise the JSON that comes back
+    fn post_json<T: DeserializeOwned>(
+        &self,
+        uri: &str,
+        data: &serde_json::Value,
+    ) -> Result<ApiResult<T>, Box<dyn Error>> {
+        let response = self.send(
+            self.client
+                .post(uri)
+                .header(AUTHORIZATION, format!("Bearer {}", self.api_key))
+                .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                .json(data),
+        )?;
+        Self::json_result(response)
+    }
+
+    /// Check the status of a response and deserialise its body.  If
+    /// OpenAI explains a failure the explanation is the reason in
+    /// the error
+    fn json_result<T: DeserializeOwned>(
+        response: Response,
+    ) -> Result<ApiResult<T>, Box<dyn Error>> {
+        let headers = Self::header_map_to_hash_map(response.headers());
+        let status = response.status();
+        let text = response.text()?;
+        if !status.is_success() {
+            let reason = match serde_json::from_str::<serde_json::Value>(text.as_str()) {
+                Ok(v) => match v["error"]["message"].as_str() {
+                    Some(message) => message.to_string(),
+                    None => status
+                        .canonical_reason()
+                        .unwrap_or("Unknown Reason")
+                        .to_string(),
+                },
+                Err(_) => status
+                    .canonical_reason()
+                    .unwrap_or("Unknown Reason")
+                    .to_string(),
+            };
+            return Err(Box::new(ApiError::new(
+                ApiErrorType::Status(status, reason),
+                headers,
+            )));
+        }
+        match serde_json::from_str::<T>(text.as_str()) {
+            Ok(body) => Ok(ApiResult { headers, body }),
+            Err(err) => Err(Box::new(ApiError::new(
+                ApiErrorType::BadJson(format!("{err}: {text}")),
+                headers,
+            ))),
+        }
+    }
+
+    /// Convert the usege into a price.  `None` if the model has no
+    /// known price
+    pub(crate) fn cost(usage: Usage, model: &str) -> Option<f64> {
+        // Newer models are in a table of prices
+        if let Some(cost) = listed_cost(&usage, model) {
+            return Some(cost);
+        }
+        // The first GPT-4 models are more expensive
+        if model == "gpt-4" || model.starts_with("gpt-4-0") || model.starts_with("gpt-4-32k") {
+            Some(
+                usage.completion_tokens as f64 / 1000.0 * 12.0
+                    + usage.prompt_tokens as f64 / 1000.0 * 0.06,
+            )
         } else if model.starts_with("gpt-3") {
-            usage.total_tokens as f64 / 1000.0 * 0.2
+            Some(usage.total_tokens as f64 / 1000.0 * 0.2)
         } else {
-            panic!("{model}");
+            None
+        }
+    }
+
+    /// What `usage` by `model` cost.  A model with no price costs
+    /// nothing, and a warning says so
+    fn charge(usage: Usage, model: &str, warnings: &mut Vec<String>) -> f64 {
+        match Self::cost(usage, model) {
+            Some(cost) => cost,
+            None => {
+                warnings.push(format!(
+                    "No price is known for {model}, so what it cost is not counted"
+                ));
+                0.0
+            }
+        }
+    }
+
+    /// Refuse a request to `model` that could use `prompt_tokens` and
+    /// `completion_tokens` if the budget cannot afford it.  Returns
+    /// any warnings about limits getting close
+    fn check_budget(
+        &self,
+        model: &str,
+        prompt_tokens: usize,
+        completion_tokens: u32,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        if self.budget.is_none() {
+            return Ok(vec![]);
+        }
+        let prompt_tokens = prompt_tokens as u32;
+        let estimate = Self::cost(
+            Usage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+                completion_tokens_details: None,
+            },
+            model,
+        )
+        .ok_or_else(|| {
+            format!("No price is known for {model}, so the budget cannot be enforced")
+        })?;
+        self.check_estimate(estimate)
+    }
+
+    /// Refuse a request that could cost `estimate` cents if the
+    /// budget cannot afford it
+    fn check_estimate(&self, estimate: f64) -> Result<Vec<String>, Box<dyn Error>> {
+        match self.budget {
+            Some(ref budget) => budget.check(estimate),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Add what a request cost to the budget, and put `warnings` from
+    /// `check_budget` in `headers`.  The request has been paid for, so
+    /// failing to write the ledger is reported in `headers` too
+    fn record_spending(
+        &mut self,
+        cost: f64,
+        mut warnings: Vec<String>,
+        headers: &mut HashMap<String, String>,
+    ) {
+        if let Some(ref mut budget) = self.budget {
+            if let Err(err) = budget.record(cost) {
+                warnings.push(format!("Failed to record spending: {err}"));
+            }
+        }
+        if !warnings.is_empty() {
+            headers.insert(BUDGET_WARNING_HEADER.to_string(), warnings.join("; "));
         }
     }
 
@@ -761,6 +1550,12 @@ impl ApiInterface {
         result.insert("Tokens prompt".to_string(), prompt_tokens);
         result.insert("Tokens completion".to_string(), completion_tokens);
         result.insert("Tokens total".to_string(), total_tokens);
+        if usage.reasoning_tokens() > 0 {
+            result.insert(
+                "Tokens reasoning".to_string(),
+                usage.reasoning_tokens().to_string(),
+            );
+        }
         result
     }
 
@@ -782,70 +1577,365 @@ impl ApiInterface {
     pub fn clear_context(&mut self) {
         self.context.clear();
     }
+}
 
-    /// Send a request, the body of which is coded in `data`, to `uri`.
-    /// Return the Json data as a String
-    fn send_curl(
-        api_key: &str,
-        data: &serde_json::Value,
-        uri: &str,
-    ) -> Result<(HashMap<String, String>, String), Box<dyn Error>> {
-        let body = format!("{data}");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::BudgetLimits;
+    use crate::interceptor::Interceptor;
+    use llm_mock::MockResponse;
+    use llm_mock::MockServer;
+    use std::sync::Mutex;
+
+    /// Answers every request with `body`, and remembers where they
+    /// were sent
+    #[derive(Debug)]
+    struct FakeTransport {
+        body: String,
+        urls: Mutex<Vec<String>>,
+    }
 
-        let mut body = body.as_bytes();
-        let mut curl_easy = Easy::new();
-        curl_easy.url(uri)?;
+    impl Transport for FakeTransport {
+        fn execute(&self, request: Request) -> Result<Response, Box<dyn Error>> {
+            self.urls.lock().unwrap().push(request.url().to_string());
+            Ok(Response::from(
+                http::Response::builder()
+                    .status(200)
+                    .body(self.body.clone())?,
+            ))
+        }
+    }
 
-        // Prepare the headers
-        let mut list = List::new();
-        list.append(format!("Authorization: Bearer {}", api_key).as_str())?;
-        list.append("Content-Type: application/json")?;
-        curl_easy.http_headers(list)?;
+    /// Answers the `n`th request for the job with `jobs[n]`, and a
+    /// request for events after `n` requests for the job with
+    /// `events[n]`
+    #[derive(Debug)]
+    struct PollTransport {
+        events: Vec<String>,
+        jobs: Vec<&'static str>,
+        polls: Mutex<usize>,
+    }
 
-        // I am unsure why I have to do this magick incantation
-        curl_easy.post_field_size(body.len() as u64)?;
+    impl Transport for PollTransport {
+        fn execute(&self, request: Request) -> Result<Response, Box<dyn Error>> {
+            let mut polls = self.polls.lock().unwrap();
+            let body = if request.url().path().ends_with("/events") {
+                assert!(!request.url().query().unwrap().contains("after"));
+                format!(
+                    r#"{{"object": "list", "has_more": false, "data": [{}]}}"#,
+                    self.events[*polls]
+                )
+            } else {
+                let status = self.jobs[*polls];
+                *polls += 1;
+                format!(
+                    r#"{{"id": "ftjob-abc", "object": "fine_tuning.job", "created_at": 0,
+                        "finished_at": null, "model": "gpt-4o-mini-2024-07-18",
+                        "fine_tuned_model": null, "organization_id": "org-123",
+                        "status": "{status}", "hyperparameters": {{}},
+                        "training_file": "file-abc", "validation_file": null,
+                        "result_files": [], "trained_tokens": null, "error": null,
+                        "estimated_finish": null, "seed": 42}}"#
+                )
+            };
+            Ok(Response::from(
+                http::Response::builder().status(200).body(body)?,
+            ))
+        }
+    }
 
-        // To get the normal output of the server
-        let mut output_buffer = Vec::new();
+    fn event(id: &str) -> String {
+        format!(
+            r#"{{"id": "{id}", "object": "fine_tuning.job.event", "created_at": 0,
+                "level": "info", "message": "{id}"}}"#
+        )
+    }
 
-        // To get the headers
-        let mut header_buffer = Vec::new();
+    #[test]
+    fn fine_tune_monitor_shows_each_event_once() {
+        let events = vec![
+            event("e1"),
+            format!("{},{}", event("e2"), event("e1")),
+            // Added as the job finished
+            format!("{},{},{}", event("e3"), event("e2"), event("e1")),
+        ];
+        let mut api_interface = ApiInterface::new("sk-fake".to_string(), 100, 0.5);
+        api_interface.transport = Arc::new(PollTransport {
+            events,
+            jobs: vec!["running", "succeeded"],
+            polls: Mutex::new(0),
+        });
+        let mut shown: Vec<String> = vec![];
+        let job = api_interface
+            .fine_tune_monitor("ftjob-abc", Duration::ZERO, |event| {
+                shown.push(event.id.clone())
+            })
+            .unwrap();
+        assert_eq!(job.body.status, "succeeded");
+        assert_eq!(shown, ["e1", "e2", "e3"]);
+    }
 
-        // Time the process.
-        let start = Instant::now();
+    fn api_interface(server: &MockServer) -> ApiInterface {
+        let mut api_interface = ApiInterface::new("sk-mock".to_string(), 100, 0.5);
+        api_interface.api_url = server.base_url();
+        api_interface
+    }
 
-        {
-            // Start a block so `transfer` is destroyed and releases the
-            // borrow it has on `header_buffer` and `output_buffer`
-            let mut transfer = curl_easy.transfer();
-            transfer.header_function(|data| {
-                header_buffer.push(String::from_utf8(data.to_vec()).unwrap());
-                true
-            })?;
-            transfer.read_function(|buf| Ok(body.read(buf).unwrap_or(0)))?;
-            transfer.write_function(|data| {
-                output_buffer.extend_from_slice(data);
-                Ok(data.len())
-            })?;
-            transfer.perform()?;
+    #[test]
+    fn images_use_the_transport() {
+        // Images used to make their own clients
+        let transport = Arc::new(FakeTransport {
+            body: r#"{"created": 0, "data": [{"url": "https://example.com/cat.png"}]}"#.to_string(),
+            urls: Mutex::new(vec![]),
+        });
+        let mut api_interface = ApiInterface::new("sk-fake".to_string(), 100, 0.5);
+        api_interface.api_url = "http://nowhere.invalid/v1".to_string();
+        api_interface.transport = transport.clone();
+        let result = api_interface.image("A cat").unwrap();
+        assert_eq!(result.body, "https://example.com/cat.png");
+        assert_eq!(
+            transport.urls.lock().unwrap().as_slice(),
+            ["http://nowhere.invalid/v1/images/generations"]
+        );
+    }
+
+    #[test]
+    fn chat_against_mock() {
+        let server = MockServer::start().unwrap();
+        let mut api_interface = api_interface(&server);
+        api_interface.context.purpose = "Be terse".to_string();
+        let result = api_interface.chat("Hello", "gpt-4o-mini").unwrap();
+        assert_eq!(result.body, "Mock reply to: Hello");
+        assert!(result
+            .headers
+            .contains_key("x-ratelimit-remaining-requests"));
+        assert_eq!(api_interface.context.len(), 2);
+        let requests = server.requests();
+        assert_eq!(
+            requests[0].headers.get("authorization").unwrap(),
+            "Bearer sk-mock"
+        );
+        assert_eq!(requests[0].json()["max_tokens"], 100);
+        // The system prompt is sent from the start
+        assert_eq!(requests[0].json()["messages"][0]["content"], "Be terse");
+        assert!(requests[0].json().get("seed").is_none());
+
+        // Options override `temperature` and `tokens`
+        api_interface.chat_options = ChatOptions::new().max_tokens(20).seed(1);
+        api_interface.chat("Again", "gpt-4o-mini").unwrap();
+        let body = server.requests()[1].json();
+        assert_eq!(body["max_tokens"], 20);
+        assert_eq!(body["seed"], 1);
+        assert_eq!(body["temperature"], 0.5);
+    }
+
+    #[test]
+    fn chat_stream_against_mock() {
+        let server = MockServer::start().unwrap();
+        let mut api_interface = api_interface(&server);
+        let mut tokens: Vec<String> = vec![];
+        let result = api_interface
+            .chat_stream("Hello there", "gpt-4o-mini", &mut |t| {
+                tokens.push(t.to_string())
+            })
+            .unwrap();
+        assert_eq!(result.body, "Mock reply to: Hello there");
+        assert_eq!(tokens.concat(), result.body);
+        assert!(tokens.len() > 1);
+        assert!(result.headers.contains_key("Tokens total"));
+        assert_eq!(api_interface.context.len(), 2);
+        let body = server.requests()[0].json();
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["stream_options"]["include_usage"], true);
+    }
+
+    #[test]
+    fn choices_against_mock() {
+        let server = MockServer::start().unwrap();
+        let mut api_interface = api_interface(&server);
+        api_interface.chat_options = ChatOptions::new().n(2).logprobs(1);
+        let result = api_interface.chat_choices("Hi", "gpt-4o-mini").unwrap();
+        assert_eq!(result.body.len(), 2);
+        assert_eq!(result.body[1].message.content, "Mock reply 2 to: Hi");
+        assert_eq!(result.body[1].tokens()[0].token, "Mock ");
+        assert_eq!(result.body[1].tokens()[0].top_logprobs.len(), 1);
+        assert!(api_interface.context.is_empty());
+        api_interface.commit("Hi", result.body[1].message.content.as_str(), "gpt-4o-mini");
+        assert_eq!(
+            api_interface.context.prompt_response[1],
+            "Mock reply 2 to: Hi"
+        );
+    }
+
+    #[test]
+    fn fill_in_middle_against_mock() {
+        let server = MockServer::start().unwrap();
+        let mut api_interface = api_interface(&server);
+        api_interface.completion_options = CompletionOptions::new().stop("\n");
+        let fim = FillInMiddle::new("let x = <FILL>;\n", "<FILL>").unwrap();
+        let result = api_interface
+            .fill_in_middle(&fim, "gpt-3.5-turbo-instruct")
+            .unwrap();
+        assert_eq!(fim.fill(result.body.as_str()), "let x = mock_fill();\n");
+        let body = server.requests()[0].json();
+        assert_eq!(body["prompt"], "let x = ");
+        assert_eq!(body["suffix"], ";\n");
+        assert_eq!(body["stop"], serde_json::json!(["\n"]));
+        assert!(body.get("echo").is_none());
+    }
+
+    #[test]
+    fn budget_against_mock() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = MockServer::start().unwrap();
+        let mut api_interface = api_interface(&server);
+        // 100 tokens of gpt-4o-mini reply could cost 0.006 cents
+        let limits = BudgetLimits {
+            session: Some(0.005),
+            ..Default::default()
+        };
+        api_interface.budget = Some(Budget::new(limits, &dir.path().join("budget.json")));
+        let err = api_interface.chat("Hello", "gpt-4o-mini").unwrap_err();
+        match err.downcast_ref::<ApiError>().unwrap().error_type {
+            ApiErrorType::OverBudget(ref over) => {
+                assert!(over.estimate > 0.006 && over.estimate < 0.0065)
+            }
+            ref e => panic!("{e:?}"),
         }
+        assert!(server.requests().is_empty());
+        assert!(api_interface.context.is_empty());
+
+        // Close to the limit
+        api_interface.budget.as_mut().unwrap().limits.session = Some(0.007);
+        let result = api_interface.chat("Hello", "gpt-4o-mini").unwrap();
+        assert!(result.headers.contains_key(BUDGET_WARNING_HEADER));
+        let spent = api_interface.budget.as_ref().unwrap().spent().unwrap();
+        assert!(spent.session > 0.0 && spent.session < 0.006);
+        assert_eq!(spent.session, spent.day);
+    }
 
-        // Made the call, got the output,  Close the timer
-        let _duration = start.elapsed();
+    #[test]
+    fn budget_for_images_and_unpriced_models() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = MockServer::start().unwrap();
+        let mut api_interface = api_interface(&server);
+        let limits = BudgetLimits {
+            session: Some(3.0),
+            ..Default::default()
+        };
+        api_interface.budget = Some(Budget::new(limits, &dir.path().join("budget.json")));
+        let err = api_interface
+            .completion("Hello", "mystery-model")
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("No price is known for mystery-model"));
+        assert!(server.requests().is_empty());
+
+        // One image fits, the second does not
+        api_interface.image("A cat").unwrap();
+        let spent = api_interface.budget.as_ref().unwrap().spent().unwrap();
+        assert_eq!(spent.session, IMAGE_COST);
+        let err = api_interface.image("A dog").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ApiError>().unwrap().error_type,
+            ApiErrorType::OverBudget(_)
+        ));
+        assert_eq!(server.requests().len(), 1);
+    }
 
-        let result = String::from_utf8(output_buffer)?; // Process the output
+    #[test]
+    fn errors_from_mock() {
+        let server = MockServer::start().unwrap();
+        let mut api_interface = api_interface(&server);
+        server.push_response(
+            "/v1/chat/completions",
+            MockResponse::error(400, "Bad model"),
+        );
+        let err = api_interface.chat("Hello", "gpt-4o-mini").unwrap_err();
+        assert!(err.to_string().contains("Bad model"));
+        server.push_response("/v1/models", MockResponse::rate_limited());
+        let err = api_interface.models().unwrap_err();
+        assert!(err.to_string().contains("429"));
+        assert!(api_interface
+            .models()
+            .unwrap()
+            .body
+            .data
+            .iter()
+            .any(|m| m.id == "gpt-4o-mini"));
+    }
 
-        let headers_hm: HashMap<String, String> = header_buffer
-            .into_iter()
-            .filter_map(|item| {
-                let mut parts = item.splitn(2, ':');
-                if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
-                    Some((key.to_string(), value.trim().to_string()))
-                } else {
-                    None
-                }
-            })
-            .collect();
-        Ok((headers_hm, result))
+    /// Redacts "secret" from what is sent, refuses images, and notes
+    /// the calls
+    #[derive(Debug, Default)]
+    struct Redact {
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl Interceptor for Redact {
+        fn before_request(&self, call: &Call, payload: &mut Value) -> Result<(), Box<dyn Error>> {
+            if call.endpoint == Endpoint::Image {
+                return Err("No images".into());
+            }
+            *payload = serde_json::from_str(&payload.to_string().replace("secret", "[redacted]"))?;
+            Ok(())
+        }
+
+        fn after_response(&self, call: &Call, result: Result<&ApiResult<Value>, &dyn Error>) {
+            let result = match result {
+                Ok(result) => result.body.to_string(),
+                Err(err) => err.to_string(),
+            };
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} {result}", call.endpoint));
+        }
+    }
+
+    #[test]
+    fn interceptors_against_mock() {
+        let server = MockServer::start().unwrap();
+        let mut api_interface = api_interface(&server);
+        let redact = Arc::new(Redact::default());
+        api_interface.interceptors.push(redact.clone());
+        let reply = api_interface
+            .chat("The secret is 42", "gpt-4o-mini")
+            .unwrap();
+        assert_eq!(reply.body, "Mock reply to: The [redacted] is 42");
+        assert_eq!(api_interface.context.prompt_response[0], "The secret is 42");
+        api_interface
+            .completion("Keep it secret", "gpt-3.5-turbo-instruct")
+            .unwrap();
+        assert_eq!(server.requests()[1].json()["prompt"], "Keep it [redacted]");
+        let err = api_interface.image("A secret garden").unwrap_err();
+        assert_eq!(err.to_string(), "No images");
+        api_interface.files_list().unwrap();
+        assert_eq!(server.requests().len(), 3);
+        let calls = redact.calls.lock().unwrap();
+        assert!(calls[0].starts_with("chat [{"));
+        assert!(calls[1].starts_with("completion \""));
+        assert_eq!(calls[2..], ["image No images", "file_list []"]);
+    }
+
+    #[test]
+    fn files_against_mock() {
+        let server = MockServer::start().unwrap();
+        let api_interface = api_interface(&server);
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "{{\"custom_id\": \"0\"}}").unwrap();
+        let id = api_interface
+            .files_upload(file.path(), "batch")
+            .unwrap()
+            .body;
+        let files = api_interface.files_list().unwrap().body;
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].1, id);
+        let contents = api_interface.file_contents(id.clone()).unwrap().body;
+        assert_eq!(contents, "{\"custom_id\": \"0\"}\n");
+        api_interface.files_delete(id).unwrap();
+        assert!(api_interface.files_list().unwrap().body.is_empty());
     }
 }
@@ -0,0 +1,374 @@
+//! Keep the responses to completion and chat requests on disk, and
+//! answer the same request again from there.  For scripted runs and
+//! demonstrations that ask the same questions over and over.
+//!
+//! Only requests whose answer should not change are cached: those
+//! with a `temperature` of 0 or a `seed`.  A request is the same if
+//! its path and body (so the model, the messages and all the
+//! parameters) are.  Entries expire after a while, and the oldest are
+//! removed when the cache is too big.
+//!
+//! `CachingTransport` puts a `ResponseCache` in front of the
+//! transport that does the sending.  Answers from the cache have the
+//! header `CACHE_HEADER`, and cost nothing
+use crate::cassette::api_key_of;
+use crate::cassette::body_of;
+use crate::cassette::header_map;
+use crate::cassette::path_of;
+use crate::cassette::redact;
+use crate::cassette::Interaction;
+use crate::transport::Transport;
+use reqwest::blocking::Request;
+use reqwest::blocking::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Set on responses that came from the cache
+pub const CACHE_HEADER: &str = "x-llm-rs-cache";
+
+/// How long an entry is good for, by default
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How big the cache can get, by default, in bytes
+pub const DEFAULT_CACHE_SIZE: u64 = 50 * 1024 * 1024;
+
+/// A cached response, and when it was received
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    /// Seconds since the epoch
+    created: u64,
+    interaction: Interaction,
+}
+
+/// A file in the cache
+struct CacheFile {
+    path: PathBuf,
+    len: u64,
+    modified: SystemTime,
+}
+
+/// A directory of responses, one file for each
+#[derive(Debug)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    /// The most bytes the files can take
+    max_size: u64,
+    /// Only one thread changes the files at a time
+    lock: Mutex<()>,
+}
+
+impl ResponseCache {
+    /// A cache in `dir`, made if it does not exist.  Entries are good
+    /// for `ttl` (so nothing is cached with 0), and the oldest are
+    /// removed when there are more than `max_size` bytes
+    pub fn new(dir: &Path, ttl: Duration, max_size: u64) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+        Ok(ResponseCache {
+            dir: dir.to_path_buf(),
+            ttl,
+            max_size,
+            lock: Mutex::new(()),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.dir.as_path()
+    }
+
+    /// The answer to `method` on `path` with `body` would be cached:
+    /// it is a completion or chat, and has a temperature of 0 or a
+    /// seed.  Streams are never cached
+    pub fn cacheable(method: &str, path: &str, body: &str) -> bool {
+        if !method.eq_ignore_ascii_case("POST") || !path.ends_with("/completions") {
+            return false;
+        }
+        match serde_json::from_str::<Value>(body) {
+            Ok(json) => {
+                json["stream"] != true
+                    && (json["temperature"].as_f64() == Some(0.0) || !json["seed"].is_null())
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// The cached answer to `method` on `path` with `body`, if there
+    /// is one that has not expired
+    pub fn get(&self, method: &str, path: &str, body: &str) -> Option<Interaction> {
+        let _lock = self.lock.lock().unwrap();
+        let file = self.file(method, path, body);
+        let entry: CacheEntry =
+            serde_json::from_str(fs::read_to_string(&file).ok()?.as_str()).ok()?;
+        if now().saturating_sub(entry.created) >= self.ttl.as_secs() {
+            let _ = fs::remove_file(&file);
+            return None;
+        }
+        if entry.interaction.matches(method, path, body) {
+            Some(entry.interaction)
+        } else {
+            // Two requests with the same hash
+            None
+        }
+    }
+
+    /// Keep `interaction`, a successful answer to `body`, then remove
+    /// the oldest entries if the cache is too big
+    pub fn put(&self, body: &str, interaction: Interaction) -> Result<(), Box<dyn Error>> {
+        let _lock = self.lock.lock().unwrap();
+        let file = self.file(interaction.method.as_str(), interaction.path.as_str(), body);
+        let entry = CacheEntry {
+            created: now(),
+            interaction,
+        };
+        fs::write(file, serde_json::to_string(&entry)?)?;
+        self.trim()
+    }
+
+    /// Remove everything from the cache
+    pub fn clear(&self) -> Result<(), Box<dyn Error>> {
+        let _lock = self.lock.lock().unwrap();
+        for file in self.entries()? {
+            fs::remove_file(file.path)?;
+        }
+        Ok(())
+    }
+
+    /// How many entries there are, and how many bytes they take
+    pub fn size(&self) -> Result<(usize, u64), Box<dyn Error>> {
+        let entries = self.entries()?;
+        Ok((entries.len(), entries.iter().map(|f| f.len).sum()))
+    }
+
+    /// Remove the oldest entries until the cache is small enough
+    fn trim(&self) -> Result<(), Box<dyn Error>> {
+        let mut entries = self.entries()?;
+        let mut size: u64 = entries.iter().map(|f| f.len).sum();
+        entries.sort_by_key(|f| f.modified);
+        for file in entries {
+            if size <= self.max_size {
+                break;
+            }
+            fs::remove_file(file.path)?;
+            size -= file.len;
+        }
+        Ok(())
+    }
+
+    /// The files in the cache, their sizes and when they were written
+    fn entries(&self) -> Result<Vec<CacheFile>, Box<dyn Error>> {
+        let mut result = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("json") {
+                let metadata = fs::metadata(&path)?;
+                result.push(CacheFile {
+                    len: metadata.len(),
+                    modified: metadata.modified()?,
+                    path,
+                });
+            }
+        }
+        Ok(result)
+    }
+
+    /// Where the answer to a request is kept
+    fn file(&self, method: &str, path: &str, body: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}", request_hash(method, path, body)))
+            .with_extension("json")
+    }
+}
+
+/// A hash of a request that is the same from one run to the next.
+/// JSON bodies are hashed in a standard form, so the order of fields
+/// does not matter
+pub fn request_hash(method: &str, path: &str, body: &str) -> u64 {
+    let body = match serde_json::from_str::<Value>(body) {
+        Ok(json) => json.to_string(),
+        Err(_) => body.to_string(),
+    };
+    // FNV-1a
+    format!("{} {path} {body}", method.to_uppercase())
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Answers requests from `cache` when it can, and sends the rest
+/// with `inner`
+#[derive(Debug)]
+pub struct CachingTransport {
+    pub cache: Arc<ResponseCache>,
+    pub inner: Arc<dyn Transport>,
+}
+
+impl CachingTransport {
+    pub fn new(cache: Arc<ResponseCache>, inner: Arc<dyn Transport>) -> Self {
+        CachingTransport { cache, inner }
+    }
+}
+
+impl Transport for CachingTransport {
+    fn execute(&self, request: Request) -> Result<Response, Box<dyn Error>> {
+        let method = request.method().to_string();
+        let url = request.url().to_string();
+        let path = path_of(url.as_str());
+        let body = body_of(&request);
+        if !ResponseCache::cacheable(method.as_str(), path.as_str(), body.as_str()) {
+            return self.inner.execute(request);
+        }
+        let key = api_key_of(&request);
+        let secrets = [key.as_str()];
+        let body = redact(body.as_str(), &secrets);
+        if let Some(mut interaction) = self
+            .cache
+            .get(method.as_str(), path.as_str(), body.as_str())
+        {
+            interaction
+                .response_headers
+                .insert(CACHE_HEADER.to_string(), "hit".to_string());
+            return interaction.to_response();
+        }
+        let request_headers = header_map(request.headers());
+        let response = self.inner.execute(request)?;
+        if !response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status().as_u16();
+        let response_headers = header_map(response.headers());
+        let response_body = response.bytes()?;
+        let interaction = Interaction::new(
+            method.as_str(),
+            url.as_str(),
+            &request_headers,
+            body.as_str(),
+            status,
+            &response_headers,
+            &response_body,
+            &secrets,
+        );
+        let response = interaction.to_response()?;
+        // The answer has been paid for, so it is returned even if it
+        // cannot be kept
+        if let Err(err) = self.cache.put(body.as_str(), interaction) {
+            eprintln!("{err}: Failed to cache {path}");
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat_request::ChatOptions;
+    use crate::openai_interface::ApiInterface;
+    use llm_mock::MockServer;
+
+    #[test]
+    fn cache_chats() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(
+            ResponseCache::new(dir.path(), DEFAULT_CACHE_TTL, DEFAULT_CACHE_SIZE).unwrap(),
+        );
+        let server = MockServer::start().unwrap();
+        let mut api_interface = ApiInterface::new("sk-mock".to_string(), 100, 0.0);
+        api_interface.api_url = server.base_url();
+        api_interface.transport = Arc::new(CachingTransport::new(
+            cache.clone(),
+            api_interface.transport.clone(),
+        ));
+
+        let first = api_interface.chat("Hello", "gpt-4o-mini").unwrap();
+        assert!(!first.headers.contains_key(CACHE_HEADER));
+        api_interface.context.clear();
+        let second = api_interface.chat("Hello", "gpt-4o-mini").unwrap();
+        assert_eq!(second.body, first.body);
+        assert_eq!(second.headers.get(CACHE_HEADER).unwrap(), "hit");
+        assert_eq!(second.headers.get("Cost").unwrap(), "0");
+        assert_eq!(server.requests().len(), 1);
+        assert_eq!(cache.size().unwrap().0, 1);
+        assert!(!fs::read_to_string(
+            fs::read_dir(dir.path())
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap()
+                .path()
+        )
+        .unwrap()
+        .contains("sk-mock"));
+
+        // Random answers are not cached, unless there is a seed
+        api_interface.context.clear();
+        api_interface.temperature = 1.0;
+        api_interface.chat("Hello", "gpt-4o-mini").unwrap();
+        api_interface.context.clear();
+        api_interface.chat_options = ChatOptions::new().seed(3);
+        api_interface.chat("Hello", "gpt-4o-mini").unwrap();
+        api_interface.context.clear();
+        api_interface.chat("Hello", "gpt-4o-mini").unwrap();
+        assert_eq!(server.requests().len(), 3);
+
+        cache.clear().unwrap();
+        assert_eq!(cache.size().unwrap(), (0, 0));
+
+        // An answer that cannot be kept is still returned
+        fs::remove_dir_all(dir.path()).unwrap();
+        api_interface.context.clear();
+        let reply = api_interface.chat("Goodbye", "gpt-4o-mini").unwrap();
+        assert_eq!(reply.body, "Mock reply to: Goodbye");
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[test]
+    fn expiry_and_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let interaction = |body: &str| {
+            Interaction::new(
+                "POST",
+                "http://localhost/v1/chat/completions",
+                &Default::default(),
+                body,
+                200,
+                &Default::default(),
+                b"{}",
+                &[],
+            )
+        };
+        let expired =
+            ResponseCache::new(dir.path(), Duration::from_secs(0), DEFAULT_CACHE_SIZE).unwrap();
+        let body = r#"{"seed": 1}"#;
+        expired.put(body, interaction(body)).unwrap();
+        assert!(expired.get("POST", "/v1/chat/completions", body).is_none());
+        assert_eq!(expired.size().unwrap().0, 0);
+
+        // Room for one entry
+        let small = ResponseCache::new(dir.path(), DEFAULT_CACHE_TTL, 250).unwrap();
+        small.put(body, interaction(body)).unwrap();
+        let body2 = r#"{"seed": 2}"#;
+        small.put(body2, interaction(body2)).unwrap();
+        assert_eq!(small.size().unwrap().0, 1);
+        assert!(small.get("POST", "/v1/chat/completions", body2).is_some());
+        assert_eq!(
+            request_hash("POST", "/", r#"{"a": 1, "b": 2}"#),
+            request_hash("post", "/", r#"{"b":2,"a":1}"#)
+        );
+    }
+}
@@ -0,0 +1,359 @@
+//! Scripts of prompts and meta commands, with values captured from
+//! the replies and assertions about them.  One step a line:
+//!
+//! ```text
+//! # A comment
+//! ! sp You answer in one word
+//! What colour is the sky on a clear day?
+//! @assert contains blue
+//! @assert max-cost 0.5
+//! @capture colour regex (\w+)
+//! Name a fruit that is {colour}.  Answer in JSON: {"name": ...}
+//! @assert schema fruit.schema.json
+//! @capture fruit json $.name
+//! ```
+//!
+//! Lines that start with "!" are meta commands, with "@" they are
+//! about the last reply, and the rest are prompts.  A line that ends
+//! with "\" carries on on the next
+use regex::Regex;
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// How a value is taken from a reply
+#[derive(Debug)]
+pub enum Capture {
+    /// The first group, or the whole match if there are no groups
+    Regex(Regex),
+    /// Like `$.a.b[0]`, in the JSON in the reply
+    Json(String),
+}
+
+/// What must be true of a reply
+#[derive(Debug)]
+pub enum Assertion {
+    Contains(String),
+    Matches(Regex),
+    /// The JSON in the reply is valid.  The schema, and where it came
+    /// from
+    Schema(Value, String),
+    /// The reply cost no more than this many cents
+    MaxCost(f64),
+}
+
+#[derive(Debug)]
+pub enum Step {
+    /// Without the "!"
+    Meta(String),
+    Prompt(String),
+    Capture(String, Capture),
+    Assert(Assertion),
+}
+
+/// A step, and the line of the script it starts on
+#[derive(Debug)]
+pub struct ScriptLine {
+    pub line: usize,
+    pub step: Step,
+}
+
+/// Read the script at `path`.  Schemas are relative to it
+pub fn read_script(path: &Path) -> Result<Vec<ScriptLine>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{err}: {}", path.display()))?;
+    parse(text.as_str(), path.parent().unwrap_or(Path::new(".")))
+        .map_err(|err| format!("{}: {err}", path.display()))
+}
+
+/// The steps in `text`.  `dir` is where schema files are
+pub fn parse(text: &str, dir: &Path) -> Result<Vec<ScriptLine>, String> {
+    let mut result: Vec<ScriptLine> = vec![];
+    let mut lines = text.lines().enumerate();
+    while let Some((i, line)) = lines.next() {
+        let mut line = line.to_string();
+        while line.ends_with('\\') {
+            line.pop();
+            match lines.next() {
+                Some((_, next)) => {
+                    line.push('\n');
+                    line.push_str(next);
+                }
+                None => break,
+            }
+        }
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let step = if let Some(meta) = trimmed.strip_prefix('!') {
+            Step::Meta(meta.trim().to_string())
+        } else if let Some(directive) = trimmed.strip_prefix('@') {
+            parse_directive(directive, dir).map_err(|err| format!("Line {}: {err}", i + 1))?
+        } else {
+            Step::Prompt(trimmed.to_string())
+        };
+        result.push(ScriptLine { line: i + 1, step });
+    }
+    Ok(result)
+}
+
+/// The words after "@"
+fn parse_directive(directive: &str, dir: &Path) -> Result<Step, String> {
+    let (word, rest) = split_word(directive);
+    match word {
+        "assert" => {
+            let (kind, argument) = split_word(rest);
+            if argument.is_empty() {
+                return Err(format!("@assert {kind} needs an argument"));
+            }
+            Ok(Step::Assert(match kind {
+                "contains" => Assertion::Contains(argument.to_string()),
+                "matches" => Assertion::Matches(
+                    Regex::new(argument).map_err(|err| format!("{err}: {argument}"))?,
+                ),
+                "schema" => {
+                    let path = dir.join(argument);
+                    let schema = fs::read_to_string(path.as_path())
+                        .map_err(|err| err.to_string())
+                        .and_then(|s| serde_json::from_str(s.as_str()).map_err(|e| e.to_string()))
+                        .map_err(|err| format!("{err}: {}", path.display()))?;
+                    Assertion::Schema(schema, argument.to_string())
+                }
+                "max-cost" => Assertion::MaxCost(
+                    argument
+                        .parse::<f64>()
+                        .map_err(|err| format!("{err}: {argument}"))?,
+                ),
+                _ => {
+                    return Err(format!(
+                        "Unknown assertion: {kind}.  Use contains, matches, schema or max-cost"
+                    ))
+                }
+            }))
+        }
+        "capture" => {
+            let (name, rest) = split_word(rest);
+            let (kind, argument) = split_word(rest);
+            if name.is_empty() || argument.is_empty() {
+                return Err("@capture <name> regex|json <expression>".to_string());
+            }
+            let capture = match kind {
+                "regex" => Capture::Regex(
+                    Regex::new(argument).map_err(|err| format!("{err}: {argument}"))?,
+                ),
+                "json" => Capture::Json(argument.to_string()),
+                _ => return Err(format!("Unknown capture: {kind}.  Use regex or json")),
+            };
+            Ok(Step::Capture(name.to_string(), capture))
+        }
+        _ => Err(format!(
+            "Unknown directive: @{word}.  Use @assert or @capture"
+        )),
+    }
+}
+
+/// The first word, and the rest trimmed
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    }
+}
+
+/// The JSON in a reply: all of it, or the first fenced block, or
+/// from the first bracket to the last
+pub fn reply_json(reply: &str) -> Result<Value, String> {
+    if let Ok(value) = serde_json::from_str(reply.trim()) {
+        return Ok(value);
+    }
+    if let Some(start) = reply.find("```") {
+        let block = &reply[start + 3..];
+        // Past the language
+        let block = block.split_once('\n').map(|(_, b)| b).unwrap_or(block);
+        if let Some(end) = block.find("```") {
+            if let Ok(value) = serde_json::from_str(&block[..end]) {
+                return Ok(value);
+            }
+        }
+    }
+    let start = reply.find(['{', '[']);
+    let end = reply.rfind(['}', ']']);
+    if let (Some(start), Some(end)) = (start, end) {
+        if start < end {
+            if let Ok(value) = serde_json::from_str(&reply[start..=end]) {
+                return Ok(value);
+            }
+        }
+    }
+    Err("No JSON in the reply".to_string())
+}
+
+/// The value at `path` in `value`.  Paths are like `$.a.b[0]`, or
+/// `a.b.0`
+pub fn json_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let path = path.trim().trim_start_matches('$');
+    let path = path.replace('[', ".").replace(']', "");
+    path.split('.')
+        .filter(|p| !p.is_empty())
+        .try_fold(value, |v, key| match v {
+            Value::Array(a) => key.parse::<usize>().ok().and_then(|i| a.get(i)),
+            Value::Object(o) => o.get(key.trim_matches(|c| c == '"' || c == '\'')),
+            _ => None,
+        })
+}
+
+impl Capture {
+    /// The value captured from `reply`
+    pub fn capture(&self, reply: &str) -> Result<String, String> {
+        match self {
+            Capture::Regex(re) => re
+                .captures(reply)
+                .map(|c| c.get(1).or_else(|| c.get(0)).unwrap().as_str().to_string())
+                .ok_or_else(|| format!("{re} does not match")),
+            Capture::Json(path) => {
+                let value = reply_json(reply)?;
+                match json_path(&value, path) {
+                    Some(Value::String(s)) => Ok(s.clone()),
+                    Some(v) => Ok(v.to_string()),
+                    None => Err(format!("Nothing at {path}")),
+                }
+            }
+        }
+    }
+}
+
+impl Assertion {
+    /// Whether `reply`, that cost `cost` cents if it is known, passes
+    pub fn check(&self, reply: &str, cost: Option<f64>) -> Result<(), String> {
+        match self {
+            Assertion::Contains(text) if reply.contains(text.as_str()) => Ok(()),
+            Assertion::Contains(text) => Err(format!("Does not contain {text:?}")),
+            Assertion::Matches(re) if re.is_match(reply) => Ok(()),
+            Assertion::Matches(re) => Err(format!("Does not match {re}")),
+            Assertion::Schema(schema, name) => {
+                let instance = reply_json(reply)?;
+                let compiled = jsonschema::JSONSchema::compile(schema)
+                    .map_err(|err| format!("{err}: Bad schema {name}"))?;
+                let result = match compiled.validate(&instance) {
+                    Ok(()) => Ok(()),
+                    Err(errors) => Err(format!(
+                        "Not valid for {name}: {}",
+                        errors
+                            .map(|e| format!("{} at {}", e, e.instance_path))
+                            .collect::<Vec<String>>()
+                            .join("; ")
+                    )),
+                };
+                result
+            }
+            Assertion::MaxCost(max) => match cost {
+                Some(cost) if cost <= *max => Ok(()),
+                Some(cost) => Err(format!("Cost {cost:.4} cents, more than {max}")),
+                None => Err("The cost is not known".to_string()),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Assertion::Contains(text) => write!(f, "contains {text}"),
+            Assertion::Matches(re) => write!(f, "matches {re}"),
+            Assertion::Schema(_, name) => write!(f, "schema {name}"),
+            Assertion::MaxCost(max) => write!(f, "max-cost {max}"),
+        }
+    }
+}
+
+/// How a run went
+#[derive(Debug, Default)]
+pub struct ScriptReport {
+    /// Assertions checked
+    pub assertions: usize,
+    /// Steps that failed: the line and why
+    pub failures: Vec<(usize, String)>,
+    /// Cents, for the replies whose cost is known
+    pub cost: f64,
+}
+
+impl ScriptReport {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// The summary.  Each failure is shown as it happens, so only its
+/// line is here
+impl fmt::Display for ScriptReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} assertions, {} failures.  Cost {:.4} cents",
+            if self.passed() { "PASSED" } else { "FAILED" },
+            self.assertions,
+            self.failures.len(),
+            self.cost
+        )?;
+        if !self.passed() {
+            let lines: Vec<String> = self.failures.iter().map(|(l, _)| l.to_string()).collect();
+            write!(f, "\nLines that failed: {}", lines.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_steps() {
+        let script = "# Comment\n\n! sp Be terse\nHello \\\nthere\n@assert contains Hi\n@capture greeting regex (\\w+)\n@assert max-cost 0.5\n";
+        let steps = parse(script, Path::new(".")).unwrap();
+        assert_eq!(steps.len(), 5);
+        assert!(matches!(steps[0].step, Step::Meta(ref m) if m == "sp Be terse"));
+        assert!(matches!(steps[1].step, Step::Prompt(ref p) if p == "Hello \nthere"));
+        assert_eq!(steps[2].line, 6);
+        assert!(
+            matches!(steps[3].step, Step::Capture(ref n, Capture::Regex(_)) if n == "greeting")
+        );
+        assert!(parse("@assert sounds nice", Path::new(".")).is_err());
+        assert!(parse("@capture x regex", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn captures() {
+        let reply = "Here it is:\n```json\n{\"fruits\": [{\"name\": \"lemon\"}], \"n\": 1}\n```";
+        let json = Capture::Json("$.fruits[0].name".to_string());
+        assert_eq!(json.capture(reply).unwrap(), "lemon");
+        assert_eq!(Capture::Json("n".to_string()).capture(reply).unwrap(), "1");
+        assert!(Capture::Json("$.nope".to_string()).capture(reply).is_err());
+        let re = Capture::Regex(Regex::new(r"name.: .(\w+)").unwrap());
+        assert_eq!(re.capture(reply).unwrap(), "lemon");
+    }
+
+    #[test]
+    fn assertions() {
+        let reply = r#"{"name": "lemon", "colour": "yellow"}"#;
+        assert!(Assertion::Contains("lemon".to_string())
+            .check(reply, None)
+            .is_ok());
+        assert!(Assertion::Matches(Regex::new("^x").unwrap())
+            .check(reply, None)
+            .is_err());
+        assert!(Assertion::MaxCost(0.5).check(reply, Some(0.4)).is_ok());
+        assert!(Assertion::MaxCost(0.5).check(reply, Some(0.6)).is_err());
+        assert!(Assertion::MaxCost(0.5).check(reply, None).is_err());
+        let schema: Value = serde_json::from_str(
+            r#"{"type": "object", "required": ["name"], "properties": {"name": {"type": "string"}}}"#,
+        )
+        .unwrap();
+        let assertion = Assertion::Schema(schema, "fruit".to_string());
+        assert!(assertion.check(reply, None).is_ok());
+        assert!(assertion.check(r#"{"name": 3}"#, None).is_err());
+        assert!(assertion.check("No JSON", None).is_err());
+    }
+}
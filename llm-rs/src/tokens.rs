@@ -0,0 +1,56 @@
+//! Estimate how many tokens a piece of text will use.  There is no
+//! tokeniser here, so this is an approximation: a run of letters and
+//! digits costs one token per four characters (at least one), and
+//! every other visible character costs one token.  It errs on the
+//! high side for English prose.
+
+/// Tokens the chat format adds for each message (role and
+/// separators)
+pub const TOKENS_PER_MESSAGE: usize = 4;
+
+/// Tokens the chat format adds to prime the reply
+pub const TOKENS_PER_REPLY: usize = 3;
+
+/// Estimate the tokens in `text`
+pub fn estimate_tokens(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut word: usize = 0;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            word += 1;
+        } else {
+            tokens += word.div_ceil(4);
+            word = 0;
+            if !c.is_whitespace() {
+                tokens += 1;
+            }
+        }
+    }
+    tokens + word.div_ceil(4)
+}
+
+/// Estimate the tokens in a chat, given the content of its messages
+pub fn estimate_chat_tokens<'a>(contents: impl Iterator<Item = &'a str>) -> usize {
+    contents
+        .map(|c| estimate_tokens(c) + TOKENS_PER_MESSAGE)
+        .sum::<usize>()
+        + TOKENS_PER_REPLY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("a"), 1);
+        assert_eq!(estimate_tokens("word"), 1);
+        assert_eq!(estimate_tokens("words"), 2);
+        assert_eq!(estimate_tokens("Hello, world!"), 6);
+        assert_eq!(
+            estimate_chat_tokens(["Hello", "world"].into_iter()),
+            4 + 2 * TOKENS_PER_MESSAGE + TOKENS_PER_REPLY
+        );
+    }
+}
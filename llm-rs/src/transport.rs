@@ -0,0 +1,95 @@
+//! How requests get to OpenAI.  Every endpoint of `ApiInterface`
+//! sends its requests through a `Transport`, so timeouts, proxies,
+//! extra CA certificates, recording (see `cassette`) and mocks are set
+//! up in one place
+use reqwest::blocking::Client;
+use reqwest::blocking::ClientBuilder;
+use reqwest::blocking::Request;
+use reqwest::blocking::Response;
+use reqwest::Certificate;
+use reqwest::Proxy;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long a request can take.  Image generation is slow
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1200);
+
+/// Sends requests and returns their responses
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Send `request` and wait for the response
+    fn execute(&self, request: Request) -> Result<Response, Box<dyn Error>>;
+}
+
+/// How an `HttpTransport` connects
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransportOptions {
+    /// The longest a request can take, from connecting to the end of
+    /// the response.  `None` for no limit
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    /// The URL of a proxy for all requests, like
+    /// "http://proxy.example.com:8080"
+    pub proxy: Option<String>,
+    /// PEM files of certificate authorities to trust as well as the
+    /// system's
+    pub ca_certificates: Vec<PathBuf>,
+}
+
+impl Default for TransportOptions {
+    fn default() -> Self {
+        TransportOptions {
+            timeout: Some(DEFAULT_TIMEOUT),
+            connect_timeout: None,
+            proxy: None,
+            ca_certificates: vec![],
+        }
+    }
+}
+
+/// Sends requests over the network
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    client: Client,
+}
+
+impl HttpTransport {
+    pub fn new(options: &TransportOptions) -> Result<Self, Box<dyn Error>> {
+        let mut builder = ClientBuilder::new()
+            .timeout(options.timeout)
+            .pool_idle_timeout(None)
+            .connection_verbose(false);
+        if let Some(connect_timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(ref proxy) = options.proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str())?);
+        }
+        for path in options.ca_certificates.iter() {
+            let pem = fs::read(path).map_err(|err| format!("{err}: {}", path.display()))?;
+            builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+        }
+        Ok(HttpTransport {
+            client: builder.build()?,
+        })
+    }
+
+    /// The client that does the sending
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        Self::new(&TransportOptions::default()).unwrap()
+    }
+}
+
+impl Transport for HttpTransport {
+    fn execute(&self, request: Request) -> Result<Response, Box<dyn Error>> {
+        Ok(self.client.execute(request)?)
+    }
+}
@@ -0,0 +1,35 @@
+//! `cli ask`, which answers one prompt and exits
+use std::path::Path;
+use std::process::Command;
+use std::process::Output;
+
+/// Run `cli` in `dir` with `args`
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cli"))
+        .current_dir(dir)
+        .env("HOME", dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_DATA_HOME", dir.join("data"))
+        .env("OPENAI_API_KEY", "sk-mock")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn flags_that_cannot_be_used_are_usage_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = run(dir.path(), &["--replay", "missing.json", "ask", "Hello"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("missing.json"));
+
+    let output = run(dir.path(), &["--proxy", "::bad::", "ask", "Hello"]);
+    assert_eq!(output.status.code(), Some(2));
+
+    // So is a file of model capabilities that cannot be read
+    let config = dir.path().join("config").join("llm-rs");
+    std::fs::create_dir_all(&config).unwrap();
+    std::fs::write(config.join("model_capabilities.json"), "{").unwrap();
+    let output = run(dir.path(), &["ask", "Hello"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
@@ -0,0 +1,71 @@
+//! The interactive interface, with what is typed piped in
+use llm_mock::MockServer;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::process::Output;
+use std::process::Stdio;
+
+/// Run `cli` against `server` in `dir`, typing `input`
+fn run(server: &MockServer, dir: &Path, input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cli"))
+        .current_dir(dir)
+        .env("HOME", dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_DATA_HOME", dir.join("data"))
+        .env("OPENAI_API_KEY", "sk-mock")
+        .args(["--api-url", server.base_url().as_str()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn unknown_session_leaves_the_session_running() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let output = run(
+        &server,
+        dir.path(),
+        "Hello\n\
+         ! sessions open nosuch\n\
+         ! sessions rename nosuch other\n\
+         ! sessions delete nosuch\n\
+         Again\n\
+         ! sessions\n",
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("No session with ID or name nosuch: Failed to open session nosuch"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("Failed to rename session nosuch"));
+    assert!(stdout.contains("Failed to delete session nosuch"));
+    // The conversation carried on in the same session
+    assert!(stdout.contains("Mock reply to: Again"), "{stdout}");
+    assert_eq!(stdout.matches("\n* ").count(), 1, "{stdout}");
+    assert!(stdout.contains("2 turns"), "{stdout}");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn missing_script_is_reported() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let output = run(&server, dir.path(), "! run missing.txt\nHello\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Failed to run missing.txt"), "{stdout}");
+    assert!(!stdout.contains("Scripts cannot run scripts"));
+    assert!(stdout.contains("Mock reply to: Hello"));
+    assert_eq!(output.status.code(), Some(0));
+}
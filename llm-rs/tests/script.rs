@@ -0,0 +1,64 @@
+//! `cli --script` against the mock server
+use llm_mock::MockServer;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::process::Output;
+
+/// Run `script` with `cli` against `server` in `dir`
+fn run(server: &MockServer, dir: &Path, script: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cli"))
+        .current_dir(dir)
+        .env("HOME", dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_DATA_HOME", dir.join("data"))
+        .env("OPENAI_API_KEY", "sk-mock")
+        .args(["--api-url", server.base_url().as_str(), "--script"])
+        .arg(script)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn script_runs_against_mock() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("check.txt");
+    fs::write(
+        &script,
+        "! run other.txt\n\
+         Hello\n\
+         @assert contains Mock reply to: Hello\n\
+         @assert contains Goodbye\n\
+         @capture who regex to: (\\w+)\n\
+         Again {who}\n\
+         @assert contains Again Hello\n",
+    )
+    .unwrap();
+    let output = run(&server, dir.path(), &script);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // A script cannot run another, and the run goes on
+    assert!(
+        stdout.contains("Scripts cannot run scripts: Failed to run other.txt"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("PASS line 3: contains Mock reply to: Hello"));
+    assert!(stdout.contains("PASS line 7: contains Again Hello"));
+    // Each failure is shown once, then counted in the summary
+    assert_eq!(stdout.matches("FAIL line 4").count(), 1, "{stdout}");
+    assert!(stdout.contains("FAILED: 3 assertions, 1 failures"));
+    assert!(stdout.contains("Lines that failed: 4"));
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn missing_script_is_a_usage_error() {
+    let server = MockServer::start().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let output = run(&server, dir.path(), &dir.path().join("missing.txt"));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("missing.txt"));
+    assert!(server.requests().is_empty());
+}
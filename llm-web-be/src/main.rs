@@ -5,10 +5,20 @@ extern crate llm_web_common;
 mod authorisation;
 mod server;
 mod session;
+use llm_rs::cassette::Cassette;
+use llm_rs::response_cache::ResponseCache;
+use llm_rs::response_cache::DEFAULT_CACHE_SIZE;
+use llm_rs::response_cache::DEFAULT_CACHE_TTL;
+use llm_rs::transport::HttpTransport;
 use std::env;
+use std::sync::Arc;
 mod data_store;
 //use async_std::task;
 
+/// Names the directory to cache chat answers in.  Only chats with a
+/// temperature of 0, or a seed, are cached
+const CACHE_DIR_VAR: &str = "LLM_WEB_CACHE_DIR";
+
 #[allow(dead_code)]
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 // main
@@ -17,10 +27,66 @@ async fn main() {
     let mut args: env::Args = env::args();
     let _programme_name = args.next().unwrap();
 
-    // If one argument "test", then the LLM is not contacted
+    // If one argument "test", then the LLM is not contacted.  Requests
+    // go to a mock server that answers with constant replies.
+    // "record <cassette>" records the traffic with the LLM, and
+    // "replay <cassette>" answers from the recording
     let mut args: Vec<String> = args.collect();
+    if args.len() == 2 && (args[0] == "record" || args[0] == "replay") {
+        let path = std::path::Path::new(args[1].as_str());
+        let cassette = if args[0] == "record" {
+            Cassette::record(path, Arc::new(HttpTransport::default()))
+        } else {
+            if env::var("OPENAI_API_KEY").is_err() {
+                env::set_var("OPENAI_API_KEY", "sk-replay");
+            }
+            Cassette::replay(path)
+        };
+        let cassette = match cassette {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("FAILED to open cassette {}: {}", args[1], e);
+                std::process::exit(1);
+            }
+        };
+        eprintln!("Cassette: {} {}", cassette.mode(), args[1]);
+        if let Err(e) = server::AppBackend::run_server(
+            llm_rs::openai_interface::API_URL,
+            Some(Arc::new(cassette)),
+            response_cache(),
+        )
+        .await
+        {
+            eprintln!("FAILED: {}", e);
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
     if args.is_empty() || args[0] == "test" {
-        if let Err(e) = server::AppBackend::run_server().await {
+        let mock = if args.is_empty() {
+            None
+        } else {
+            match llm_mock::MockServer::start() {
+                Ok(mock) => {
+                    eprintln!("Test mode: LLM requests go to {}", mock.base_url());
+                    if env::var("OPENAI_API_KEY").is_err() {
+                        env::set_var("OPENAI_API_KEY", "sk-mock");
+                    }
+                    Some(mock)
+                }
+                Err(e) => {
+                    eprintln!("FAILED to start the mock server: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        };
+        let api_url = match mock {
+            Some(ref mock) => mock.base_url(),
+            None => llm_rs::openai_interface::API_URL.to_string(),
+        };
+        if let Err(e) =
+            server::AppBackend::run_server(api_url.as_str(), None, response_cache()).await
+        {
             eprintln!("FAILED: {}", e);
             std::process::exit(1);
         }
@@ -75,3 +141,22 @@ async fn main() {
         _ => panic!("{}\nNot: '{}'", USAGE, s1),
     };
 }
+
+/// The response cache, if `CACHE_DIR_VAR` is set
+fn response_cache() -> Option<Arc<ResponseCache>> {
+    let dir = env::var(CACHE_DIR_VAR).ok()?;
+    match ResponseCache::new(
+        std::path::Path::new(dir.as_str()),
+        DEFAULT_CACHE_TTL,
+        DEFAULT_CACHE_SIZE,
+    ) {
+        Ok(cache) => {
+            eprintln!("Caching chat answers in {dir}");
+            Some(Arc::new(cache))
+        }
+        Err(e) => {
+            eprintln!("FAILED to open the response cache {dir}: {e}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -14,18 +14,33 @@ use hyper::body;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::Server;
 use hyper::{Body, Request, Response, StatusCode};
+use llm_rs::chat_request::chat_request_body;
+use llm_rs::chat_request::listed_cost;
 use llm_rs::json::ChatRequestInfo;
+use llm_rs::json::Message as ChatMessage;
 use llm_rs::json::Usage;
+use llm_rs::model_registry::available_models;
+use llm_rs::model_registry::Capability;
+use llm_rs::model_registry::ModelEntry;
+use llm_rs::model_registry::ModelRegistry;
+use llm_rs::model_registry::MODEL_CACHE_TTL;
 use llm_rs::openai_interface;
+use llm_rs::response_cache::CachingTransport;
+use llm_rs::response_cache::ResponseCache;
+use llm_rs::response_cache::CACHE_HEADER;
+use llm_rs::transport::HttpTransport;
+use llm_rs::transport::Transport;
 use llm_web_common::communication::ChatPrompt;
 use llm_web_common::communication::ChatResponse;
 use llm_web_common::communication::ExtraInfo;
 use llm_web_common::communication::InvalidRequest;
-use llm_web_common::communication::LLMMessage;
 use llm_web_common::communication::LoginResponse;
 use llm_web_common::communication::LogoutRequest;
 use llm_web_common::communication::LogoutResponse;
 use llm_web_common::communication::Message;
+use llm_web_common::communication::ModelDescription;
+use llm_web_common::communication::ModelsRequest;
+use llm_web_common::communication::ModelsResponse;
 use llm_web_common::communication::{CommType, LoginRequest};
 use serde_json::json;
 use std::collections::HashMap;
@@ -33,6 +48,7 @@ use std::convert::Infallible;
 use std::error::Error;
 use std::fmt;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -40,6 +56,17 @@ use std::vec::Vec;
 use std::{env, fs, io};
 use uuid::Uuid;
 
+/// Where the list of models from OpenAI is cached
+const MODEL_CACHE_FILE: &str = "models.json";
+
+/// Where the list of models is cached when the backend is talking
+/// to something other than OpenAI.  Kept apart so the mock server's
+/// models are never taken for OpenAI's
+const OTHER_MODEL_CACHE_FILE: &str = "models_other.json";
+
+/// Overrides for what models can do.  See `ModelRegistry`
+const MODEL_CAPABILITIES_FILE: &str = "model_capabilities.json";
+
 fn _error(err: String) -> io::Error {
     io::Error::new(io::ErrorKind::Other, err)
 }
@@ -49,17 +76,39 @@ pub struct AppBackend {
     /// The front end logs in and starts a session.  Sessions are
     /// indexed by the session token
     pub sessions: Arc<Mutex<HashMap<String, Session>>>,
+
+    /// Where requests for the LLM go.  `openai_interface::API_URL`
+    /// unless testing
+    pub api_url: String,
+
+    /// Sends requests to the LLM.  If not set each request gets an
+    /// `HttpTransport` of its own
+    pub transport: Option<Arc<dyn Transport>>,
+
+    /// Earlier answers to chats, if caching is on
+    pub response_cache: Option<Arc<ResponseCache>>,
 }
 
 impl AppBackend {
     pub fn new() -> Self {
         let hm = HashMap::<String, Session>::new();
         let sessions = Arc::new(Mutex::new(hm));
-        Self { sessions }
+        Self {
+            sessions,
+            api_url: openai_interface::API_URL.to_string(),
+            transport: None,
+            response_cache: None,
+        }
     }
 
-    /// Main loop
-    pub async fn run_server() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// Main loop.  Requests for the LLM are sent to `api_url` with
+    /// `transport`, if there is one.  Chats are answered from
+    /// `response_cache` when they can be
+    pub async fn run_server(
+        api_url: &str,
+        transport: Option<Arc<dyn Transport>>,
+        response_cache: Option<Arc<ResponseCache>>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // First parameter is port number (optional, defaults to 1337)
         let port: usize = // std::env::args()
         // .nth(1)
@@ -68,7 +117,10 @@ impl AppBackend {
 	    1337;
         let addr: SocketAddr = format!("127.0.0.1:{}", port).parse()?;
 
-        let app_backend = AppBackend::new();
+        let mut app_backend = AppBackend::new();
+        app_backend.api_url = api_url.to_string();
+        app_backend.transport = transport;
+        app_backend.response_cache = response_cache;
         let data_server = Arc::new(app_backend);
         let service = make_service_fn(move |_: _| {
             let data_server = Arc::clone(&data_server);
@@ -259,21 +311,39 @@ impl AppBackend {
             // Need an API key for OpenAI
             let api_key = env::var("OPENAI_API_KEY").expect("No API Key found");
 
-            // Put the conversation so far in here
-            let messages: Vec<LLMMessage> = prompt.messages;
-
-            // The JSON payload
-            let data = json!({
-            "messages": messages,
-            "model": prompt.model.as_str(),
-            "temperature": prompt.temperature,
-                });
+            // Put the conversation so far in here.  The roles of
+            // `LLMMessage` serialise to the role names OpenAI uses
+            let messages: Vec<ChatMessage> =
+                serde_json::from_value(json!(prompt.messages)).expect("LLMMessage to Message");
+
+            // The JSON payload, with only what the model accepts.
+            // Reasoning models take no system prompt or temperature
+            let registry = ModelRegistry::with_overrides(Path::new(MODEL_CAPABILITIES_FILE))
+                .unwrap_or_default();
+            let data = chat_request_body(
+                prompt.model.as_str(),
+                &messages,
+                &prompt.options,
+                &registry.capabilities(prompt.model.as_str()),
+            );
 
             // Send the request to the LLM
+            let api_url = self.api_url.clone();
+            let transport = self.transport.clone();
+            let response_cache = self.response_cache.clone();
             let response_result: Result<(HashMap<String, String>, ChatRequestInfo), Message> =
-                tokio::task::spawn_blocking(
-                    move || match openai_interface::ApiInterface::send_chat(api_key.as_str(), &data)
-                    {
+                tokio::task::spawn_blocking(move || {
+                    let mut transport =
+                        transport.unwrap_or_else(|| Arc::new(HttpTransport::default()));
+                    if let Some(cache) = response_cache {
+                        transport = Arc::new(CachingTransport::new(cache, transport));
+                    }
+                    match openai_interface::ApiInterface::send_chat(
+                        transport.as_ref(),
+                        api_url.as_str(),
+                        api_key.as_str(),
+                        &data,
+                    ) {
                         Ok(r) => Ok(r),
                         Err(err) => {
                             let chat_response = InvalidRequest {
@@ -284,8 +354,8 @@ impl AppBackend {
                                 object: serde_json::to_string(&chat_response).unwrap(),
                             })
                         }
-                    },
-                )
+                    }
+                })
                 .await
                 .unwrap();
 
@@ -302,13 +372,25 @@ impl AppBackend {
                 .expect("Time went backwards")
                 .as_secs();
             headers_r.insert("timestamp".to_string(), format!("{timestamp}"));
+            let reasoning_tokens = chat_response.1.usage.reasoning_tokens();
+            if reasoning_tokens > 0 {
+                headers_r.insert(
+                    "Tokens reasoning".to_string(),
+                    format!("{reasoning_tokens}"),
+                );
+            }
 
             // let mut result = "".to_string();
             // for (k, v) in chat_response.0.iter() {
             //     result = format!("{result}{k} => {v}\n");
             // }
 
-            let cost = Self::cost(chat_response.1.usage, chat_response.1.model.as_str());
+            // Answers from the cache are free
+            let cost = if chat_response.0.contains_key(CACHE_HEADER) {
+                0.0
+            } else {
+                Self::cost(chat_response.1.usage, chat_response.1.model.as_str())
+            };
 
             let model = chat_response.1.model.clone();
             let response = chat_response.1.choices[0].message.content.clone();
@@ -374,6 +456,68 @@ impl AppBackend {
         }
     }
 
+    /// Tell the front end what models it can chat with
+    async fn process_models_request(&self, message: &Message) -> Message {
+        if message.comm_type != CommType::ModelsRequest {
+            return Message::from(InvalidRequest {
+                reason: format!(
+                    "Invalid message type sent to `models`: {}",
+                    message.comm_type
+                ),
+            });
+        }
+        let request: ModelsRequest = match serde_json::from_str(&message.object) {
+            Ok(r) => r,
+            Err(err) => {
+                return Message::from(InvalidRequest {
+                    reason: format!("{err}: Not a ModelsRequest"),
+                })
+            }
+        };
+        if !self.valid_session(request.token.as_str()) {
+            return Message::from(InvalidRequest {
+                reason: "Invalid session".to_string(),
+            });
+        }
+
+        let api_key = env::var("OPENAI_API_KEY").expect("No API Key found");
+        let api_url = self.api_url.clone();
+        let transport = self.transport.clone();
+        let cache = if api_url == openai_interface::API_URL && transport.is_none() {
+            MODEL_CACHE_FILE
+        } else {
+            OTHER_MODEL_CACHE_FILE
+        };
+        let models: Result<Vec<ModelEntry>, String> = tokio::task::spawn_blocking(move || {
+            let mut api_interface = openai_interface::ApiInterface::new(api_key, 0, 0.0);
+            api_interface.api_url = api_url;
+            if let Some(transport) = transport {
+                api_interface.transport = transport;
+            }
+            let registry = ModelRegistry::with_overrides(Path::new(MODEL_CAPABILITIES_FILE))
+                .map_err(|err| format!("{err}: {MODEL_CAPABILITIES_FILE}"))?;
+            available_models(&api_interface, Path::new(cache), MODEL_CACHE_TTL, &registry)
+                .map_err(|err| format!("{err}"))
+        })
+        .await
+        .unwrap();
+        match models {
+            Ok(models) => Message::from(ModelsResponse {
+                models: models
+                    .iter()
+                    .filter(|m| m.can(Capability::Chat))
+                    .map(|m| ModelDescription {
+                        id: m.id.clone(),
+                        capabilities: m.capabilities.iter().map(|c| c.to_string()).collect(),
+                    })
+                    .collect(),
+            }),
+            Err(err) => Message::from(InvalidRequest {
+                reason: format!("OpenAI Models Error: {err}"),
+            }),
+        }
+    }
+
     /// Handle requests and route them to handlers
     async fn process_request(&self, req: Request<Body>) -> Result<Response<Body>, ServerError> {
         let response: Response<Body> = match (req.method(), req.uri().path()) {
@@ -408,6 +552,16 @@ impl AppBackend {
 
                 Response::new(Body::from(s))
             }
+            (_, "/api/models") => {
+                let str = Self::body_to_string(req.into_body()).await.unwrap();
+                let message: Message = match serde_json::from_str(&str) {
+                    Ok(s) => s,
+                    Err(err) => return Err(ServerError::from(err)),
+                };
+                let return_message = self.process_models_request(&message).await;
+                let s = serde_json::to_string(&return_message).unwrap();
+                Response::new(Body::from(s))
+            }
             (_, "/api/logout") => {
                 let str = Self::body_to_string(req.into_body()).await.unwrap();
                 let message: Message = match serde_json::from_str(&str) {
@@ -461,6 +615,10 @@ impl AppBackend {
     // Calculate the cost of a OpenAI chat
     /// Convert the usege into a price.
     fn cost(usage: Usage, model: &str) -> f64 {
+        // Reasoning and newer models are in the list of prices
+        if let Some(cost) = listed_cost(&usage, model) {
+            return cost;
+        }
         // GPT-4 is more expensive
         if model.starts_with("gpt-4o") {
             usage.completion_tokens as f64 * 1000_f64 / 1_000_000_f64
@@ -472,7 +630,8 @@ impl AppBackend {
             usage.completion_tokens as f64 * 500_f64 / 1_000_000_f64
                 + usage.prompt_tokens as f64 * 150_f64 / 1_000_000_f64
         } else {
-            panic!("{}", model);
+            eprintln!("No price is known for {model}, so what it cost is not counted");
+            0.0
         }
     }
 }
@@ -588,6 +747,17 @@ mod tests {
         assert!(result.comm_type == CommType::InvalidRequest);
     }
 
+    #[tokio::test]
+    async fn models_invalid_session() {
+        // Models are only listed for a logged in user
+        let msg = Message::from(ModelsRequest {
+            token: "not a token".to_string(),
+        });
+        let server = AppBackend::new();
+        let result = server.process_models_request(&msg).await;
+        assert_eq!(result.comm_type, CommType::InvalidRequest);
+    }
+
     #[tokio::test]
     async fn server_test() {
         // Server to test
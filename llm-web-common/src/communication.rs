@@ -5,6 +5,8 @@ use std::collections::HashMap;
 use std::fmt;
 use uuid::Uuid;
 
+pub use llm_chat_options::ChatOptions;
+
 /// The communication between the front end and the back end uses
 /// `Message` struct.  `CommType` categorises the communication and
 /// defines what object is being relayed in the `Message.object` type
@@ -16,6 +18,8 @@ pub enum CommType {
     LogoutResponse,
     ChatPrompt,
     ChatResponse,
+    ModelsRequest,
+    ModelsResponse,
     InvalidRequest,
 }
 
@@ -91,7 +95,10 @@ pub struct ChatPrompt {
     //
     pub messages: Vec<LLMMessage>,
 
-    pub temperature: f64,
+    /// Temperature, token limit and the other parameters of the
+    /// request
+    #[serde(default)]
+    pub options: ChatOptions,
 
     // The user's authenticating data
     pub token: String,
@@ -135,6 +142,29 @@ pub struct ChatResponse {
     pub backend_data: Option<ExtraInfo>,
 }
 
+/// From llm-web-fe -> llm-web-be.  Ask what chat models there are
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ModelsRequest {
+    // The user's authenticating data
+    pub token: String,
+}
+
+/// A model the user can chat with
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ModelDescription {
+    pub id: String,
+
+    /// What else the model can do: "vision", "tools", "audio",
+    /// "system_role", "temperature"...
+    pub capabilities: Vec<String>,
+}
+
+/// From llm-web-be -> llm-web-fe.  The chat models, sorted by name
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ModelsResponse {
+    pub models: Vec<ModelDescription>,
+}
+
 // Display for CommType
 impl fmt::Display for CommType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -145,6 +175,8 @@ impl fmt::Display for CommType {
             CommType::LogoutResponse => write!(f, "Logout Response"),
             CommType::ChatPrompt => write!(f, "Chat Prompt"),
             CommType::ChatResponse => write!(f, "Chat Response"),
+            CommType::ModelsRequest => write!(f, "Models Request"),
+            CommType::ModelsResponse => write!(f, "Models Response"),
             CommType::InvalidRequest => write!(f, "Invalid Request"),
         }
     }
@@ -237,3 +269,21 @@ impl From<LogoutResponse> for Message {
         }
     }
 }
+
+impl From<ModelsRequest> for Message {
+    fn from(request: ModelsRequest) -> Self {
+        Message {
+            comm_type: CommType::ModelsRequest,
+            object: serde_json::to_string(&request).unwrap(),
+        }
+    }
+}
+
+impl From<ModelsResponse> for Message {
+    fn from(response: ModelsResponse) -> Self {
+        Message {
+            comm_type: CommType::ModelsResponse,
+            object: serde_json::to_string(&response).unwrap(),
+        }
+    }
+}
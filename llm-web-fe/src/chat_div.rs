@@ -17,6 +17,7 @@ use crate::set_page::update_user_display;
 use crate::utility::format_with_commas;
 use crate::utility::print_to_console;
 use gloo_events::EventListener;
+use llm_web_common::communication::ChatOptions;
 use llm_web_common::communication::ChatPrompt;
 use llm_web_common::communication::ChatResponse;
 use llm_web_common::communication::CommType;
@@ -24,6 +25,9 @@ use llm_web_common::communication::InvalidRequest;
 use llm_web_common::communication::LLMMessage;
 use llm_web_common::communication::LLMMessageType;
 use llm_web_common::communication::Message;
+use llm_web_common::communication::ModelDescription;
+use llm_web_common::communication::ModelsRequest;
+use llm_web_common::communication::ModelsResponse;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -39,12 +43,9 @@ use web_sys::{
     HtmlInputElement, HtmlLabelElement, HtmlSpanElement, HtmlTextAreaElement,
 };
 
-/// The model names
-const GPT_3: (&str, &str) = ("gpt-3.5-turbo", "GPT-3.5");
-const GPT_4: (&str, &str) = ("gpt-4", "GPT-4");
-const GPT_4_0_MINI:  (&str, &str) = ("gpt-4o-mini", "GPT-4o mini");
-const O1_PREVIEW:  (&str, &str) = ("o1-preview", "o1-preview");
-const O1_MINI:  (&str, &str) = ("o1-mini", "o1-mini");
+/// The model selected to start with.  Until the list of models
+/// arrives from the back end it is the only one offered
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
 
 /// Hold the code for creating and manipulating the chat_div
 #[derive(Debug, Deserialize)]
@@ -118,7 +119,13 @@ impl LlmWebPage for ChatDiv {
         prompt_div.append_child(&multi_line_button)?;
 
         let side_panel_div = make_side_panel(document, chats.clone())?;
-        set_selected_model(GPT_4_0_MINI.0, &side_panel_div)?;
+        set_selected_model(DEFAULT_MODEL, &side_panel_div)?;
+
+        // Ask the back end what models there are.  The selection tool
+        // is remade when they arrive
+        if let Err(err) = request_models() {
+            print_to_console(format!("Failed to request models: {err:?}"));
+        }
 
         // Put the page together
         chat_div.append_child(&conversation_div)?;
@@ -1224,7 +1231,11 @@ fn send_prompt(prompt: String, chats: Rc<RefCell<Chats>>) -> Result<(), JsValue>
     let chat_prompt = ChatPrompt {
         model,
         messages,
-        temperature: 1.0, // TODO: Get this from user interface
+        // TODO: Get the options from user interface
+        options: ChatOptions {
+            temperature: Some(1.0),
+            ..ChatOptions::default()
+        },
         token,
     };
 
@@ -1376,12 +1387,10 @@ fn make_model_selection_tool(document: &Document) -> Result<HtmlDivElement, JsVa
         .dyn_into::<HtmlInputElement>()
         .map_err(|err| format!("Error casting to HtmlImageElement: {:?}", err))?;
     select_element.set_id("model_chat");
-    let models = [GPT_3.0, GPT_4.0, GPT_4_0_MINI.0, O1_PREVIEW.0, O1_MINI.0,];
-    let names = [GPT_3.1, GPT_4.1, GPT_4_0_MINI.1, O1_PREVIEW.1, O1_MINI.1,];
+    let models = get_models();
     let options = models
         .iter()
-        .zip(names.iter())
-        .map(|(m, n)| {
+        .map(|m| {
             let element = document
                 .create_element("input")
                 .expect("Create model <input>");
@@ -1390,13 +1399,15 @@ fn make_model_selection_tool(document: &Document) -> Result<HtmlDivElement, JsVa
             input.set_type("radio"); //.expect("Set model <input> type");
             input.set_class_name("model_input");
             input.set_name("model");
-            input.set_value(m);
+            input.set_value(m.id.as_str());
             let label: HtmlLabelElement = document
                 .create_element("label")
                 .expect("Create model <label>")
                 .dyn_into()
                 .expect("Cast to HtmlLabelElement failed");
-            label.set_inner_html(n);
+            label.set_inner_html(m.id.as_str());
+            // Tool tip
+            label.set_title(m.capabilities.join(", ").as_str());
             let s: HtmlSpanElement = document
                 .create_element("span")
                 .expect("Create model <span>")
@@ -1413,6 +1424,85 @@ fn make_model_selection_tool(document: &Document) -> Result<HtmlDivElement, JsVa
     Ok(result)
 }
 
+/// The models the back end sent, stored in the DOM.  Before they
+/// arrive only the default model
+fn get_models() -> Vec<ModelDescription> {
+    let models: Option<Vec<ModelDescription>> = get_doc()
+        .body()
+        .and_then(|body| body.get_attribute("data.models"))
+        .and_then(|models| serde_json::from_str(models.as_str()).ok());
+    match models {
+        Some(models) if !models.is_empty() => models,
+        _ => vec![ModelDescription {
+            id: DEFAULT_MODEL.to_string(),
+            capabilities: vec![],
+        }],
+    }
+}
+
+/// Ask the back end for the models the user can chat with
+fn request_models() -> Result<(), JsValue> {
+    let token = get_doc()
+        .body()
+        .ok_or("request_models: Cannot get <body>")?
+        .get_attribute("data.token")
+        .ok_or("request_models: Cannot get token")?;
+    make_request(
+        Message::from(ModelsRequest { token }),
+        models_cb,
+        abort_request_cb,
+    )?;
+    Ok(())
+}
+
+/// Callback for `request_models`.  Store the models and remake the
+/// selection tool, keeping the selected model if it is still there
+fn models_cb(message: Message) {
+    let closure = move || -> Result<(), JsValue> {
+        match message.comm_type {
+            CommType::ModelsResponse => {
+                let models_response: ModelsResponse = serde_json::from_str(message.object.as_str())
+                    .map_err(|e| JsValue::from_str(&e.to_string()))?;
+                let document = get_doc();
+                let model = get_model()?;
+                document.body().ok_or("models_cb: Cannot get <body>")?.set_attribute(
+                    "data.models",
+                    serde_json::to_string(&models_response.models)
+                        .map_err(|e| JsValue::from_str(&e.to_string()))?
+                        .as_str(),
+                )?;
+                let models = get_models();
+                let model = if models.iter().any(|m| m.id == model) {
+                    model
+                } else if models.iter().any(|m| m.id == DEFAULT_MODEL) {
+                    DEFAULT_MODEL.to_string()
+                } else {
+                    models[0].id.clone()
+                };
+                let old_tool = document
+                    .get_element_by_id("model_selection_tool")
+                    .ok_or("models_cb: Cannot get model selection tool")?;
+                let new_tool = make_model_selection_tool(&document)?;
+                old_tool
+                    .parent_node()
+                    .ok_or("models_cb: Model selection tool has no parent")?
+                    .replace_child(&new_tool, &old_tool)?;
+                set_model(model.as_str())?;
+            }
+            CommType::InvalidRequest => {
+                let inr: InvalidRequest =
+                    serde_json::from_str(message.object.as_str()).expect("Not an InvalidRequest");
+                set_status(&inr.reason);
+            }
+            _ => (),
+        };
+        Ok(())
+    };
+    if let Err(err) = closure() {
+        print_to_console(format!("models_cb failed.  err: {err:?}"));
+    }
+}
+
 fn set_selected_model(model: &str, model_selection_tool: &Element) -> Result<(), JsValue> {
     let elements: HtmlCollection = model_selection_tool.get_elements_by_class_name("model_input");
     for i in 0..elements.length() {
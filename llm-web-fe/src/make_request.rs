@@ -23,6 +23,7 @@ pub fn make_request(
     let api = match message.comm_type {
         CommType::LoginRequest => "login",
         CommType::ChatPrompt => "chat",
+        CommType::ModelsRequest => "models",
         _ => {
             print_to_console(format!("make_request Unimplemented: {message}"));
             let err = format!(